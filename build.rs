@@ -0,0 +1,25 @@
+use std::env;
+use std::process::Command;
+
+// The `nightly` feature relies on the unstable diagnostics API, so it only
+// takes effect on compilers which allow unstable features. Elsewhere it is
+// ignored, so that building with `--all-features` works on stable
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTC_BOOTSTRAP");
+    println!("cargo:rustc-check-cfg=cfg(rudeboy_diagnostics)");
+    if env::var_os("CARGO_FEATURE_NIGHTLY").is_none() {
+        return;
+    }
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    let unstable = version.contains("-nightly") || version.contains("-dev") || env::var_os("RUSTC_BOOTSTRAP").is_some();
+    if unstable {
+        println!("cargo:rustc-cfg=rudeboy_diagnostics");
+    }
+}
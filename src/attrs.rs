@@ -0,0 +1,497 @@
+use crate::paths::CratePaths;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The name of the helper attribute used to configure individual fields and
+/// methods, e.g. `#[rudeboy(skip)]`
+pub(crate) const RUDEBOY_ATTR: &str = "rudeboy";
+
+/// The name of the helper attribute listing the operator traits implemented by a
+/// type, used to infer its metamethods, e.g. `#[rudeboy_ops(Add, Neg)]`
+pub(crate) const RUDEBOY_OPS_ATTR: &str = "rudeboy_ops";
+
+/// The names of the attribute macros provided by this crate
+const MACRO_NAMES: &[&str] = &["constants", "export", "lua_module", "metamethods", "methods", "user_data"];
+
+/// Returns the nested metas of every `#[rudeboy(...)]` attribute in the list
+pub(crate) fn rudeboy_metas(attrs: &[syn::Attribute]) -> Result<Vec<syn::NestedMeta>, TokenStream2> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident(RUDEBOY_ATTR)) {
+        match attr.parse_meta() {
+            Ok(syn::Meta::List(l)) => metas.extend(l.nested),
+            Ok(_) => {
+                return Err(quote_spanned! {
+                    attr.span() => compile_error!("Expected an attribute of the form #[rudeboy(...)]");
+                })
+            }
+            Err(e) => return Err(e.to_compile_error()),
+        }
+    }
+    Ok(metas)
+}
+
+/// Options given in `#[rudeboy(...)]` attributes on a type deriving one of the
+/// conversion traits
+#[derive(Default)]
+pub(crate) struct ContainerOptions {
+    /// The paths to the rudeboy and rlua crates
+    pub paths: CratePaths,
+    /// Fieldless enums are also converted from their discriminants
+    pub integers: bool,
+}
+
+impl ContainerOptions {
+    const INTEGERS_IDENT: &'static str = "integers";
+
+    pub(crate) fn try_parse(attrs: &[syn::Attribute]) -> Result<ContainerOptions, TokenStream2> {
+        let mut options = ContainerOptions::default();
+        for meta in rudeboy_metas(attrs)? {
+            if options.paths.try_parse_option(&meta)? {
+                continue;
+            }
+            use syn::{Meta, NestedMeta};
+            match &meta {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::INTEGERS_IDENT) => options.integers = true,
+                _ => {
+                    return Err(quote_spanned! {
+                        meta.span() => compile_error!("Expected a valid rudeboy type option");
+                    })
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options given in `#[rudeboy(...)]` attributes on a struct field
+#[derive(Default)]
+pub(crate) struct FieldOptions {
+    /// The field is not exposed to Lua at all
+    pub skip: bool,
+    /// The field may be read, but not written, from Lua
+    pub readonly: bool,
+    /// Index lookups which match no field are forwarded to this field
+    pub delegate: bool,
+    /// The fields of this field are read and assigned through Index and
+    /// NewIndex as though they were fields of the struct containing it
+    pub flatten: bool,
+    /// The function which must accept a value before it is assigned to the
+    /// field from Lua
+    pub validate: Option<syn::Path>,
+}
+
+impl FieldOptions {
+    const SKIP_IDENT: &'static str = "skip";
+    const READONLY_IDENT: &'static str = "readonly";
+    const DELEGATE_IDENT: &'static str = "delegate";
+    const FLATTEN_IDENT: &'static str = "flatten";
+    const VALIDATE_IDENT: &'static str = "validate";
+
+    pub(crate) fn try_parse(field: &syn::Field) -> Result<FieldOptions, TokenStream2> {
+        let mut options = FieldOptions::default();
+        for meta in rudeboy_metas(&field.attrs)? {
+            use syn::{Meta, NestedMeta};
+            match &meta {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::SKIP_IDENT) => options.skip = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::READONLY_IDENT) => {
+                    options.readonly = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::DELEGATE_IDENT) => {
+                    options.delegate = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::FLATTEN_IDENT) => {
+                    options.flatten = true
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::VALIDATE_IDENT) => {
+                    options.validate = Some(function_path(&nv.lit)?)
+                }
+                _ => {
+                    return Err(quote_spanned! {
+                        meta.span() => compile_error!("Expected a valid rudeboy field option");
+                    })
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Generates a statement passing a reference to the value in `value` to the
+    /// field's validator, if any, which returns from the enclosing function with
+    /// a Lua error naming the field if the value is rejected
+    pub(crate) fn validation(&self, value: &TokenStream2, key: &str, rlua: &TokenStream2) -> TokenStream2 {
+        match &self.validate {
+            Some(validate) => quote_spanned! {
+                validate.span() => if let Err(error) = #validate(&#value) {
+                    return Err(#rlua::Error::external(format!("invalid value for field {}: {}", #key, error)));
+                }
+            },
+            None => quote!(),
+        }
+    }
+}
+
+/// The names of the variants of `rlua::MetaMethod`
+pub(crate) const RLUA_META_METHODS: &[&str] = &[
+    "Add", "Sub", "Mul", "Div", "Mod", "Pow", "Unm", "IDiv", "BAnd", "BOr", "BXor", "BNot", "Shl",
+    "Shr", "Concat", "Len", "Eq", "Lt", "Le", "Index", "NewIndex", "Call", "ToString", "Pairs",
+];
+
+/// Options given in `#[rudeboy(...)]` attributes on a method
+#[derive(Default)]
+pub(crate) struct MethodOptions {
+    /// The method is registered with `add_method_mut` even if it takes `&self`
+    pub force_mut: bool,
+    /// The `rlua::MetaMethod` the method is registered as, instead of being
+    /// registered as a named method
+    pub metamethod: Option<syn::Ident>,
+    /// The method is not exported
+    pub skip: bool,
+    /// The method is exported even though it is marked `#[doc(hidden)]`
+    pub export: bool,
+    /// The function converting errors returned by the method into an
+    /// `rlua::Error`, overriding the default for the impl block
+    pub map_err: Option<syn::Path>,
+    /// The error type returned by the method implements `std::error::Error`,
+    /// and is raised with its message and those of its sources
+    pub std_error: bool,
+    /// The value returned by the method is a userdata of another type, which is
+    /// created with `create_userdata` rather than converted
+    pub userdata: bool,
+    /// The method is exported even though it is an `unsafe fn`, calling it in
+    /// an `unsafe` block
+    pub allow_unsafe: bool,
+    /// The method is read as a property through the Index metamethod rather
+    /// than registered as a method
+    pub getter: bool,
+    /// The method is registered as a method even if the auto_props option of
+    /// the impl block would make it a getter
+    pub method: bool,
+    /// The property assigned through the NewIndex metamethod by calling the
+    /// method, which is then not registered as a method
+    pub setter: Option<String>,
+    /// Further Lua names the method is registered under, e.g. to keep the old
+    /// names of a renamed method working
+    pub aliases: Vec<syn::LitStr>,
+    /// The message of the warning given the first time the method is called
+    /// from Lua, naming e.g. its replacement
+    pub deprecated: Option<String>,
+    /// The results of the method are cached in Lua by its arguments, and
+    /// returned again when it is called with the same ones
+    pub memoize: bool,
+    /// The capability which the host must grant for the method to be
+    /// registered
+    pub capability: Option<String>,
+}
+
+impl MethodOptions {
+    const MUT_IDENT: &'static str = "mut";
+    const METAMETHOD_IDENT: &'static str = "metamethod";
+    const SKIP_IDENT: &'static str = "skip";
+    const EXPORT_IDENT: &'static str = "export";
+    pub(crate) const MAP_ERR_IDENT: &'static str = "map_err";
+    pub(crate) const STD_ERROR_IDENT: &'static str = "std_error";
+    const USERDATA_IDENT: &'static str = "userdata";
+    const ALLOW_UNSAFE_IDENT: &'static str = "allow_unsafe";
+    const GETTER_IDENT: &'static str = "getter";
+    const METHOD_IDENT: &'static str = "method";
+    const SETTER_IDENT: &'static str = "setter";
+    const ALIAS_IDENT: &'static str = "alias";
+    const DEPRECATED_IDENT: &'static str = "deprecated";
+    const MEMOIZE_IDENT: &'static str = "memoize";
+    const CAPABILITY_IDENT: &'static str = "capability";
+
+    pub(crate) fn try_parse(method: &syn::ImplItemMethod) -> Result<MethodOptions, TokenStream2> {
+        let mut options = MethodOptions::default();
+        for meta in rudeboy_metas(&method.attrs)? {
+            use syn::{Meta, NestedMeta};
+            match &meta {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::MUT_IDENT) => options.force_mut = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::SKIP_IDENT) => options.skip = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::EXPORT_IDENT) => options.export = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::USERDATA_IDENT) => options.userdata = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::ALLOW_UNSAFE_IDENT) => {
+                    options.allow_unsafe = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::GETTER_IDENT) => options.getter = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::METHOD_IDENT) => options.method = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::MEMOIZE_IDENT) => options.memoize = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::STD_ERROR_IDENT) => options.std_error = true,
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::MAP_ERR_IDENT) => {
+                    options.map_err = Some(function_path(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::SETTER_IDENT) => match &nv.lit {
+                    syn::Lit::Str(s) => options.setter = Some(s.value()),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the property name");
+                        })
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::DEPRECATED_IDENT) => match &nv.lit {
+                    syn::Lit::Str(s) => options.deprecated = Some(s.value()),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the deprecation message");
+                        })
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::CAPABILITY_IDENT) => match &nv.lit {
+                    syn::Lit::Str(s) => options.capability = Some(s.value()),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the capability name");
+                        })
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::ALIAS_IDENT) => match &nv.lit {
+                    syn::Lit::Str(s) => options.aliases.push(s.clone()),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the alias");
+                        })
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::METAMETHOD_IDENT) => {
+                    match &nv.lit {
+                        syn::Lit::Str(s) if RLUA_META_METHODS.contains(&s.value().as_str()) => {
+                            options.metamethod = Some(syn::Ident::new(&s.value(), s.span()))
+                        }
+                        lit => {
+                            return Err(quote_spanned! {
+                                lit.span() => compile_error!("Expected a string containing the name of an rlua::MetaMethod");
+                            })
+                        }
+                    }
+                }
+                _ => {
+                    return Err(quote_spanned! {
+                        meta.span() => compile_error!("Expected a valid rudeboy method option");
+                    })
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether the method should be exported, which it is unless it is skipped,
+    /// or marked `#[doc(hidden)]` as an internal method without being
+    /// explicitly exported
+    pub(crate) fn is_exported(&self, method: &syn::ImplItemMethod) -> bool {
+        !self.skip && (self.export || !is_doc_hidden(&method.attrs))
+    }
+}
+
+/// Parses a string literal containing the path of a function, e.g.
+/// `"errors::to_lua"`
+pub(crate) fn function_path(lit: &syn::Lit) -> Result<syn::Path, TokenStream2> {
+    match lit {
+        syn::Lit::Str(s) => s.parse().map_err(|e| e.to_compile_error()),
+        lit => Err(quote_spanned! {
+            lit.span() => compile_error!("Expected a string containing the path of a function");
+        }),
+    }
+}
+
+/// Whether the given attributes include `#[doc(hidden)]`
+fn is_doc_hidden(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().filter(|a| a.path.is_ident("doc")).any(|a| match a.parse_meta() {
+        Ok(syn::Meta::List(l)) => l
+            .nested
+            .iter()
+            .any(|n| matches!(n, syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("hidden"))),
+        _ => false,
+    })
+}
+
+/// Options given in `#[rudeboy(...)]` attributes on a method parameter, which
+/// validate the argument before the method is called
+#[derive(Clone, Default)]
+pub(crate) struct ParamOptions {
+    /// The smallest value the argument may have, and how it is written
+    pub min: Option<(syn::Expr, String)>,
+    /// The largest value the argument may have, and how it is written
+    pub max: Option<(syn::Expr, String)>,
+    /// The argument may not be empty, according to its `is_empty` method
+    pub non_empty: bool,
+}
+
+impl ParamOptions {
+    const RANGE_IDENT: &'static str = "range";
+    const MIN_IDENT: &'static str = "min";
+    const MAX_IDENT: &'static str = "max";
+    const NON_EMPTY_IDENT: &'static str = "non_empty";
+
+    pub(crate) fn try_parse(param: &syn::PatType) -> Result<ParamOptions, TokenStream2> {
+        let mut options = ParamOptions::default();
+        for meta in rudeboy_metas(&param.attrs)? {
+            use syn::{Meta, NestedMeta};
+            match &meta {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::NON_EMPTY_IDENT) => options.non_empty = true,
+                NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(Self::RANGE_IDENT) => {
+                    for nested in &l.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::MIN_IDENT) => {
+                                options.min = Some(Self::bound(&nv.lit)?)
+                            }
+                            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::MAX_IDENT) => {
+                                options.max = Some(Self::bound(&nv.lit)?)
+                            }
+                            _ => {
+                                return Err(quote_spanned! {
+                                    nested.span() => compile_error!("Expected min = ... or max = ...");
+                                })
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    return Err(quote_spanned! {
+                        meta.span() => compile_error!("Expected a valid rudeboy parameter option");
+                    })
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Parses a bound of a range, which is a number, or a string containing an
+    /// expression for bounds which cannot be written as a literal, e.g. "-1"
+    fn bound(lit: &syn::Lit) -> Result<(syn::Expr, String), TokenStream2> {
+        match lit {
+            syn::Lit::Int(i) => Ok((syn::parse_quote!(#lit), i.base10_digits().to_string())),
+            syn::Lit::Float(f) => Ok((syn::parse_quote!(#lit), f.base10_digits().to_string())),
+            syn::Lit::Str(s) => Ok((s.parse().map_err(|e| e.to_compile_error())?, s.value())),
+            _ => Err(quote_spanned! {
+                lit.span() => compile_error!("Expected a number");
+            }),
+        }
+    }
+}
+
+/// Returns the lines of the doc comments in the given list, with the space
+/// which usually follows `///` removed
+pub(crate) fn doc_lines(attrs: &[syn::Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(s), ..
+            })) => Some(s.value()),
+            _ => None,
+        })
+        .flat_map(|doc| {
+            doc.split('\n')
+                .map(|line| line.strip_prefix(' ').unwrap_or(line).to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Returns the conditional compilation attributes from the given list, so that
+/// code generated for an item is only compiled alongside the item itself.
+/// `#[cfg(...)]` attributes are returned as-is, while `#[cfg_attr(...)]`
+/// attributes are only kept to the extent that they conditionally apply a
+/// `cfg`
+pub(crate) fn cfg_attrs(attrs: &[syn::Attribute]) -> Vec<TokenStream2> {
+    let mut cfgs = Vec::new();
+    for attr in attrs {
+        if attr.path.is_ident("cfg") {
+            cfgs.push(quote!(#attr));
+        } else if attr.path.is_ident("cfg_attr") {
+            if let Ok(syn::Meta::List(l)) = attr.parse_meta() {
+                let mut nested = l.nested.iter();
+                let predicate = match nested.next() {
+                    Some(p) => p,
+                    None => continue,
+                };
+                let inner_cfgs: Vec<_> = nested
+                    .filter(|n| match n {
+                        syn::NestedMeta::Meta(m) => m.path().is_ident("cfg"),
+                        _ => false,
+                    })
+                    .collect();
+                if !inner_cfgs.is_empty() {
+                    cfgs.push(quote!(#[cfg_attr(#predicate, #( #inner_cfgs ),*)]));
+                }
+            }
+        }
+    }
+    cfgs
+}
+
+/// Whether the given path names one of the given macros of this crate, either
+/// by a bare name, e.g. `user_data`, or through the rudeboy crates, e.g.
+/// `rudeboy::user_data`. Macros of the same name from other crates, e.g.
+/// `other::user_data`, are not this crate's
+pub(crate) fn is_rudeboy_path(path: &syn::Path, names: &[&str]) -> bool {
+    let named = path.segments.last().is_some_and(|s| names.iter().any(|name| s.ident == name));
+    let crate_named = match path.segments.len() {
+        1 => path.leading_colon.is_none(),
+        2 => path.segments[0].ident == "rudeboy" || path.segments[0].ident == "rudeboy_derive",
+        _ => false,
+    };
+    named && crate_named
+}
+
+/// Whether the given attribute invokes one of this crate's attribute macros
+fn is_rudeboy_macro(attr: &syn::Attribute) -> bool {
+    is_rudeboy_path(&attr.path, MACRO_NAMES)
+}
+
+fn strip_attrs(attrs: &mut Vec<syn::Attribute>) {
+    attrs.retain(|a| !a.path.is_ident(RUDEBOY_ATTR));
+}
+
+fn strip_fields(fields: &mut syn::Fields) {
+    for field in fields.iter_mut() {
+        strip_attrs(&mut field.attrs);
+    }
+}
+
+/// Removes the `#[rudeboy(...)]` helper attributes from the item so that it can
+/// be emitted. The attributes are left in place if another of this crate's
+/// macros has yet to be applied to the item, since it may need to read them
+pub(crate) fn strip_helper_attrs(item: &syn::Item) -> syn::Item {
+    let mut item = item.clone();
+    match &mut item {
+        syn::Item::Struct(s) if !s.attrs.iter().any(is_rudeboy_macro) => {
+            s.attrs.retain(|a| !a.path.is_ident(RUDEBOY_OPS_ATTR));
+            strip_fields(&mut s.fields)
+        }
+        syn::Item::Enum(e) if !e.attrs.iter().any(is_rudeboy_macro) => {
+            e.attrs.retain(|a| !a.path.is_ident(RUDEBOY_OPS_ATTR));
+            for variant in e.variants.iter_mut() {
+                strip_attrs(&mut variant.attrs);
+                strip_fields(&mut variant.fields);
+            }
+        }
+        syn::Item::Trait(t) if !t.attrs.iter().any(is_rudeboy_macro) => {
+            for trait_item in t.items.iter_mut() {
+                if let syn::TraitItem::Method(m) = trait_item {
+                    strip_attrs(&mut m.attrs);
+                    for input in m.sig.inputs.iter_mut() {
+                        if let syn::FnArg::Typed(t) = input {
+                            strip_attrs(&mut t.attrs);
+                        }
+                    }
+                }
+            }
+        }
+        syn::Item::Impl(i) if !i.attrs.iter().any(is_rudeboy_macro) => {
+            for impl_item in i.items.iter_mut() {
+                if let syn::ImplItem::Method(m) = impl_item {
+                    strip_attrs(&mut m.attrs);
+                    for input in m.sig.inputs.iter_mut() {
+                        if let syn::FnArg::Typed(t) = input {
+                            strip_attrs(&mut t.attrs);
+                        }
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+    item
+}
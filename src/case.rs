@@ -0,0 +1,87 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote_spanned;
+
+/// A rule for converting a Rust identifier into the name seen from Lua.
+/// Mirrors the `rename_all` rules used by serde
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    #[default]
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    const RULES: &'static [(&'static str, RenameRule)] = &[
+        ("lowercase", RenameRule::Lower),
+        ("UPPERCASE", RenameRule::Upper),
+        ("PascalCase", RenameRule::Pascal),
+        ("camelCase", RenameRule::Camel),
+        ("snake_case", RenameRule::Snake),
+        ("SCREAMING_SNAKE_CASE", RenameRule::ScreamingSnake),
+        ("kebab-case", RenameRule::Kebab),
+        ("SCREAMING-KEBAB-CASE", RenameRule::ScreamingKebab),
+    ];
+
+    pub(crate) fn try_parse(lit: &syn::Lit) -> Result<RenameRule, TokenStream2> {
+        if let syn::Lit::Str(s) = lit {
+            let value = s.value();
+            for (name, rule) in Self::RULES {
+                if value == *name {
+                    return Ok(*rule);
+                }
+            }
+        }
+        Err(quote_spanned! {
+            lit.span() => compile_error!("Expected one of: \"lowercase\", \"UPPERCASE\", \"PascalCase\", \"camelCase\", \"snake_case\", \"SCREAMING_SNAKE_CASE\", \"kebab-case\", \"SCREAMING-KEBAB-CASE\"");
+        })
+    }
+
+    /// Joins the given words according to this rule
+    pub(crate) fn apply_to_words(self, words: &[String]) -> String {
+        let capitalize = |w: &str| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        };
+        match self {
+            RenameRule::Lower => words.concat().to_lowercase(),
+            RenameRule::Upper => words.concat().to_uppercase(),
+            RenameRule::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+            RenameRule::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.to_lowercase() } else { capitalize(w) })
+                .collect(),
+            RenameRule::Snake => words.join("_").to_lowercase(),
+            RenameRule::ScreamingSnake => words.join("_").to_uppercase(),
+            RenameRule::Kebab => words.join("-").to_lowercase(),
+            RenameRule::ScreamingKebab => words.join("-").to_uppercase(),
+        }
+    }
+}
+
+/// Splits a snake_case or PascalCase identifier into its component words
+pub(crate) fn split_words(ident: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in ident.split('_').filter(|p| !p.is_empty()) {
+        let mut word = String::new();
+        let mut prev_lower = false;
+        for c in part.chars() {
+            if c.is_uppercase() && prev_lower {
+                words.push(std::mem::take(&mut word));
+            }
+            prev_lower = c.is_lowercase() || c.is_numeric();
+            word.push(c);
+        }
+        words.push(word);
+    }
+    words
+}
@@ -0,0 +1,19 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+
+/// Generates an expression evaluating to an `rlua::Result` of the function
+/// compiled from the given Lua chunk. The chunk is only compiled the first time
+/// in each Lua state, and the function kept in the registry under the given
+/// name for later calls. The expression uses the context `ctx`
+pub(crate) fn cached_function(name: &str, chunk: &str, rlua: &TokenStream2) -> TokenStream2 {
+    quote! {
+        match ctx.named_registry_value::<_, Option<#rlua::Function>>(#name) {
+            Ok(Some(function)) => Ok(function),
+            Ok(None) => ctx.load(#chunk).into_function().and_then(|function| {
+                ctx.set_named_registry_value(#name, ::std::clone::Clone::clone(&function))?;
+                Ok(function)
+            }),
+            Err(error) => Err(error),
+        }
+    }
+}
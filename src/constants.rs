@@ -0,0 +1,112 @@
+use crate::attrs::cfg_attrs;
+use crate::paths::CratePaths;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// A constant to be exported, along with its conditional compilation attributes
+pub(crate) type Constant = (syn::Ident, Vec<TokenStream2>);
+
+/// Generates the body of a function taking an `rlua::Context` named `ctx` and
+/// returning a table which maps the name of each given constant to its value.
+/// The given statements are run before returning, and may add further entries
+/// to `table`
+pub(crate) fn constants_table_body(consts: &[Constant], prefix: TokenStream2, extra: TokenStream2) -> TokenStream2 {
+    let sets = consts.iter().map(|(name, cfgs)| {
+        quote! {
+            #( #cfgs )*
+            table.set(stringify!(#name), #prefix #name)?;
+        }
+    });
+    quote! {
+        let table = ctx.create_table()?;
+        #( #sets )*
+        #extra
+        Ok(table)
+    }
+}
+
+fn constants_table_fn(consts: &[Constant], prefix: TokenStream2, rlua: &TokenStream2) -> TokenStream2 {
+    let body = constants_table_body(consts, prefix, quote!());
+    quote! {
+        /// Creates a Lua table holding the constants exported by rudeboy
+        pub fn constants_table<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Table<'lua>> {
+            #body
+        }
+    }
+}
+
+fn implitem_constants_attr_macro(ast: &syn::ItemImpl, paths: &CratePaths) -> TokenStream2 {
+    let consts: Vec<_> = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::ImplItem::Const(c) => Some((c.ident.clone(), cfg_attrs(&c.attrs))),
+            _ => None,
+        })
+        .collect();
+
+    let self_ty = &ast.self_ty;
+    let (impl_generics, _, where_clause) = ast.generics.split_for_impl();
+    let table_fn = constants_table_fn(&consts, quote!(Self::), &paths.rlua);
+    let table_impl = paths.allow_lints(quote! {
+        impl #impl_generics #self_ty #where_clause {
+            #table_fn
+        }
+    });
+    quote! {
+        #ast
+
+        #table_impl
+    }
+}
+
+fn mod_constants_attr_macro(ast: &syn::ItemMod, paths: &CratePaths) -> TokenStream2 {
+    let mut ast = ast.clone();
+    let items = match &mut ast.content {
+        Some((_, items)) => items,
+        None => {
+            return quote_spanned! {
+                ast.span() => compile_error!("constants macro can only be applied to an inline module");
+            }
+        }
+    };
+
+    let consts: Vec<_> = items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Const(c) => Some((c.ident.clone(), cfg_attrs(&c.attrs))),
+            _ => None,
+        })
+        .collect();
+
+    let table_fn = constants_table_fn(&consts, quote!(self::), &paths.rlua);
+    items.push(syn::parse_quote!(#table_fn));
+    quote!(#ast)
+}
+
+pub(crate) fn impl_constants_attr_macro(
+    item: syn::Item,
+    attrs: Vec<&syn::NestedMeta>,
+) -> TokenStream2 {
+    let mut paths = CratePaths::default();
+    for attr in attrs {
+        match paths.try_parse_option(attr) {
+            Ok(true) => (),
+            Ok(false) => {
+                return quote_spanned! {
+                    attr.span() => compile_error!("Expected a valid constants parameter");
+                }
+            }
+            Err(e) => return e,
+        }
+    }
+
+    match &item {
+        syn::Item::Impl(i) => implitem_constants_attr_macro(i, &paths),
+        syn::Item::Mod(m) => mod_constants_attr_macro(m, &paths),
+        _ => quote_spanned! {
+            item.span() => compile_error!("constants macro can only be applied to an impl block or a module");
+        },
+    }
+}
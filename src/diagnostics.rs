@@ -0,0 +1,26 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+#[cfg(not(rudeboy_diagnostics))]
+use quote::quote_spanned;
+
+/// Generates code which causes the compiler to emit a warning with the given
+/// message at the given span. Stable proc macros have no way to emit warnings
+/// directly, so this relies on the use of a deprecated constant
+#[cfg(not(rudeboy_diagnostics))]
+pub(crate) fn warning(span: Span, message: &str) -> TokenStream2 {
+    quote_spanned! {
+        span => const _: () = {
+            #[deprecated(note = #message)]
+            #[allow(non_upper_case_globals)]
+            const rudeboy_warning: () = ();
+            rudeboy_warning
+        };
+    }
+}
+
+/// Emits a warning with the given message at the given span through the
+/// unstable diagnostics API, which needs no generated code
+#[cfg(rudeboy_diagnostics)]
+pub(crate) fn warning(span: Span, message: &str) -> TokenStream2 {
+    proc_macro::Diagnostic::spanned(span.unwrap(), proc_macro::Level::Warning, message).emit();
+    TokenStream2::new()
+}
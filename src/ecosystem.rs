@@ -0,0 +1,171 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+
+/// A common type from another crate which implements neither of rlua's
+/// conversion traits, and is converted by generated code instead when the
+/// feature of the same name is enabled. Generated code refers to the crates by
+/// name, so crates using these conversions must depend on them. Byte vectors
+/// are likewise converted when the `bytes_as_string` feature is enabled, in
+/// place of rlua's conversion to and from tables, and 128-bit integers always
+/// are, in place of rlua's conversion of those beyond Lua's integers to floats
+enum EcosystemType {
+    /// `uuid::Uuid`, converted to and from its hyphenated string
+    Uuid,
+    /// `chrono::DateTime<Tz>`, converted to an RFC 3339 string, and from one or
+    /// from a number of seconds since the Unix epoch
+    DateTime,
+    /// `chrono::Duration`, converted to and from a number of seconds
+    Duration,
+    /// `Vec<u8>`, converted to and from a Lua string holding the same bytes
+    Bytes,
+    /// `i128` or `u128`, converted to a Lua integer when one can hold the
+    /// value and to its decimal string otherwise, and from either or from a
+    /// float holding a whole number
+    Integer128,
+}
+
+impl EcosystemType {
+    /// Returns the ecosystem type the given type is, if its feature is enabled.
+    /// Types of other crates are recognized by their name alone, as imported
+    /// with `use`, or by a path starting with their crate, e.g. `uuid::Uuid`,
+    /// so that a type of the same name elsewhere, e.g. `my::Uuid`, is not. Since
+    /// `Duration` is also the name of `std::time::Duration`, chrono's is only
+    /// recognized by a path starting with the crate, or by its other name
+    /// `TimeDelta`
+    fn of(ty: &syn::Type) -> Option<EcosystemType> {
+        let path = match ty {
+            syn::Type::Path(p) if p.qself.is_none() => &p.path,
+            _ => return None,
+        };
+        let segment = path.segments.last()?;
+        let in_crate = |krate: &str| path.segments.len() > 1 && path.segments[0].ident == krate;
+        let imported = path.leading_colon.is_none() && path.segments.len() == 1;
+        let names = |krate: &str, item: &str| segment.ident == item && (imported || in_crate(krate));
+        if path.is_ident("i128") || path.is_ident("u128") {
+            Some(EcosystemType::Integer128)
+        } else if cfg!(feature = "uuid") && names("uuid", "Uuid") {
+            Some(EcosystemType::Uuid)
+        } else if cfg!(feature = "chrono") && names("chrono", "DateTime") {
+            Some(EcosystemType::DateTime)
+        } else if cfg!(feature = "chrono")
+            && (names("chrono", "TimeDelta") || (segment.ident == "Duration" && in_crate("chrono")))
+        {
+            Some(EcosystemType::Duration)
+        } else if cfg!(feature = "bytes_as_string") && segment.ident == "Vec" && is_u8_argument(&segment.arguments) {
+            Some(EcosystemType::Bytes)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the given arguments are the single type argument `u8`
+fn is_u8_argument(arguments: &syn::PathArguments) -> bool {
+    match arguments {
+        syn::PathArguments::AngleBracketed(args) => {
+            args.args.len() == 1
+                && matches!(args.args.first(), Some(syn::GenericArgument::Type(syn::Type::Path(p))) if p.path.is_ident("u8"))
+        }
+        _ => false,
+    }
+}
+
+/// Returns the LDoc type of the given type if it is an ecosystem type
+pub(crate) fn lua_type(ty: &syn::Type) -> Option<&'static str> {
+    Some(match EcosystemType::of(ty)? {
+        EcosystemType::Uuid | EcosystemType::DateTime | EcosystemType::Bytes => "string",
+        EcosystemType::Duration => "number",
+        EcosystemType::Integer128 => "integer|string",
+    })
+}
+
+/// If the given type is an ecosystem type, returns an expression converting the
+/// owned value in `value` into Lua, evaluating to an `rlua::Result` of the
+/// `rlua::Value`. The expression uses the context `ctx`
+pub(crate) fn to_lua_conversion(ty: &syn::Type, value: &TokenStream2, rlua: &TokenStream2) -> Option<TokenStream2> {
+    Some(match EcosystemType::of(ty)? {
+        EcosystemType::Uuid => quote!(ctx.create_string(&::std::string::ToString::to_string(&#value)).map(#rlua::Value::String)),
+        EcosystemType::DateTime => quote!(ctx.create_string(&#value.to_rfc3339()).map(#rlua::Value::String)),
+        EcosystemType::Duration => quote! {{
+            let duration = #value;
+            Ok(#rlua::Value::Number(duration.num_seconds() as f64 + f64::from(duration.subsec_nanos()) / 1e9))
+        }},
+        EcosystemType::Bytes => quote!(ctx.create_string(&#value).map(#rlua::Value::String)),
+        EcosystemType::Integer128 => quote! {{
+            let value: #ty = #value;
+            match <i64 as ::std::convert::TryFrom<#ty>>::try_from(value) {
+                Ok(i) => Ok(#rlua::Value::Integer(i)),
+                Err(_) => ctx.create_string(&::std::string::ToString::to_string(&value)).map(#rlua::Value::String),
+            }
+        }},
+    })
+}
+
+/// If the given type is an ecosystem type, returns an expression converting the
+/// `rlua::Value` in `value` into it, evaluating to an `rlua::Result` of the
+/// type. The expression uses the context `ctx`
+pub(crate) fn from_lua_conversion(ty: &syn::Type, value: &TokenStream2, rlua: &TokenStream2) -> Option<TokenStream2> {
+    let from_string = |value: &TokenStream2, parse: TokenStream2, what: &str| {
+        let message = format!("invalid {}: {{}}", what);
+        quote! {
+            <#rlua::String as #rlua::FromLua>::from_lua(#value, ctx).and_then(|s| {
+                #parse(s.to_str()?).map_err(|error| #rlua::Error::external(format!(#message, error)))
+            })
+        }
+    };
+    Some(match EcosystemType::of(ty)? {
+        EcosystemType::Uuid => from_string(value, quote!(::uuid::Uuid::parse_str), "uuid"),
+        // Timestamps are taken as UTC, which every time zone converts from
+        EcosystemType::DateTime => {
+            let parsed = from_string(&quote!(value), quote!(<#ty as ::std::str::FromStr>::from_str), "date and time");
+            quote! {{
+                let timestamp = |secs: i64, nanos: u32| {
+                    ::chrono::TimeZone::timestamp_opt(&::chrono::Utc, secs, nanos)
+                        .single()
+                        .map(::std::convert::Into::into)
+                        .ok_or_else(|| #rlua::Error::external(format!("timestamp {} is out of range", secs)))
+                };
+                let value = #value;
+                let converted: #rlua::Result<#ty> = match value {
+                    #rlua::Value::Integer(secs) => timestamp(secs, 0),
+                    #rlua::Value::Number(secs) => {
+                        let whole = secs.floor();
+                        timestamp(whole as i64, ((secs - whole) * 1e9) as u32)
+                    }
+                    value => #parsed,
+                };
+                converted
+            }}
+        }
+        EcosystemType::Duration => quote! {
+            <f64 as #rlua::FromLua>::from_lua(#value, ctx).map(|secs| <#ty>::nanoseconds((secs * 1e9).round() as i64))
+        },
+        EcosystemType::Bytes => quote! {
+            <#rlua::String as #rlua::FromLua>::from_lua(#value, ctx).map(|s| s.as_bytes().to_vec())
+        },
+        // Values out of the type's range raise an error rather than wrapping
+        // or saturating, e.g. "value -1 out of range for u128"
+        EcosystemType::Integer128 => {
+            let name = quote!(#ty).to_string();
+            let parsed = from_string(&quote!(value), quote!(<#ty as ::std::str::FromStr>::from_str), &name);
+            quote! {{
+                let out_of_range = |value: &dyn ::std::fmt::Display| {
+                    #rlua::Error::external(format!("value {} out of range for {}", value, #name))
+                };
+                let value = #value;
+                let converted: #rlua::Result<#ty> = match value {
+                    #rlua::Value::Integer(i) => ::std::convert::TryFrom::try_from(i).map_err(|_| out_of_range(&i)),
+                    #rlua::Value::Number(n) if n.fract() != 0.0 => {
+                        Err(#rlua::Error::external(format!("value {} is not an integer for {}", n, #name)))
+                    }
+                    // The maximum rounds up to a power of two as a float, which
+                    // is itself out of range
+                    #rlua::Value::Number(n) if n >= #ty::MIN as f64 && n < #ty::MAX as f64 => Ok(n as #ty),
+                    #rlua::Value::Number(n) => Err(out_of_range(&n)),
+                    value => #parsed,
+                };
+                converted
+            }}
+        }
+    })
+}
@@ -0,0 +1,86 @@
+use crate::attrs::strip_helper_attrs;
+use crate::methods::{impl_methods_attr_macro, MethodsOptions};
+use crate::paths::CratePaths;
+use crate::user_data::{user_data_impl, UserDataAttr, UserDataParams};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use std::collections::HashSet;
+use syn::spanned::Spanned;
+
+const METHODS_IDENT: &str = "methods";
+const META_METHODS_IDENT: &str = "metamethods";
+
+/// Parses the export parameters into the equivalent user_data parameters
+fn attrs_to_export_attrs(attrs: Vec<&syn::NestedMeta>) -> Result<UserDataParams<'_>, TokenStream2> {
+    let mut ret = HashSet::new();
+    let mut metamethods = Vec::new();
+    let mut paths = CratePaths::default();
+    for attr in attrs {
+        if paths.try_parse_option(attr)? {
+            continue;
+        }
+        use syn::{Meta, NestedMeta};
+        ret.insert(match attr {
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(METHODS_IDENT) => UserDataAttr::Methods,
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(META_METHODS_IDENT) => {
+                UserDataAttr::MetaMethods
+            }
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(META_METHODS_IDENT) => {
+                metamethods.extend(l.nested.iter());
+                UserDataAttr::MetaMethods
+            }
+            _ => {
+                return Err(quote_spanned! {
+                    attr.span() => compile_error!("Expected one of: methods, metamethods, metamethods(...)");
+                })
+            }
+        });
+    }
+    Ok(UserDataParams {
+        attrs: ret,
+        metamethods,
+        extends: None,
+        includes: Vec::new(),
+        shared: None,
+        type_name: None,
+        exports: false,
+        introspect: false,
+        extensions: false,
+        paths,
+    })
+}
+
+pub(crate) fn impl_export_attr_macro(
+    item: syn::Item,
+    attrs: Vec<&syn::NestedMeta>,
+) -> TokenStream2 {
+    let params = match attrs_to_export_attrs(attrs) {
+        Ok(ea) => ea,
+        Err(e) => return e,
+    };
+    let has_methods = params.attrs.contains(&UserDataAttr::Methods);
+    let methods_options = MethodsOptions {
+        paths: params.paths.clone(),
+        ..MethodsOptions::default()
+    };
+
+    let user_data_impl = match user_data_impl(&item, params) {
+        Ok(udi) => udi,
+        Err(e) => return e,
+    };
+
+    // When placed on the impl block, the methods are generated here as well
+    let item = match &item {
+        syn::Item::Impl(_) if has_methods => impl_methods_attr_macro(item, &methods_options),
+        _ => {
+            let item = strip_helper_attrs(&item);
+            quote!(#item)
+        }
+    };
+
+    quote! {
+        #item
+
+        #user_data_impl
+    }
+}
@@ -0,0 +1,75 @@
+use crate::attrs::{is_rudeboy_path, RUDEBOY_ATTR};
+use crate::user_data::impl_user_data_attr_macro;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The option opting a type out of export_all, i.e. `#[rudeboy(skip)]`
+const SKIP_IDENT: &str = "skip";
+
+/// The attributes which already export the type they are placed on
+const EXPORTING_MACROS: &[&str] = &["user_data", "export"];
+
+/// Whether the attribute is `#[rudeboy(skip)]`
+fn is_skip_attr(attr: &syn::Attribute) -> bool {
+    if !attr.path.is_ident(RUDEBOY_ATTR) {
+        return false;
+    }
+    match attr.parse_meta() {
+        Ok(syn::Meta::List(l)) => {
+            l.nested.len() == 1
+                && matches!(&l.nested[0], syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident(SKIP_IDENT))
+        }
+        _ => false,
+    }
+}
+
+/// Whether the attribute exports the type itself, e.g. `#[user_data(...)]`
+fn is_exporting_macro(attr: &syn::Attribute) -> bool {
+    is_rudeboy_path(&attr.path, EXPORTING_MACROS)
+}
+
+/// Returns the attributes and generics of the item if it is a public struct or
+/// enum, the types which export_all applies to
+fn exportable(item: &mut syn::Item) -> Option<(&mut Vec<syn::Attribute>, &syn::Generics)> {
+    match item {
+        syn::Item::Struct(s) if matches!(s.vis, syn::Visibility::Public(_)) => Some((&mut s.attrs, &s.generics)),
+        syn::Item::Enum(e) if matches!(e.vis, syn::Visibility::Public(_)) => Some((&mut e.attrs, &e.generics)),
+        _ => None,
+    }
+}
+
+pub(crate) fn impl_export_all_attr_macro(item: syn::Item, attrs: Vec<&syn::NestedMeta>) -> TokenStream2 {
+    let mut ast = match item {
+        syn::Item::Mod(m) => m,
+        item => {
+            return quote_spanned! {
+                item.span() => compile_error!("export_all macro can only be applied to a module");
+            }
+        }
+    };
+    let items = match &mut ast.content {
+        Some((_, items)) => items,
+        None => {
+            return quote_spanned! {
+                ast.span() => compile_error!("export_all macro can only be applied to an inline module");
+            }
+        }
+    };
+
+    for item in items.iter_mut() {
+        let (item_attrs, generics) = match exportable(item) {
+            Some(exportable) => exportable,
+            None => continue,
+        };
+        // Generic types cannot be exported without their type arguments
+        let skipped = item_attrs.iter().any(is_skip_attr);
+        if skipped || item_attrs.iter().any(is_exporting_macro) || !generics.params.is_empty() {
+            item_attrs.retain(|a| !is_skip_attr(a));
+            continue;
+        }
+        let exported = impl_user_data_attr_macro(item.clone(), attrs.clone());
+        *item = syn::Item::Verbatim(exported);
+    }
+    quote!(#ast)
+}
@@ -0,0 +1,215 @@
+use crate::attrs::{cfg_attrs, ContainerOptions, FieldOptions};
+use crate::ecosystem;
+use crate::numbers::checked_integer_conversion;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+pub(crate) fn impl_from_lua_derive(ast: syn::DeriveInput) -> TokenStream2 {
+    let options = match ContainerOptions::try_parse(&ast.attrs) {
+        Ok(options) => options,
+        Err(e) => return e,
+    };
+    let conversion = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) if !options.integers => struct_from_lua(&ast, &fields.named, &options),
+        syn::Data::Enum(e) if e.variants.iter().all(|v| v.fields.is_empty()) => enum_from_lua(&ast, e, &options),
+        syn::Data::Struct(_) if options.integers => {
+            return quote_spanned! {
+                ast.span() => compile_error!("The integers option is only supported on fieldless enums");
+            }
+        }
+        _ => {
+            return quote_spanned! {
+                ast.span() => compile_error!("RudeboyFromLua can only be derived for structs with named fields or fieldless enums");
+            }
+        }
+    };
+    options.paths.allow_lints(quote! {
+        const _: () = {
+            #conversion
+        };
+    })
+}
+
+/// Generates a conversion of a fieldless enum from the name of one of its
+/// variants, or from its discriminant if the integers option is given
+fn enum_from_lua(ast: &syn::DeriveInput, data: &syn::DataEnum, options: &ContainerOptions) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    let from_lua = options.paths.trait_from_lua();
+    let name = &ast.ident;
+    let type_name = name.to_string();
+    let (_, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let mut impl_generics = ast.generics.clone();
+    impl_generics.params.insert(0, syn::parse_quote!('lua));
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    let variant_names: Vec<_> = data.variants.iter().map(|v| v.ident.to_string()).collect();
+    let expected = variant_names.join(", ");
+    let name_arms = data.variants.iter().zip(&variant_names).map(|(v, lua_name)| {
+        let ident = &v.ident;
+        let cfgs = cfg_attrs(&v.attrs);
+        quote! {
+            #( #cfgs )*
+            #lua_name => Ok(#name::#ident),
+        }
+    });
+    let integer_arms = if options.integers {
+        let arms = data.variants.iter().map(|v| {
+            let ident = &v.ident;
+            let cfgs = cfg_attrs(&v.attrs);
+            quote! {
+                #( #cfgs )*
+                i if i == #name::#ident as i64 => Ok(#name::#ident),
+            }
+        });
+        quote! {
+            value @ #rlua::Value::Integer(_) | value @ #rlua::Value::Number(_) => {
+                let i = <i64 as #rlua::FromLua>::from_lua(value, ctx)?;
+                match i {
+                    #( #arms )*
+                    i => Err(#rlua::Error::external(format!(
+                        "no {} variant has the discriminant {}", #type_name, i
+                    ))),
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+    let ctx = if options.integers { quote!(ctx) } else { quote!(_) };
+
+    quote! {
+        impl #impl_generics #from_lua<'lua> for #name #ty_generics #where_clause {
+            fn from_lua(value: #rlua::Value<'lua>, #ctx: #rlua::Context<'lua>) -> #rlua::Result<Self> {
+                match value {
+                    #rlua::Value::String(s) => match s.to_str()? {
+                        #( #name_arms )*
+                        other => Err(#rlua::Error::external(format!(
+                            "unknown {} variant `{}`, expected one of {}", #type_name, other, #expected
+                        ))),
+                    },
+                    #integer_arms
+                    value => Err(#rlua::Error::FromLuaConversionError {
+                        from: value.type_name(),
+                        to: #type_name,
+                        message: None,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Generates a conversion of a struct from a table holding its fields
+fn struct_from_lua(
+    ast: &syn::DeriveInput,
+    fields: &syn::punctuated::Punctuated<syn::Field, syn::Token![,]>,
+    options: &ContainerOptions,
+) -> TokenStream2 {
+    let paths = &options.paths;
+    let rlua = &paths.rlua;
+    let from_lua = paths.trait_from_lua();
+    let name = &ast.ident;
+    let type_name = name.to_string();
+
+    let mut inits = Vec::new();
+    let mut field_tys = Vec::new();
+    let mut skipped_tys = Vec::new();
+    for field in fields {
+        let options = match FieldOptions::try_parse(field) {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+        if options.readonly || options.delegate {
+            return quote_spanned! {
+                field.span() => compile_error!("RudeboyFromLua only supports the skip and validate field options");
+            };
+        }
+        let ident = field.ident.as_ref().unwrap();
+        if options.skip {
+            inits.push(quote!(#ident: Default::default()));
+            skipped_tys.push(&field.ty);
+            continue;
+        }
+        let ty = &field.ty;
+        let key = ident.to_string();
+        let validation = options.validation(&quote!(value), &key, rlua);
+        // Spanned at the field's type, so that a type which cannot be converted
+        // from Lua is reported there
+        let field_value = quote!(table.get::<_, #rlua::Value>(#key)?);
+        let conversion = ecosystem::from_lua_conversion(ty, &field_value, rlua)
+            .or_else(|| checked_integer_conversion(ty, &field_value, "field", &key, rlua));
+        let value = match conversion {
+            Some(conversion) => quote!(#conversion.map_err(|error| field_error(#key, error))?),
+            None => quote_spanned! {
+                ty.span() => <#ty as #from_lua>::from_lua(table.get::<_, #rlua::Value>(#key)?, ctx)
+                    .map_err(|error| field_error(#key, error))?
+            },
+        };
+        inits.push(quote! {
+            #ident: {
+                let value = #value;
+                #validation
+                value
+            }
+        });
+        field_tys.push(ty);
+    }
+
+    // Generic field types may only be converted if their type parameters allow
+    let mut generics = ast.generics.clone();
+    if !ast.generics.params.is_empty() {
+        let where_clause = generics.make_where_clause();
+        for ty in field_tys.iter().filter(|ty| ecosystem::lua_type(ty).is_none()) {
+            where_clause.predicates.push(syn::parse_quote!(#ty: #from_lua<'lua>));
+        }
+        for ty in &skipped_tys {
+            where_clause.predicates.push(syn::parse_quote!(#ty: Default));
+        }
+    }
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let mut impl_generics = generics.clone();
+    impl_generics.params.insert(0, syn::parse_quote!('lua));
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    // Structs whose fields are all skipped only check that a table was given
+    let (table, ctx, field_error) = if field_tys.is_empty() {
+        (quote!(_), quote!(_), quote!())
+    } else {
+        let field_error = quote! {
+            let field_error = |key: &str, error: #rlua::Error| {
+                let detail = match &error {
+                    #rlua::Error::FromLuaConversionError { from, to, .. } => {
+                        format!("expected {}, got {}", to, from)
+                    }
+                    error => ::std::string::ToString::to_string(error),
+                };
+                #rlua::Error::external(format!("field `{}` of {}: {}", key, #type_name, detail))
+            };
+        };
+        (quote!(table), quote!(ctx), field_error)
+    };
+    quote! {
+        impl #impl_generics #from_lua<'lua> for #name #ty_generics #where_clause {
+            fn from_lua(value: #rlua::Value<'lua>, #ctx: #rlua::Context<'lua>) -> #rlua::Result<Self> {
+                let #table = match value {
+                    #rlua::Value::Table(table) => table,
+                    value => {
+                        return Err(#rlua::Error::FromLuaConversionError {
+                            from: value.type_name(),
+                            to: #type_name,
+                            message: None,
+                        })
+                    }
+                };
+                #field_error
+                Ok(#name {
+                    #( #inits, )*
+                })
+            }
+        }
+    }
+}
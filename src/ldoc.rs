@@ -0,0 +1,140 @@
+use crate::ecosystem;
+use crate::manifest::{output_stem, write_output};
+use crate::methods::{is_lua_value, is_multi_value};
+use proc_macro2::{Span, TokenStream as TokenStream2};
+
+/// The environment variable naming the directory which LDoc stubs for the
+/// exported API are written to. No stubs are written if it is unset
+const LDOC_DIR_VAR: &str = "RUDEBOY_LDOC_DIR";
+
+/// Returns the LDoc type of the Lua value the given Rust type converts to, or
+/// None for the unit type. Types which are not known to convert to a builtin Lua
+/// type are named after the Rust type, as they are usually user data
+pub(crate) fn lua_type(ty: &syn::Type) -> Option<String> {
+    if let Some(lua_type) = ecosystem::lua_type(ty) {
+        return Some(lua_type.to_string());
+    }
+    if is_lua_value(ty) || is_multi_value(ty) {
+        return Some("any".to_string());
+    }
+    let segment = match ty {
+        syn::Type::Reference(r) => return lua_type(&r.elem),
+        // Slices are taken from Lua as vectors
+        syn::Type::Slice(s) => {
+            let elem = &s.elem;
+            return lua_type(&syn::parse_quote!(Vec<#elem>));
+        }
+        syn::Type::Paren(p) => return lua_type(&p.elem),
+        syn::Type::Tuple(t) if t.elems.is_empty() => return None,
+        syn::Type::ImplTrait(_) => return Some("function".to_string()),
+        syn::Type::Path(p) => match p.path.segments.last() {
+            Some(segment) => segment,
+            None => return Some("any".to_string()),
+        },
+        _ => return Some("any".to_string()),
+    };
+    let first_arg = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|a| match a {
+            syn::GenericArgument::Type(t) => Some(t),
+            _ => None,
+        }),
+        _ => None,
+    };
+    let name = segment.ident.to_string();
+    Some(match name.as_str() {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => {
+            "integer".to_string()
+        }
+        "f32" | "f64" => "number".to_string(),
+        "bool" => "boolean".to_string(),
+        "String" | "str" | "char" => "string".to_string(),
+        "Vec" | "VecDeque" | "HashMap" | "BTreeMap" | "HashSet" | "BTreeSet" => "table".to_string(),
+        "IntoIter" => "function".to_string(),
+        "Option" => format!("?{}", first_arg.and_then(lua_type).unwrap_or_else(|| "any".to_string())),
+        "Result" | "Box" | "Rc" | "Arc" | "Cow" => match first_arg {
+            Some(syn::Type::TraitObject(_)) => "function".to_string(),
+            Some(t) => return lua_type(t),
+            None => "any".to_string(),
+        },
+        _ => name,
+    })
+}
+
+/// A function exported to Lua, as documented in an LDoc stub
+pub(crate) struct LDocFunction {
+    /// The name of the function in Lua
+    pub name: String,
+    /// The lines of the function's doc comment
+    pub docs: Vec<String>,
+    /// The name and Lua type of each parameter
+    pub params: Vec<(String, String)>,
+    /// The Lua type returned, or None if nothing is returned
+    pub returns: Option<String>,
+}
+
+impl LDocFunction {
+    /// Renders the function's doc comment and an empty definition of it as a
+    /// method of `table`
+    fn render(&self, table: &str, out: &mut String) {
+        let mut lines = self.docs.iter();
+        match lines.next() {
+            Some(summary) => out.push_str(&format!("--- {}\n", summary)),
+            None => out.push_str(&format!("--- {}\n", self.name)),
+        }
+        for line in lines {
+            out.push_str(&format!("-- {}\n", line).replace("-- \n", "--\n"));
+        }
+        for (name, ty) in &self.params {
+            out.push_str(&format!("-- @tparam {} {}\n", ty, name));
+        }
+        if let Some(ty) = &self.returns {
+            out.push_str(&format!("-- @treturn {}\n", ty));
+        }
+        let params: Vec<_> = self.params.iter().map(|(name, _)| name.as_str()).collect();
+        out.push_str(&format!("function {}:{}({}) end\n\n", table, self.name, params.join(", ")));
+    }
+}
+
+/// Writes an LDoc stub documenting the methods exported for the given class
+/// into the LDoc directory, if one has been set. Namespaced methods are
+/// documented in a section of their own, as methods of the namespace table
+pub(crate) fn write_ldoc(
+    span: Span,
+    class: &str,
+    docs: &[String],
+    functions: &[LDocFunction],
+    namespace: Option<(&str, &[LDocFunction])>,
+    constants: &[String],
+) -> TokenStream2 {
+    let mut out = String::new();
+    match docs.first() {
+        Some(summary) => out.push_str(&format!("--- {}\n", summary)),
+        None => out.push_str(&format!("--- Lua bindings for `{}`\n", class)),
+    }
+    out.push_str(&format!("-- @classmod {}\n\n", class));
+    out.push_str(&format!("local {} = {{}}\n\n", class));
+    for function in functions {
+        function.render(class, &mut out);
+    }
+
+    if let Some((namespace, functions)) = namespace {
+        out.push_str(&format!("--- Returns the `{}` namespace\n", namespace));
+        out.push_str(&format!("-- @treturn {}\n", namespace));
+        out.push_str(&format!("function {}:{}() end\n\n", class, namespace));
+        out.push_str(&format!("--- @section {}\n\nlocal {} = {{}}\n\n", namespace, namespace));
+        for function in functions {
+            function.render(namespace, &mut out);
+        }
+    }
+
+    if !constants.is_empty() {
+        out.push_str("--- The associated constants, available from `class_table`\n");
+        out.push_str("-- @table class_table\n");
+        for constant in constants {
+            out.push_str(&format!("-- @field {}\n", constant));
+        }
+        out.push('\n');
+    }
+    out.push_str(&format!("return {}\n", class));
+    write_output(span, LDOC_DIR_VAR, &format!("{}.lua", output_stem(span, class)), out)
+}
@@ -1,21 +1,525 @@
 //! This crate provides derive and attr macros for use by the [`rudeboy`] crate.
 //! Please refer to it for documentation and usage information.
 //!
+//! Every attribute in this crate also accepts the parameters
+//! `crate = "path::to::rudeboy"` and `rlua = "path::to::rlua"`, which override
+//! the paths used to refer to those crates in generated code (`::rudeboy` and
+//! `::rlua` by default). This is needed when either crate is renamed or
+//! re-exported through another crate.
+//!
+//! Similarly, the parameters `to_lua = "path::to::ToLua"` and
+//! `from_lua = "path::to::FromLua"` replace the `rlua::ToLua` and
+//! `rlua::FromLua` traits used to convert field values, method arguments, and
+//! (when `to_lua` is given) method return values. The replacement traits must
+//! have the same shape as rlua's, i.e. `fn to_lua(self, ctx)` and
+//! `fn from_lua(value, ctx)`, and allow conversions to be customized for types,
+//! including foreign ones, which do not implement rlua's traits, e.g. to
+//! convert a `Uuid` to a string.
+//!
+//! Items generated by the macros carry an `#[allow(...)]` attribute for the
+//! lints which generated code can trigger for some types and options, e.g.
+//! `clippy::unnecessary_cast` for the bounds of a `range` check, so that crates
+//! building with `-D warnings` are not broken by rudeboy's output. The items
+//! written by the user are left alone. The parameter `allow(...)` replaces the
+//! list of allowed lints, e.g. `allow(clippy::too_many_arguments)`, and
+//! `allow()` emits no attribute at all.
+//!
+//! Some common foreign types are converted without a replacement trait when
+//! the feature of their crate is enabled, wherever they appear as a field,
+//! method parameter, or method return value. The `uuid` feature converts
+//! `uuid::Uuid` to and from its hyphenated string. The `chrono` feature converts
+//! `chrono::DateTime` to an RFC 3339 string, and from one or from a number of
+//! seconds since the Unix epoch (taken as UTC), as well as `chrono::Duration`
+//! (named with its crate, or as `TimeDelta`) to and from a number of seconds.
+//! These types are recognized by their name alone, as imported with `use`, or
+//! by a path starting with their crate, so e.g. `my::Uuid` is left to rlua.
+//! Generated code names these crates directly, so the crate using the macros
+//! must depend on them.
+//!
+//! Likewise, the `bytes_as_string` feature converts `Vec<u8>` to and from a Lua
+//! string holding the same bytes, rather than a table of numbers, as does a
+//! `&[u8]` parameter. Lua strings may hold any bytes, so binary payloads pass
+//! through unchanged.
+//!
+//! `i128` and `u128` are converted without any feature. A value which a Lua
+//! integer can hold becomes one, and any other becomes its decimal string, since
+//! rlua would convert it to a float and lose precision. They are taken from Lua
+//! integers, from floats holding a whole number, and from decimal strings, and a
+//! value outside of the type's range, e.g. a negative `u128`, raises an error
+//! rather than wrapping.
+//!
+//! Generated code calls traits and their methods by fully qualified paths,
+//! e.g. `::rlua::ToLua::to_lua(value, ctx)`, and declares no `use` items, so
+//! it cannot conflict with the imports or the trait methods in scope where the
+//! macros are used. The trait impls it generates are placed inside anonymous
+//! `const _: () = { ... };` blocks, so that nothing declared alongside them
+//! can collide with the names of the surrounding module.
+//!
+//! No parameter is needed to protect the metatables of exported user data:
+//! rlua itself sets their `__metatable` field, so `getmetatable` returns
+//! `false` in scripts, and Lua cannot replace the metatable of user data.
+//! rlua does not allow the field to be set through `UserDataMethods` either.
+//!
+//! Generated code only relies on the parts of the rlua API which are shared by
+//! rlua 0.17 through 0.19 (the `UserDataMethods` registration methods, the
+//! `MetaMethod` variants, `ToLua`, and `ExternalError`), so it works unchanged
+//! with any of those versions and no version needs to be selected.
+//!
+//! If two items exported by the same attribute would end up with the same name
+//! in Lua, e.g. after case conversion, a compile error is raised naming both.
+//! The names registered by `user_data` itself, e.g. the `get_x` methods of its
+//! Getters parameter, are also compared with those of the `methods` attribute
+//! on the same type, and any registered by both fail to compile when the
+//! userdata's methods are added. So do metamethods registered both by the
+//! `metamethods` attribute and by a method given `metamethod = "Name"`, e.g. a
+//! Collection on a type whose `methods` attribute also registers Len.
+//!
+//! Usage which compiles but is likely a mistake raises a warning rather than an
+//! error, so that it is not first noticed when a script runs: an Index or
+//! NewIndex whose fields are all skipped, or a [`methods`] attribute on an impl
+//! block exporting no methods. Stable compilers have no way for a macro to raise a warning, so
+//! these are reported as the use of a deprecated item, whose note is the
+//! warning. With the `nightly` feature on a nightly compiler, they are instead
+//! raised as proper warnings through the compiler's diagnostics API. The
+//! feature is ignored by stable compilers.
+//!
+//! If the `RUDEBOY_MANIFEST_DIR` environment variable is set at compile time,
+//! the [`methods`] and [`metamethods`] attributes also write a JSON manifest
+//! describing what they export into that directory, named e.g.
+//! `Ship.methods.json`. Method manifests list each method's Lua name,
+//! metamethod (if any), parameter names and types, and return type, along with
+//! the exported constants. The methods registered by [`user_data`] parameters,
+//! e.g. the getters of `Getters`, are listed in the same way in a manifest named
+//! e.g. `Ship.user_data.json`. Since types in different modules may share a
+//! name, the manifests of types outside the root of a crate are prefixed with
+//! the module of their source file, e.g. `fleet.Ship.methods.json` for a type
+//! in `src/fleet.rs`. A macro cannot see the inline modules enclosing it, so
+//! these are not part of the prefix. Since the manifests are only written when
+//! the macros run, a clean build may be needed after setting the variable.
+//!
+//! Likewise, if `RUDEBOY_LDOC_DIR` is set, the [`methods`] attribute writes an
+//! LDoc stub for each type into that directory, e.g. `Ship.lua` (or
+//! `fleet.Ship.lua` for a type in `src/fleet.rs`, as with the manifests),
+//! declaring the exported methods along with their doc comments and the Lua
+//! types of their parameters and return values, for use in an LDoc based
+//! documentation pipeline.
+//!
 //! [`rudeboy`]: https://docs.rs/rudeboy
+#![cfg_attr(rudeboy_diagnostics, feature(proc_macro_diagnostic))]
 use proc_macro::TokenStream;
-use syn;
+
+mod attrs;
+mod case;
+mod chunks;
+mod diagnostics;
+mod ecosystem;
+mod ldoc;
+mod manifest;
+mod names;
+mod numbers;
+mod paths;
+mod stats;
+mod trace;
 
 mod methods;
-use methods::impl_methods_attr_macro;
+use methods::{impl_methods_attr_macro, MethodsOptions};
 
 /// Placed on an inherent impl block; generates an impl of [`RudeboyMethods`] to
-/// add the contained methods to the exported user data. Takes no parameters.
+/// add the contained methods to the exported user data. The type of the impl
+/// block may be a path with generic arguments, e.g. `impl other::Config<u32>`,
+/// and the block's generic parameters, including const generics such as
+/// `impl<const N: usize> Matrix<N>`, are carried through to the generated impls.
+/// Uses of `Self` in parameter and return types are replaced by that type, so
+/// that error messages and generated documentation name the concrete type.
+///
+/// May also be placed on a trait without generic parameters, in which case no
+/// impl is generated; instead, types implementing the trait export its methods,
+/// including default implementations, by naming it with `from_trait`. Methods
+/// of a trait cannot be getters or setters, and the `dot_call`, `stats`, and
+/// `auto_props` parameters are not available on a trait.
+///
+/// Takes zero or more of the following parameters:
+/// * lenient - methods which cannot be exported (e.g. class level functions or
+///   methods which move self) are skipped with a warning instead of causing a
+///   compile error
+/// * catch_panic - panics inside exported methods are caught and converted into
+///   Lua errors carrying the panic message, rather than unwinding through Lua
+/// * trace - each exported method enters a `tracing` span recording the type,
+///   the method name, and a summary of its parameters when called. Requires
+///   the `trace` feature and a dependency on the `tracing` crate
+/// * stats - counts the calls to each exported method and the total time spent
+///   in it, including argument conversion. The counts are returned by a
+///   generated `binding_stats()` associated function as a list of
+///   `(lua_name, calls, total_time)`, e.g. to find the hot script-facing
+///   methods without a profiler. The counts of a generic impl block are kept
+///   per impl block, not per concrete type, so e.g. `binding_stats()` of
+///   `Grid<i32>` and of `Grid<f64>` both report the calls made on either.
+///   Requires the `stats` feature
+/// * help - adds a `help(name)` method, which returns the signature and doc
+///   comment of the named method, e.g. for display in an interactive console.
+///   If called without a name, it returns the signatures of every method
+/// * strict_numbers - arguments for primitive integer and float parameters are
+///   not coerced as rlua otherwise would: integer parameters reject numbers
+///   with a fractional part rather than truncating them, and numeric parameters
+///   reject strings, e.g. `expected i64, got fractional number`
+/// * integers = "float" - `i64` and `u64` return values are converted to Lua
+///   floats rather than integers, raising an error for values which a float
+///   cannot hold exactly (those beyond 2^53), instead of silently losing
+///   precision under Lua 5.1 or LuaJIT. With `integers = "string"`, such values
+///   are converted to decimal strings instead, and `i64` and `u64` parameters
+///   also accept decimal strings, so that e.g. 64-bit IDs survive a round trip
+///   through scripts. `integers = "integer"`, the default, leaves them to rlua
+/// * builder - methods which take `self` by value, such as the steps of a
+///   builder, are exported by calling them on a clone of the user data, so the
+///   original is left unchanged, e.g. `Config.new():width(3):build()`. Requires
+///   the type to implement `Clone`. Without this option, such methods cannot be
+///   exported
+/// * auto_props - every method taking `&self` and no other parameters and
+///   returning a value is read as a property, as if marked with
+///   `#[rudeboy(getter)]`, e.g. `ship.health` rather than `ship:health()`. Since
+///   `ship:health()` looks up `ship.health` before calling it, the two cannot
+///   share a name in Lua, so such methods are no longer registered as methods.
+///   Methods marked `#[rudeboy(method)]` or given an alias are left as methods,
+///   as are methods returning iterators
+/// * dot_call - each method is also added to the table returned by the
+///   generated `class_table` function, as a function taking the user data as
+///   its first argument, so that `Ship.rename(ship, "Ada")` works as well as
+///   `ship:rename("Ada")`
+/// * namespace = "name" - rather than adding the methods directly to the user
+///   data, a single method `name` is added which returns a namespace table
+///   holding them, e.g. `obj:math():rotate(90)`
+/// * prefix = "dbg_" - the given prefix is added to every Lua name the impl
+///   block registers on the user data: the names of its methods and their
+///   aliases, its properties, and the namespace and help methods, e.g.
+///   `obj:dbg_dump()` for a method `dump`. This sets a group of methods apart,
+///   e.g. so that a sandbox may filter out debugging methods by name. Methods in
+///   a namespace are not prefixed, as the namespace already is
+/// * map_err = "path::to::function" - errors returned by methods are converted
+///   into an `rlua::Error` by the given function, which takes the error by
+///   value, rather than being raised with their message. This lets the
+///   function choose the Lua error, e.g. to carry a structured payload. Methods
+///   returning an `rlua::Error` are unaffected
+/// * std_error - the error types returned by methods implement
+///   `std::error::Error`, and are raised with their message followed by those
+///   of their sources rather than returned after `nil`
+/// * from_trait = "path::to::Trait" - also registers the methods of the given
+///   trait, which must itself be marked with this attribute, as implemented by
+///   the type. May be repeated. Methods of the impl block take precedence over
+///   trait methods of the same Lua name
+/// * on_deprecated = "path::to::function" - the warnings of deprecated methods
+///   are passed to the given `fn(&str)`, e.g. to log them through the host's
+///   logger. Without it, they are emitted as `tracing` warnings if the `trace`
+///   option is given, and deprecated methods are a compile error otherwise
+/// * capabilities = "path::to::function" - the given `fn(&str) -> bool`
+///   decides whether the host grants a capability, which methods marked with
+///   `#[rudeboy(capability = "...")]` are only registered if it does. It is
+///   called when the methods are registered, i.e. when the type is first used
+///   in a Lua state, so e.g. the capabilities set from a server's configuration
+///   at startup decide what its scripts may call without recompiling
+///
+/// Methods in the impl block may be individually configured with a
+/// `#[rudeboy(...)]` attribute taking any combination of the following options:
+/// * mut - the method is registered as mutating (via `add_method_mut`) even
+///   though it takes `&self`, e.g. because it mutates through a `RefCell`
+/// * metamethod = "Name" - the method is registered as the given
+///   `rlua::MetaMethod` rather than as a named method, e.g.
+///   `#[rudeboy(metamethod = "Call")]`. Methods taking `&mut self` (or marked
+///   `mut`) are registered via `add_meta_method_mut`
+/// * skip - the method is not exported
+/// * alias = "name" - the method is also registered under the given Lua name,
+///   which may be repeated, e.g. `#[rudeboy(alias = "remove", alias = "delete")]`
+///   to keep the old names of a method working. Aliases are listed in
+///   `LUA_METHODS` like any other method
+/// * deprecated = "message" - the first call to the method from Lua gives a
+///   warning such as "Ship:kill is deprecated: use :despawn() instead", while
+///   the method keeps working. Each alias of the method warns separately. The
+///   warning is reported as given by the `on_deprecated` option
+/// * memoize - the results of the method are cached per userdata by its
+///   arguments, and returned again without calling it when it is called with
+///   the same ones, e.g. for an expensive pure computation. The caches are kept
+///   in the Lua registry until the userdata is collected. Only calls whose
+///   arguments are all nil, booleans, numbers, or strings are cached. Cached
+///   results are not invalidated when the data changes, so they must depend on
+///   nothing but the arguments and data which does not change. The method must
+///   take `&self`, and cannot be a getter, setter, or metamethod, or return an
+///   iterator
+/// * capability = "name" - the method is only registered if the function given
+///   by the `capabilities` parameter of the impl block, which is required,
+///   grants the named capability, e.g. `#[rudeboy(capability = "filesystem")]`.
+///   Otherwise scripts cannot call it, as if it were not exported, though it is
+///   still listed in `LUA_METHODS`. Getters and setters requiring a capability
+///   check it whenever they are accessed, and functions added to the class
+///   table by `dot_call` when the table is created
+/// * export - the method is exported even though it is marked
+///   `#[doc(hidden)]`. Such methods are otherwise skipped, as they are internal
+///   by convention
+/// * map_err = "path::to::function" - as for the impl block, overriding any
+///   function given there. The method must return a `Result`
+/// * std_error - as for the impl block, for the method's error type
+/// * userdata - the method returns (possibly in a `Result` or an `Option`)
+///   another type exported as user data, e.g. a factory method, which is
+///   created with `ctx.create_userdata` rather than converted with `to_lua`.
+///   This is only needed when `to_lua` is overridden, as rlua converts user
+///   data itself, and is implied for methods returning `Self`
+/// * allow_unsafe - the method is exported even though it is an `unsafe fn`,
+///   and is called in an `unsafe` block. Unsafe methods cannot be exported
+///   otherwise, so that doing so is a deliberate decision. It is up to the
+///   method to uphold its safety requirements whatever arguments Lua passes
+/// * getter - the method is read as a property named after it through the
+///   Index metamethod, e.g. `rect.area` rather than `rect:area()`, instead of
+///   being registered as a method. It must take `&self` and no other
+///   parameters. Requires the type to use `#[user_data(Methods)]`, which
+///   registers Index for the getters if it is not among the type's
+///   metamethods, in which case unknown keys are nil as usual. Errors which
+///   the method returns, such as a `String`, are raised rather than returned,
+///   since a property holds a single value
+/// * method - the method is registered as a method even though the `auto_props`
+///   parameter would make it a getter
+/// * setter = "name" - the method is called through the NewIndex metamethod
+///   when the named property is assigned, e.g. `rect.width = 3` calling
+///   `set_width(3.0)`, instead of being registered as a method. It must take
+///   `&mut self` and one other parameter, which is converted and validated as
+///   for any argument. As for getters, requires `#[user_data(Methods)]`. Errors
+///   which the method returns, such as a `String`, are raised rather than
+///   returned, since an assignment has no result
+///
+/// Arguments passed from Lua are converted with `rlua::FromLua`. If a conversion
+/// fails, the Lua error names the type, method, and argument, e.g.
+/// `Ship:set_speed: argument #1 (speed): expected f64, got string`.
+///
+/// Parameters may be validated after conversion with a `#[rudeboy(...)]`
+/// attribute taking any combination of the following options, raising an
+/// argument error such as `must be at most 100, got 150` before the method runs:
+/// * range(min = 0, max = 100) - the argument must lie within the given bounds,
+///   either of which may be omitted. Bounds which are not plain literals are
+///   given as strings, e.g. `min = "-1.5"`. Bounds must be of the parameter's
+///   type, so e.g. `max = 300` for a `u8` fails to compile, though integer
+///   literals may bound floats. NaN is outside of any range of a float
+/// * non_empty - the argument's `is_empty` method must return false, e.g. for
+///   strings and vectors
+///
+/// Calls with too few or too many arguments raise an error listing the
+/// parameters, e.g. "Ship:steer: expected 2 arguments (speed: number, wrap:
+/// boolean), got 1", before any argument is converted. Trailing `nil`
+/// arguments are not counted, and trailing `Option` parameters may be left
+/// out. Metamethods, getters, and setters are not checked, as their arguments
+/// are given by Lua.
+///
+/// Parameters which are references to other types, e.g. `other: &Ship` or
+/// `target: &mut Ship`, are taken from Lua as user data of that type, which is
+/// borrowed for the duration of the call. This allows user data to be passed to
+/// methods without being cloned, or when its type does not implement `Clone`.
+///
+/// Parameters which are vectors or slices, e.g. `Vec<Self>` or `&[Self]`, take
+/// a Lua sequence whose elements are each converted, which clones user data,
+/// e.g. `group:add_all({a, b, c})`.
+///
+/// Parameters of type `impl AsRef<str>` or `impl Borrow<str>` are converted
+/// into a `String`, those of type `impl AsRef<[T]>` or `impl Borrow<[T]>` into
+/// a `Vec<T>`, and those of type `impl Into<T>` into a `T`, which is then passed
+/// to the method. Other `impl Trait` parameters cannot be exported.
+///
+/// Parameters of type `Cow<str>` take a Lua string as `Cow::Owned`, and a
+/// returned `Cow<str>` (or `Result` of one) is converted into a Lua string from
+/// its borrowed contents.
+///
+/// A returned `HashSet` or `BTreeSet` (or `Result` or `Option` of one), which
+/// rlua cannot convert, is converted into a sequence table of its elements, in
+/// sorted order for a `BTreeSet` and in no particular order for a `HashSet`.
+///
+/// Parameters of type `rlua::Value` (or `LuaValue`) receive the argument as
+/// is, without any conversion, so that the method can inspect it itself, e.g.
+/// `fn describe<'lua>(&self, value: rlua::Value<'lua>)`. Such parameters are
+/// not affected by the `from_lua` parameter. Likewise, a last parameter of type
+/// `rlua::MultiValue` (or `LuaMultiValue`) receives all of the remaining
+/// arguments unconverted, e.g. `fn format(&self, pattern: String, args:
+/// rlua::MultiValue)`, so that a method can parse a variable number of
+/// arguments itself.
+///
+/// Arguments for integer parameters narrower than Lua's integers (`u8` through
+/// `u64`, `usize`, `i8` through `i32` and `isize`) are range checked, raising
+/// e.g. "value 300 out of range for u8 parameter 'alpha'" rather than rlua's
+/// conversion error, and numbers with a fractional part are rejected rather
+/// than truncated. Fields of these types are checked in the same way wherever
+/// they are assigned from Lua.
+///
+/// Methods returning an iterator (`impl Iterator<Item = T>`,
+/// `Box<dyn Iterator<Item = T>>`, or a type named `IntoIter` such as
+/// `std::vec::IntoIter<T>`) return a Lua iterator function instead, for use as
+/// `for v in obj:items() do ... end`. The iterator is collected when the method
+/// is called, so it must be finite and its items must implement `rlua::ToLua`.
+///
+/// Methods returning a `Result` have an error raised in Lua, and a value
+/// converted as if it had been returned directly. An `rlua::Error`, named
+/// through the path given by the `rlua` parameter if it is overridden, is
+/// raised as is, as is the error of a bare `Result<T>`, which is taken to be
+/// imported from rlua. A `Box<dyn Error>`, or any error type implementing
+/// `std::error::Error` if the method or its impl block is given `std_error`,
+/// is raised with its message followed by those of its sources, e.g.
+/// "save failed: disk full". With the `anyhow` feature, `anyhow::Result` may
+/// be returned too, raising the error with its context chain. Other errors,
+/// such as a `String` or `&str`, are instead returned after `nil`, following
+/// the Lua convention as rlua does, and so must implement `rlua::ToLua`.
+/// The value in a returned `Option`, including one in a `Result` such as the
+/// `Result<Option<T>, E>` of a lookup, is converted in the same way as a value
+/// returned directly, e.g. into user data or a string, and `None` into nil.
+///
+/// Any associated constants in the impl block are exported through a generated
+/// `class_table` associated function, which returns a Lua table mapping each
+/// constant's name to its value. A generated `add_to_table(ctx, &table)`
+/// associated function sets the class table in a table provided by the caller
+/// under the name of the type, e.g. `plugin.Ship`, for hosts which build their
+/// own namespaces rather than writing to the Lua globals.
+///
+/// The Lua names of the exported methods are listed by a `LUA_METHODS`
+/// associated constant, along with any metamethods, the namespace, and `help`.
+/// Methods behind a `cfg` attribute are listed even when disabled. The names of
+/// the properties accessed by getters and setters are listed by `LUA_PROPERTIES`.
 ///
 /// [`RudeboyMethods`]: trait.RudeboyMethods.html
 #[proc_macro_attribute]
-pub fn methods(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn methods(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let options = match parsed_attrs.map(|attrs| MethodsOptions::try_parse(attrs.iter().collect())) {
+        Ok(Ok(options)) => options,
+        Ok(Err(e)) => return e.into(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_methods_attr_macro(input, &options).into()
+}
+
+mod constants;
+use constants::impl_constants_attr_macro;
+
+/// Placed on an impl block or an inline module; generates an associated (or
+/// module level) function `constants_table` which creates a Lua table mapping
+/// the name of each contained constant to its value. Takes no parameters other
+/// than the crate path overrides.
+#[proc_macro_attribute]
+pub fn constants(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_constants_attr_macro(input, attrs).into()
+}
+
+mod table;
+use table::impl_table_attr_macro;
+
+/// Placed on a `const` or immutable `static` whose value is an array or struct
+/// literal; generates a function `<name>_table`, with the lowercased name of
+/// the item, which creates the equivalent Lua table. Arrays and tuples become
+/// sequences and struct literals become tables keyed by field name, converted
+/// recursively; any other value is converted with `ToLua`. Takes the following
+/// parameters, in addition to the crate path overrides:
+/// - map: the value must be an array of `(key, value)` pairs, which becomes a
+///   table mapping each key to its value
+#[proc_macro_attribute]
+pub fn table(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_table_attr_macro(input, attrs).into()
+}
+
+mod export_all;
+use export_all::impl_export_all_attr_macro;
+
+/// Placed on an inline module; applies the [`user_data`] attribute, with the
+/// given parameters, to every public struct and enum declared in the module,
+/// e.g. `#[export_all(Methods, MetaMethods)]`. This saves annotating each type
+/// of a scripting surface made of many small types, which must all support the
+/// given parameters, e.g. have a [`methods`] impl block for Methods.
+///
+/// A type may opt out with a `#[rudeboy(skip)]` attribute. Types which have
+/// their own [`user_data`] or [`export`] attribute, and generic types, which
+/// cannot be exported without their type arguments, are skipped as well.
+///
+/// [`user_data`]: attr.user_data.html
+/// [`methods`]: attr.methods.html
+/// [`export`]: attr.export.html
+#[proc_macro_attribute]
+pub fn export_all(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
     let input = syn::parse_macro_input!(item as syn::Item);
-    impl_methods_attr_macro(input).into()
+    impl_export_all_attr_macro(input, attrs).into()
+}
+
+mod lua_module;
+use lua_module::impl_lua_module_attr_macro;
+
+/// Placed on an inline module; generates a module level function `lua_module`
+/// which creates a Lua table holding each public function in the module, under
+/// the function's name. Arguments and return values are converted with rlua's
+/// conversion traits, so the functions may not be generic or take references.
+/// Errors returned in a `Result` are raised, or returned after `nil`, as for
+/// the methods of the [`methods`] attribute. Takes no parameters other than the
+/// crate path overrides.
+///
+/// The table is suitable for returning from a loader in `package.preload`, so
+/// that scripts can `require` the module, e.g.
+/// `preload.set("util", ctx.create_function(|ctx, ()| util::lua_module(ctx))?)?`
+#[proc_macro_attribute]
+pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_lua_module_attr_macro(input, attrs).into()
+}
+
+mod test_bindings;
+use test_bindings::impl_test_bindings_attr_macro;
+
+/// Placed on an impl block above its [`methods`] attribute; generates a
+/// `#[test]` which exports a value of the type to Lua and calls each exported
+/// method, property, and metamethod of the block from a script with
+/// default-constructed arguments, so that broken bindings are caught by
+/// `cargo test` rather than when a script first uses them.
+///
+/// The test fails if a method rejects the number of arguments it is called
+/// with, if an argument or return value cannot be converted, or if a method is
+/// missing from the user data. Errors returned by the methods themselves are
+/// ignored, since the arguments are arbitrary, and so are arguments rejected by
+/// the checks of their parameters, e.g. `range`. The type and the types of all
+/// parameters must implement `Default`, or the value may be created by a
+/// function given as `value = "path::to::function"`.
+///
+/// [`methods`]: attr.methods.html
+#[proc_macro_attribute]
+pub fn test_bindings(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_test_bindings_attr_macro(input, attrs).into()
 }
 
 mod metamethods;
@@ -25,27 +529,169 @@ use metamethods::impl_metamethods_attr_macro;
 /// [`RudeboyMetamethods`] to add the specified metamethods to the exported user
 /// data.
 ///
-/// Takes any combination of the following parameters:
+/// Takes any combination of the following parameters. Each may also be given
+/// in any case, or by its Lua metamethod name, e.g. `add` or `__add` for Add:
 /// * Add - allows the use of the `+` operator. Uses `std::ops::Add`
 /// * BAnd - allows the use of the `&` operator. Uses `std::ops::BitAnd`
 /// * BNot - allows the use of the unary `~` operator. Uses `std::ops::Not`
 /// * BOr - allows the use of the `|` operator. Uses `std::ops::BitOr`
 /// * BXor - allows the use of the binary `~` operator. Uses `std::ops::BitXor`
+/// * Collection - allows the use of `[]` with integer indices, `#`, `pairs()`,
+///   and `ipairs()` on the `Vec` wrapped by a newtype, e.g.
+///   `struct Path(Vec<Point>)`. Indices start at 1 and read as `nil` past the
+///   end. Assigning just past the end appends an element, and assigning `nil`
+///   to the last element removes it. Elements are converted as with MapIndex.
+///   Cannot be combined with Index, NewIndex, Freeze, or Pairs, nor with a Len
+///   method of the `methods` attribute
 /// * Div - allows the use of the `/` operator. Uses `std::ops::Div`
 /// * Eq - allows the use of the `==` operator. Uses `std::cmp::PartialEq`
+/// * Freeze - registers a NewIndex which raises the error "attempt to modify
+///   read-only object" for any assignment, e.g. for configuration exposed to
+///   untrusted scripts. Cannot be combined with NewIndex, and prevents setters
+///   from being assigned
+/// * IDiv - allows the use of the `//` operator. Requires the method applying
+///   floor division, e.g. `IDiv(method = "div_floor")`, which is called as with
+///   Pow. `std::ops::Div` is not used, since `/` truncates integers toward zero
+///   rather than flooring, and does not round floats
 /// * Index - allows the use of `.` to retrieve fields. Only usable for structs
-/// with named fields
-/// * Le - allows the use of the `<=` operator. Uses `std::cmp::PartialOrd`
-/// * Lt - allows the use of the `<` operator. Uses `std::cmp::PartialOrd`
+///   with named fields. Accepts the options `skip = "field"`, which may be
+///   repeated, and `rename_all = "..."`, which sets the casing of the keys and
+///   accepts the same rules as serde's `rename_all`, e.g.
+///   `Index(skip = "secret", rename_all = "camelCase")`. Keys are matched
+///   regardless of case with `case_insensitive`, e.g.
+///   `Index(case_insensitive)`, in which case keys differing only in case are
+///   rejected. With `use_serde_attrs`, the `#[serde(...)]` attributes of the
+///   type are mirrored: fields marked `skip` are skipped, fields are keyed by
+///   their `rename`, and the type's `rename_all` applies unless one is given
+///   to Index. Marker fields of type `PhantomData` or `PhantomPinned` are
+///   always skipped. Fields of type `Box<T>`, `Rc<T>`, or `Arc<T>` are
+///   converted from a clone of the `T` they point to, which is returned as user
+///   data if `T` is exported, and NewIndex assigns them a new pointer to the
+///   converted value
+/// * Le - allows the use of the `<=` operator. Uses `std::cmp::PartialOrd`.
+///   Raises a Lua error if `partial_cmp` returns `None`
+/// * Lt - allows the use of the `<` operator. Uses `std::cmp::PartialOrd`.
+///   Raises a Lua error if `partial_cmp` returns `None`
+/// * MapIndex - allows the use of `.` and `[]` to look up and insert keys of
+///   the `HashMap` or `BTreeMap` wrapped by a newtype, e.g.
+///   `struct Scores(HashMap<String, i64>)`. Missing keys read as `nil`, and
+///   assigning `nil` removes a key. Keys and values are converted with
+///   `rlua::FromLua` and `rlua::ToLua`, and values are cloned when read.
+///   Cannot be combined with Index, NewIndex, or Freeze
 /// * Mod - allows the use of the `%` operator. Uses `std::ops::Rem`
 /// * Mul - allows the use of the `*` operator. Uses `std::ops::Mul`
+/// * NewIndex - allows the use of `.` to assign fields in place. Only usable for
+///   structs with named fields. Values are converted with `rlua::FromLua`.
+///   Accepts the same options as Index, along with
+///   `on_set = "path::to::function"`, naming a `fn(&mut T, &str)` which is
+///   called with the value and the key after each assignment, e.g. to mark the
+///   value as changed
+/// * Pairs - allows the use of `pairs()`, yielding the index/value pairs of the
+///   items produced by `std::iter::IntoIterator` on a clone of the value. May
+///   instead name a method returning an iterator, e.g. `Pairs(iter)`, whose
+///   items are cloned
+/// * Pow - allows the use of the `^` operator. Uses `num_traits::Pow` with the
+///   `num_traits` feature, or else the type's `pow` method. May instead name
+///   the method to use, e.g. `Pow(method = "powf")`, which may follow the types
+///   of the operands, e.g. `Pow(f64, method = "powf")`. On generic types,
+///   requires the `num_traits` feature and cannot name a method
 /// * Shl - allows the use of the `<<` operator. Uses `std::ops::Shl`
 /// * Shr - allows the use of the `>>` operator. Uses `std::ops::Shr`
 /// * Sub - allows the use of the binary `-` operator. Uses `std::ops::Sub`
+/// * ToString - allows the use of `tostring()`. Uses `std::fmt::Display`, or
+///   instead interpolates the fields of a struct into a format string, e.g.
+///   `ToString(format = "{name} ({id})")`, so that no `Display` impl is needed.
+///   Fields of tuple structs are named by index, e.g. `"{0}"`, and may be given
+///   format specs as with `format!`, e.g. `"{x:.2}"` or `"{tags:?}"`
 /// * Unm - allows the use of the unary `-` operator. Uses `std::ops::Neg`
 ///
-/// Note: all binary operators currently take a parameter of the same type as the
-/// type the metamethod is being added to. This is not obviously not ideal.
+/// Additionally, the following options may be given:
+/// * by_ref - operators and comparisons are applied to references to the
+///   operands, e.g. `&*a + &*b` using `impl Add for &T`, rather than to the
+///   values themselves, which requires the type to be `Copy`. The right hand
+///   operand is borrowed from its userdata instead of being cloned, so the type
+///   need not be `Clone` either. Listed operand types are referenced too, e.g.
+///   `Mul(f64)` uses `impl Mul<&f64> for &T`
+/// * Infer - additionally generates the metamethods implied by the type's
+///   derives (`PartialEq` gives Eq, `PartialOrd` gives Lt and Le) and by the
+///   `std::ops` traits listed in a `#[rudeboy_ops(...)]` attribute on the type,
+///   e.g. `#[rudeboy_ops(Add, Neg)]` gives Add and Unm. The `#[derive(...)]`
+///   attribute must come after this attribute, or it will not be seen
+/// * lua51 - for Lua 5.1 and LuaJIT, which have no floor division or bitwise
+///   metamethods. IDiv, BAnd, BOr, BXor, BNot, Shl, and Shr are instead
+///   registered as methods named after them, e.g. `a:band(b)` or `a:bnot()`.
+///   Enabling the `lua51` feature has the same effect for every type
+/// * OperatorMethods - each operator and comparison is also registered as a
+///   method named after it, e.g. `a:add(b)`, `a:unm()`, or `a:lt(b)`, so that
+///   operators can be called explicitly, e.g. from LuaJIT or by tooling
+///   discovering the methods of a value
+/// * newtype - only usable on structs with a single unnamed field, e.g.
+///   `struct Meters(f64)`. Operators and comparisons are applied to the inner
+///   values instead of the type itself, so the type needs no `std::ops` or
+///   `std::cmp` impls, and the results of operators are wrapped back up in the
+///   type. Either operand of a binary operator may also be a plain Lua value
+///   which converts into the inner type, e.g. `meters * 2`
+/// * trace - each generated metamethod enters a `tracing` span recording the
+///   type and the metamethod name when called. Requires the `trace` feature
+///   and a dependency on the `tracing` crate
+///
+/// Fields may be excluded from NewIndex by marking them `#[rudeboy(skip)]`, or
+/// `#[rudeboy(readonly)]`, in which case assigning them raises a
+/// "field X is read-only" error. A single field may be marked `#[rudeboy(delegate)]`,
+/// in which case Index forwards any key which does not name a field to that
+/// field's value (converted with `rlua::ToLua`, so it is cloned). Methods found
+/// on the delegate may be called on the outer value, e.g. `car:rev()` calls
+/// `car.engine:rev()`.
+///
+/// Fields marked `#[rudeboy(flatten)]` are not themselves reachable through
+/// Index and NewIndex, which instead read and assign the fields of the
+/// flattened value as though they were fields of the outer value, e.g.
+/// `config.port` for `config.net.port`, as with serde's `flatten`. The type of
+/// the flattened field must register Index itself, and NewIndex too if the
+/// outer value does, whose options (e.g. `rename_all`) apply to its fields.
+/// Flattening may be nested, and the outer value's own fields take precedence.
+///
+/// Eq may be given the fields to compare, e.g. `Eq(fields(id, name))`, in which
+/// case values are equal if those fields are, and only they need implement
+/// `PartialEq`. This keeps fields such as caches or handles out of equality as
+/// seen by scripts. A value is never equal to a userdata of another type,
+/// unless that type is listed with `with_types`, e.g.
+/// `Eq(with_types(Handle, Entity))`, in which case values are compared with a
+/// userdata of a listed type by the type's impl of `PartialEq<Handle>`, etc.
+/// Lua calls the Eq of the left hand operand, so the other types should list
+/// this one in turn for the comparison to work either way around.
+///
+/// By default, the binary operators (Add, Sub, Mul, Div, Mod, IDiv, Pow, BAnd,
+/// BOr, BXor, Shl, and Shr) take a right hand operand of the same type as the type the
+/// metamethod is being added to. Other operand types may be listed instead,
+/// e.g. `Mul(f64, Self)` for a vector implementing both `Mul<f64>` and `Mul`,
+/// in which case each is tried in turn and a Lua error is raised if none
+/// matches. Types which are not plain paths are given as strings, e.g.
+/// `Add("Vec<f64>")`. The result is converted according to the operator's
+/// `Output` type, which need not be the type itself. Operand types may likewise
+/// be listed in the `#[rudeboy_ops(...)]` attribute used by Infer, e.g.
+/// `#[rudeboy_ops(Mul(f64))]`, but cannot be combined with newtype.
+///
+/// The keys of the generated metamethods, e.g. `["__add", "__index"]`, are
+/// listed by a `LUA_METAMETHODS` associated constant, along with any methods
+/// standing in for them, and the fields reachable
+/// through Index and NewIndex by a `LUA_FIELDS` associated constant. Keys
+/// forwarded to a delegate or flattened field are not listed.
+///
+/// Generic types are supported, in which case the impl of
+/// [`RudeboyMetaMethods`] is bounded by only what the given metamethods use,
+/// stated on the type itself, e.g. `Wrapper<T>: Add + Copy` for Add or
+/// `T: FromLua` for a NewIndex field of type `T`. A type's `rlua::UserData` impl
+/// may then be bounded according to the metamethods it registers.
+///
+/// For other types, the `std::ops` trait of each operator is asserted to be
+/// implemented (along with `Copy`, which operators on the type itself need
+/// unless by_ref is given), so that a missing impl is reported at the operator
+/// in the attribute's parameters, e.g. ``cannot add `Point` to `Point` ``.
+///
+/// When generated by user_data along with Methods, Index and NewIndex also
+/// access the properties of the type's getters and setters, for keys which name
+/// no field.
 ///
 /// [`RudeboyMetaMethods`]: trait.RudeboyMetaMethods.html
 #[proc_macro_attribute]
@@ -65,13 +711,144 @@ mod user_data;
 use user_data::impl_user_data_attr_macro;
 
 /// Generates an implementation of `rlua::UserData` for the tagged type
-/// definition or the type that matches a tagged impl block.
+/// definition or the type that matches a tagged impl block. May also be placed
+/// on a type alias without generic parameters, e.g. `type Grid = Matrix<f64>;`,
+/// in which case the implementation is generated for the aliased type. The
+/// alias may have const generic parameters, e.g.
+/// `type Square<const N: usize> = Matrix<f64, N, N>;`, which are carried through
+/// to the implementation, as are the generic parameters of a tagged type
+/// definition or impl block.
 ///
 /// Takes zero or more of the following parameters. If given none, then the
 /// exported type will have no methods or metamethods available.
 /// * MetaMethods - will use the [`RudeboyMetaMethods`] trait to add generated
-/// meta methods
+///   meta methods. May optionally be given a list of metamethods, e.g.
+///   `MetaMethods(Add, Eq, Index)`, in which case the impl of
+///   [`RudeboyMetaMethods`] is generated as if by the [`metamethods`] attribute.
+///   Only usable on struct or enum definitions
+/// * Getters - only usable on structs with named fields. Adds a `get_<field>()`
+///   method for each field, which returns a clone of the field's value
 /// * Methods - will use the [`RudeboyMethods`] trait to add generated methods
+/// * Setters - only usable on structs with named fields. Adds a
+///   `set_<field>(value)` method for each field, which replaces the field's
+///   value
+/// * Cmp - adds a `cmp(other)` method, which returns -1, 0, or 1 as the value
+///   is less than, equal to, or greater than `other` according to
+///   `std::cmp::PartialOrd`, e.g. for use with `table.sort`. Raises a Lua error
+///   if the values are not comparable
+/// * Default - the type must implement `std::default::Default`. Adds a
+///   `default()` function to the user data, and generates an associated
+///   function `default_function` creating the same function for use in a class
+///   table, e.g. `Ship.default()` after
+///   `class.set("default", Ship::default_function(ctx)?)?`
+/// * Parse - the type must implement `std::str::FromStr` with an error
+///   implementing `Display`. Adds a `parse(str)` function to the user data,
+///   which returns the parsed value or raises the parse error's message as a
+///   Lua error, and generates an associated function `parse_function` creating
+///   the same function for use in a class table, e.g. `Duration.parse("5s")`
+///   after `class.set("parse", Duration::parse_function(ctx)?)?`
+/// * Update - only usable on structs with named fields. Adds an `update(table)`
+///   method, which assigns each field named by a key of the table to the
+///   corresponding value. Unknown keys and values of the wrong type raise an
+///   error, in which case no field is changed
+/// * VariantPredicates - only usable on enums. Adds an `is_<variant>()` method
+///   for each variant, which returns whether the value is that variant. The
+///   casing of the method names may be set with `rename_all`, e.g.
+///   `VariantPredicates(rename_all = "camelCase")` produces `isCircle()`.
+///   Accepts the same rules as serde's `rename_all`
+/// * CommonFields - only usable on enums. Adds a `get_<field>()` method for
+///   each named field which every variant has with the same type, e.g. an `id`
+///   shared by all variants of an `Entity` enum, which returns a clone of the
+///   field whatever the variant. Fields skipped with `#[rudeboy(skip)]` in any
+///   variant are left out
+/// * extends = "Base" - inherits the methods and metamethods which the `Base`
+///   type registers in its own `rlua::UserData` impl. The type must implement
+///   `Deref<Target = Base>` and `DerefMut`, which is used to call the inherited
+///   methods. The type's own methods and metamethods take precedence over the
+///   inherited ones. Inherited functions which borrow the userdata as `Base`
+///   themselves (e.g. namespaced methods) are not supported
+/// * include(Health, Inventory) - also registers the methods which each of the
+///   listed component types exports through its `#[methods]` block, calling
+///   them on the component obtained through the type's `AsRef` and `AsMut`
+///   impls for it, e.g. `player:damage(3)` for a method of `Health`. This lets
+///   the script API of an entity be composed from those of its components.
+///   Types may also be given as strings, e.g. `include("Stack<Item>")`. The
+///   type's own methods take precedence, and components' getters and setters
+///   are not included
+/// * shared - only usable on struct, enum, or type alias definitions.
+///   Additionally generates a `<Type>Shared` handle type wrapping an
+///   `Arc<RwLock<Type>>`, which implements `rlua::UserData` with the same
+///   methods and metamethods as the type. Every Lua value created from a handle
+///   (or its clones) refers to the same value as the handle, so changes made by
+///   scripts are seen by Rust and vice versa. As with `RefCell`, a method which needs a borrow that
+///   conflicts with an existing one raises a Lua error. The same limitations
+///   apply as to inherited functions with `extends`
+/// * shared = "mutex" - as with `shared`, but the handle wraps an
+///   `Arc<Mutex<Type>>`. A method called while the lock is held, e.g. by a
+///   method of the same value further up the call stack, raises a Lua error
+///   rather than deadlocking, as does a poisoned lock. `shared = "rwlock"` is
+///   equivalent to `shared`
+/// * type_name - generates a `LUA_TYPE_NAME` associated constant holding a
+///   stable name for the type, which defaults to its module path, e.g.
+///   `game::ship::Ship`, and may be given explicitly with
+///   `type_name = "game.Ship"`. The user data gets a `type_name()` function
+///   returning it, for debugging, and a `register_type_name(ctx)` associated
+///   function stores a predicate under the name in the Lua registry, which
+///   returns whether a value is a userdata of the type. This lets other native
+///   libraries sharing the Lua state identify the type, while the same type
+///   exported from two crates keeps distinct names
+/// * exports - generates a `LUA_EXPORTS` associated constant listing every
+///   name a script may access on the user data: the functions generated by
+///   user_data itself, plus the `LUA_METHODS` and `LUA_PROPERTIES` of Methods
+///   and the
+///   `LUA_METAMETHODS` and `LUA_FIELDS` of MetaMethods. This may be used as
+///   the whitelist of a sandbox, instead of maintaining one by hand. Names
+///   inherited with `extends` are not included
+/// * introspect - the user data gets `methods()` and `metamethods()` functions
+///   describing it at runtime, e.g. for the tab completion of a console.
+///   `methods()` returns a table keyed by the name of every function a script
+///   may call on the user data, whose value is a table holding the `min` and
+///   `max` number of arguments it takes, with `max` nil for a final `MultiValue`
+///   parameter. The arguments of functions generated by user_data itself, such
+///   as those of Getters, are not known, so their tables are empty.
+///   `metamethods()` returns a sorted sequence of the keys of the registered
+///   metamethods, e.g. `{"__add", "__eq"}`. Names inherited with `extends` are
+///   not included
+/// * extensions - keys which name no field, method, or property of the user
+///   data are looked up in the type's extension table, if one is set, so that
+///   scripts can add methods written in Lua, e.g.
+///   `ship:set_extensions({ describe = function(self) ... end })` makes
+///   `ship:describe()` work on every `Ship`. The table is set per type and per
+///   Lua state, by scripts through the generated `set_extensions(table)`
+///   method, or by the host through the `set_extensions(ctx, table)` associated
+///   function, and removed by setting nil. Index is registered for it if it is
+///   not among the type's metamethods. With `MapIndex` or `Collection`, keys
+///   missing from the map or list are looked up instead of reading as nil
+///
+/// Fields of a struct may be individually configured with a `#[rudeboy(...)]`
+/// attribute taking any combination of the following options:
+/// * skip - the field is not exposed by Getters, Setters or NewIndex
+/// * readonly - the field is exposed by Getters and Index, but assigning it
+///   through Setters, Update, or NewIndex raises a "field X is read-only" error
+/// * delegate - unknown keys are forwarded to the field by Index
+/// * flatten - the fields of the field are accessed through Index and NewIndex
+///   as fields of the struct. Cannot be combined with delegate, readonly, or
+///   validate
+/// * validate = "path::to::function" - values assigned to the field through
+///   Setters, Update, or NewIndex are first passed by reference to the given
+///   function, which returns a `Result` whose error implements `Display`. If it
+///   returns an error, the field is left unchanged and a Lua error is raised,
+///   e.g. `invalid value for field speed: must be positive`
+///
+/// Non-generic structs additionally get a `new_in_lua(ctx, fields...)`
+/// associated function, taking the value of each field in order, which creates
+/// the struct directly as an `rlua::AnyUserData`
+///
+/// Since rlua requires user data to be `Send`, types for which user data is
+/// created by generated code (non-generic structs, and types with Default,
+/// Parse or `shared`) are asserted to be `Send`, or `Send + Sync` with
+/// `shared = "rwlock"`. A type which is not reports the field responsible at
+/// the type's name, rather than inside rlua
 ///
 /// Note: if you wish to add additional (meta)methods beyond the ones generated
 /// by rudeboy, do not use this macro and instead manually call the appropriate
@@ -79,6 +856,7 @@ use user_data::impl_user_data_attr_macro;
 ///
 /// [`RudeboyMetaMethods`]: trait.RudeboyMetaMethods.html
 /// [`RudeboyMethods`]: trait.RudeboyMethods.html
+/// [`metamethods`]: attr.metamethods.html
 #[proc_macro_attribute]
 pub fn user_data(attr: TokenStream, item: TokenStream) -> TokenStream {
     use syn::parse::Parser;
@@ -91,3 +869,124 @@ pub fn user_data(attr: TokenStream, item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::Item);
     impl_user_data_attr_macro(input, attrs).into()
 }
+
+mod from_lua;
+use from_lua::impl_from_lua_derive;
+
+/// Derived for plain structs with named fields; implements `rlua::FromLua` by
+/// reading each field from a Lua table under the field's name, so that the
+/// struct can be taken as a method parameter without being exported as user
+/// data, e.g. `ship:configure({ speed = 10, name = "Ada" })`. Missing keys are
+/// converted from nil, so optional fields should have `Option` types.
+///
+/// Fields marked `#[rudeboy(skip)]` are not read from the table, and are
+/// initialized with `Default::default()` instead. The crate path overrides may
+/// be given in a `#[rudeboy(...)]` attribute on the struct; when `from_lua` is
+/// given, the replacement trait is implemented and used for each field instead.
+///
+/// If a field cannot be converted, the error names the field and struct, e.g.
+/// ``field `speed` of Config: expected f64, got string``. Fields marked
+/// `#[rudeboy(validate = "path::to::function")]` are also checked by the
+/// function after conversion, as they are when assigned to user data.
+///
+/// May also be derived for fieldless enums, which are converted from the name
+/// of one of their variants, e.g. `ship:set_mode("Cruise")`. Given the
+/// `#[rudeboy(integers)]` option, they are also converted from the discriminant
+/// of a variant, for scripts which already pass numeric flags.
+#[proc_macro_derive(RudeboyFromLua, attributes(rudeboy))]
+pub fn rudeboy_from_lua(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    impl_from_lua_derive(input).into()
+}
+
+mod to_lua;
+use to_lua::impl_to_lua_derive;
+
+/// Derived for plain structs with named fields; implements `rlua::ToLua` by
+/// creating a Lua table holding each field under the field's name. This suits
+/// value-like types returned from methods, which scripts read as plain tables,
+/// e.g. `ship:position().x`, rather than as user data referring back to Rust.
+///
+/// Fields marked `#[rudeboy(skip)]` are left out of the table. The crate path
+/// overrides may be given in a `#[rudeboy(...)]` attribute on the struct; when
+/// `to_lua` is given, the replacement trait is implemented and used for each
+/// field instead.
+///
+/// May also be derived for fieldless enums, which are converted into the name
+/// of their variant as a Lua string, matching the conversion derived by
+/// [`RudeboyFromLua`].
+///
+/// [`RudeboyFromLua`]: derive.RudeboyFromLua.html
+#[proc_macro_derive(RudeboyToLua, attributes(rudeboy))]
+pub fn rudeboy_to_lua(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    impl_to_lua_derive(input).into()
+}
+
+mod export;
+use export::impl_export_attr_macro;
+
+/// Combines the functionality of [`methods`], [`metamethods`], and
+/// [`user_data`] into a single attribute. Placed on either a type definition
+/// or its inherent impl block, and generates an implementation of
+/// `rlua::UserData` for the type.
+///
+/// Takes zero or more of the following parameters:
+/// * methods - adds the methods generated by [`RudeboyMethods`]. When placed on
+///   an inherent impl block, the impl of [`RudeboyMethods`] is generated from
+///   the block as if by the [`methods`] attribute
+/// * metamethods - adds the metamethods generated by [`RudeboyMetaMethods`]. May
+///   optionally be given a list of metamethods, e.g. `metamethods(Add, Index)`,
+///   in which case the impl of [`RudeboyMetaMethods`] is generated as if by the
+///   [`metamethods`] attribute. The list is only usable on type definitions
+///
+/// Note: `rlua::UserData` is implemented by each use of this attribute, so it
+/// should only be placed on one of the type definition or the impl block
+///
+/// [`methods`]: attr.methods.html
+/// [`metamethods`]: attr.metamethods.html
+/// [`user_data`]: attr.user_data.html
+/// [`RudeboyMetaMethods`]: trait.RudeboyMetaMethods.html
+/// [`RudeboyMethods`]: trait.RudeboyMethods.html
+#[proc_macro_attribute]
+pub fn export(attr: TokenStream, item: TokenStream) -> TokenStream {
+    use syn::parse::Parser;
+    let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token!(,)>::parse_terminated;
+    let parsed_attrs = parser.parse(attr);
+    let attrs = match &parsed_attrs {
+        Ok(ok) => ok.iter().collect(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = syn::parse_macro_input!(item as syn::Item);
+    impl_export_attr_macro(input, attrs).into()
+}
+
+mod wrap;
+use wrap::{impl_wrap_macro, WrapInput};
+
+/// Generates a newtype wrapper exporting a foreign type as user data, which the
+/// orphan rule otherwise prevents, e.g.
+/// `rudeboy::wrap!(pub LuaPath for std::path::PathBuf { methods: [...] });`.
+/// Attributes given before the visibility, such as doc comments or derives, are
+/// placed on the wrapper struct, whose single public field holds the value.
+///
+/// Since a macro cannot see the signatures of the foreign type's methods, each
+/// method to export is listed with the signature it is exported with, e.g.
+/// `fn exists(&self) -> bool` or `fn join(&self, path: String) -> LuaPath`.
+/// The wrapper gets a method of that signature which calls the wrapped value's
+/// method of the same name with the same arguments, and converts its return
+/// value with `From`, so that methods returning the foreign type may return the
+/// wrapper instead. These methods are exported as if by the [`methods`]
+/// attribute, and may be configured with `#[rudeboy(...)]` attributes in the
+/// same way. Being inherent methods, they take precedence over the wrapped
+/// type's methods of the same name in Rust as well.
+///
+/// The wrapper also implements `Deref` and `DerefMut` to the wrapped type,
+/// `From` it, and `From` the wrapper for it, along with `rlua::UserData`.
+///
+/// [`methods`]: attr.methods.html
+#[proc_macro]
+pub fn wrap(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as WrapInput);
+    impl_wrap_macro(input).into()
+}
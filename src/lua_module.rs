@@ -0,0 +1,128 @@
+use crate::attrs::cfg_attrs;
+use crate::methods::ResultError;
+use crate::paths::CratePaths;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// Generates the statement adding the given function to the module table
+fn function_code(f: &syn::ItemFn, paths: &CratePaths) -> Result<TokenStream2, TokenStream2> {
+    let signature = &f.sig;
+    if !signature.generics.params.is_empty() || signature.asyncness.is_some() {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("lua_module cannot export generic or async functions");
+        });
+    }
+    let mut names = Vec::new();
+    let mut tys = Vec::new();
+    for input in &signature.inputs {
+        match input {
+            syn::FnArg::Typed(syn::PatType { pat, ty, .. }) => match pat.as_ref() {
+                syn::Pat::Ident(i) => {
+                    names.push(&i.ident);
+                    tys.push(ty);
+                }
+                pat => {
+                    return Err(quote_spanned! {
+                        pat.span() => compile_error!("Expected an identifier here");
+                    })
+                }
+            },
+            syn::FnArg::Receiver(r) => {
+                return Err(quote_spanned! {
+                    r.span() => compile_error!("Expected a function without a receiver");
+                })
+            }
+        }
+    }
+
+    let name = &signature.ident;
+    let lua_name = name.to_string();
+    // Returned errors are raised or returned after nil as for exported methods
+    let call = quote!(#name(#( #names ),*));
+    let result = match ResultError::try_parse(signature, None, None, false, paths)? {
+        None | Some(ResultError::Returned) => quote!(Ok(#call)),
+        Some(error) => match error.conversion(&paths.rlua) {
+            Some(conversion) => quote!(#call.map_err(#conversion)),
+            None => call,
+        },
+    };
+    let cfgs = cfg_attrs(&f.attrs);
+    let params = if names.is_empty() {
+        quote!(())
+    } else {
+        quote!((#( #names, )*): (#( #tys, )*))
+    };
+    Ok(quote! {
+        #( #cfgs )*
+        module.set(#lua_name, ctx.create_function(|_, #params| #result)?)?;
+    })
+}
+
+fn mod_lua_module_attr_macro(ast: &syn::ItemMod, paths: &CratePaths) -> TokenStream2 {
+    let mut ast = ast.clone();
+    let items = match &mut ast.content {
+        Some((_, items)) => items,
+        None => {
+            return quote_spanned! {
+                ast.span() => compile_error!("lua_module macro can only be applied to an inline module");
+            }
+        }
+    };
+
+    let rlua = &paths.rlua;
+    let mut functions = Vec::new();
+    for item in items.iter() {
+        if let syn::Item::Fn(f) = item {
+            if matches!(f.vis, syn::Visibility::Public(_)) {
+                match function_code(f, paths) {
+                    Ok(code) => functions.push(code),
+                    Err(e) => return e,
+                }
+            }
+        }
+    }
+
+    items.push(syn::parse_quote! {
+        /// Creates a Lua table holding the public functions of this module,
+        /// suitable for returning from a `package.preload` loader
+        pub fn lua_module<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Table<'lua>> {
+            let module = ctx.create_table()?;
+            #( #functions )*
+            Ok(module)
+        }
+    });
+    quote!(#ast)
+}
+
+pub(crate) fn impl_lua_module_attr_macro(
+    item: syn::Item,
+    attrs: Vec<&syn::NestedMeta>,
+) -> TokenStream2 {
+    let mut paths = CratePaths::default();
+    for attr in attrs {
+        match paths.try_parse_option(attr) {
+            Ok(true) => (),
+            Ok(false) => {
+                return quote_spanned! {
+                    attr.span() => compile_error!("Expected a valid lua_module parameter");
+                }
+            }
+            Err(e) => return e,
+        }
+    }
+    // Arguments and return values are converted by rlua itself, so the
+    // conversion traits cannot be overridden
+    if paths.to_lua.is_some() || paths.from_lua.is_some() {
+        return quote_spanned! {
+            item.span() => compile_error!("lua_module does not support the to_lua and from_lua parameters");
+        };
+    }
+
+    match &item {
+        syn::Item::Mod(m) => mod_lua_module_attr_macro(m, &paths),
+        _ => quote_spanned! {
+            item.span() => compile_error!("lua_module macro can only be applied to a module");
+        },
+    }
+}
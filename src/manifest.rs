@@ -0,0 +1,171 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned, ToTokens};
+
+/// The environment variable naming the directory which manifests describing the
+/// exported API are written to. No manifests are written if it is unset
+const MANIFEST_DIR_VAR: &str = "RUDEBOY_MANIFEST_DIR";
+
+/// A JSON value making up part of a manifest
+pub(crate) enum Json {
+    Null,
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub(crate) fn str(s: impl Into<String>) -> Json {
+        Json::Str(s.into())
+    }
+
+    fn render(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Str(s) => {
+                out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.render(out);
+                }
+                out.push(']');
+            }
+            Json::Object(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    Json::str(*key).render(out);
+                    out.push(':');
+                    value.render(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Describes a method registered on a type's userdata, which is registered as
+/// the given metamethod if any
+pub(crate) fn method_manifest(
+    lua_name: &str,
+    metamethod: Option<String>,
+    params: Vec<(&syn::Ident, &syn::Type)>,
+    returns: String,
+) -> Json {
+    let params = params.into_iter().map(|(name, ty)| {
+        Json::Object(vec![
+            ("name", Json::str(name.to_string())),
+            ("type", Json::str(type_string(ty))),
+        ])
+    });
+    Json::Object(vec![
+        ("name", Json::str(lua_name)),
+        ("metamethod", metamethod.map_or(Json::Null, Json::Str)),
+        ("params", Json::Array(params.collect())),
+        ("returns", Json::str(returns)),
+    ])
+}
+
+/// Renders the given tokens, e.g. a type, as they would usually be written in
+/// source code, e.g. `Vec<String>` rather than `Vec < String >`
+pub(crate) fn type_string(tokens: &impl ToTokens) -> String {
+    quote!(#tokens)
+        .to_string()
+        .replace(" :: ", "::")
+        .replace(":: ", "::")
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace("& ", "&")
+        .replace(" (", "(")
+        .replace("( ", "(")
+        .replace(" )", ")")
+}
+
+/// Writes the manifest produced by the given macro for the given type into the
+/// manifest directory, if one has been set. Returns a compile error if the
+/// manifest could not be written
+pub(crate) fn write_manifest(span: Span, type_name: &str, kind: &str, manifest: Json) -> TokenStream2 {
+    let mut json = String::new();
+    manifest.render(&mut json);
+    write_output(span, MANIFEST_DIR_VAR, &format!("{}.{}.json", output_stem(span, type_name), kind), json)
+}
+
+/// Converts a type name into a name usable as part of a file name
+fn file_stem(type_name: &str) -> String {
+    type_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Returns the stem of the files written for the type at the given span, which
+/// is prefixed with the module of the source file it is in, e.g. `ships.Ship`
+/// for `Ship` in `src/ships.rs` or `src/ships/mod.rs`, so that types of the same
+/// name in different modules are written to different files. Types in the root
+/// of a crate, or whose source file is unknown to the compiler, are not prefixed
+pub(crate) fn output_stem(span: Span, type_name: &str) -> String {
+    let file = span.file();
+    let path = std::path::Path::new(&file);
+    let mut modules: Vec<_> = match path.extension() {
+        Some(extension) if extension == "rs" => path
+            .with_extension("")
+            .iter()
+            .map(|component| component.to_string_lossy().into_owned())
+            .collect(),
+        _ => Vec::new(),
+    };
+    // The module path of a file starts after the source directory of its crate
+    if let Some(src) = modules.iter().rposition(|component| component == "src") {
+        modules.drain(..=src);
+    } else if !modules.is_empty() {
+        modules.drain(..modules.len() - 1);
+    }
+    if modules.last().is_some_and(|file| file == "mod") {
+        modules.pop();
+    }
+    if let [root] = modules.as_slice() {
+        if root == "lib" || root == "main" {
+            modules.clear();
+        }
+    }
+    modules.push(file_stem(type_name));
+    modules.join(".")
+}
+
+/// Writes the given contents into the named file in the directory given by the
+/// environment variable, if it is set. Returns a compile error if the file
+/// could not be written
+pub(crate) fn write_output(span: Span, dir_var: &str, file_name: &str, contents: String) -> TokenStream2 {
+    let dir = match std::env::var_os(dir_var) {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => return quote!(),
+    };
+    let path = dir.join(file_name);
+    match std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, contents)) {
+        Ok(()) => quote!(),
+        Err(e) => {
+            let message = format!("Failed to write {}: {}", path.display(), e);
+            quote_spanned! {
+                span => compile_error!(#message);
+            }
+        }
+    }
+}
@@ -1,42 +1,1033 @@
-use std::collections::HashSet;
-use quote::{quote, quote_spanned};
-use syn;
+use std::collections::HashMap;
+use quote::{quote, quote_spanned, ToTokens};
 use syn::spanned::Spanned;
-use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use crate::attrs::{function_path, strip_helper_attrs, FieldOptions, RUDEBOY_OPS_ATTR};
+use crate::case::{split_words, RenameRule};
+use crate::chunks::cached_function;
+use crate::diagnostics::warning;
+use crate::ecosystem;
+use crate::manifest::{type_string, write_manifest, Json};
+use crate::numbers::checked_integer_conversion;
+use crate::paths::CratePaths;
+use crate::trace::{check_trace_feature, trace_span, TRACE_IDENT};
 
-fn operator_method(name: TokenStream2, rlua_enum: TokenStream2, operator: TokenStream2) -> TokenStream2 {
-    quote! {
-        fn #name<'lua, M: ::rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-            methods.add_meta_method(::rlua::MetaMethod::#rlua_enum, |ctx, data, other: Self| {
-                use ::rlua::ToLua;
-                let ret = (*data #operator other);
-                Ok(ret.to_lua(ctx))
+/// Sets the span of each of the given tokens, e.g. so that an operator which is
+/// not implemented is reported at the given span
+fn respan(tokens: TokenStream2, span: Span) -> TokenStream2 {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+/// Where an operator metamethod is registered on the user data
+struct Registration {
+    /// The `UserDataMethods` functions registering a method and a function
+    /// respectively, e.g. `add_meta_method` and `add_meta_function`
+    method: TokenStream2,
+    function: TokenStream2,
+    /// The name, or `rlua::MetaMethod`, it is registered under
+    key: TokenStream2,
+}
+
+/// How a binary operator is applied to its operands
+enum Operator {
+    /// An infix operator, e.g. `+`
+    Infix(TokenStream2),
+    /// A function called with both operands, e.g. `num_traits::Pow::pow`
+    Function(TokenStream2),
+    /// A method of the left hand operand called with the right hand one, e.g.
+    /// `powf`
+    Method(syn::Ident),
+}
+
+impl Operator {
+    /// Returns the expression applying this operator to the given operands,
+    /// with the operator itself spanned at the given span
+    fn apply(&self, lhs: TokenStream2, rhs: TokenStream2, span: Span) -> TokenStream2 {
+        match self {
+            Operator::Infix(operator) => {
+                let operator = respan(operator.clone(), span);
+                quote!(#lhs #operator #rhs)
+            }
+            Operator::Function(function) => {
+                let function = respan(function.clone(), span);
+                quote!(#function(#lhs, #rhs))
+            }
+            Operator::Method(method) => {
+                let method = syn::Ident::new(&method.to_string(), span);
+                quote!((#lhs).#method(#rhs))
+            }
+        }
+    }
+}
+
+/// Generates a binary operator metamethod whose right hand operand is `Self`.
+/// With the by_ref option, the operator is applied to references to both
+/// operands, the right hand one being borrowed from its userdata
+fn operator_method(registration: &Registration, operator: &Operator, prelude: TokenStream2, span: Span, options: &MetaMethodsOptions) -> TokenStream2 {
+    let Registration { method, key, .. } = registration;
+    let rlua = &options.paths.rlua;
+    let to_lua = options.paths.trait_to_lua();
+    if options.by_ref {
+        let ret = operator.apply(quote_spanned!(span => data), quote_spanned!(span => other), span);
+        quote_spanned! {
+            span => methods.#method(#key, |ctx, data, other: #rlua::AnyUserData| {
+                #prelude
+                let other = other.borrow::<Self>()?;
+                let other: &Self = &other;
+                let ret = #ret;
+                Ok(#to_lua::to_lua(ret, ctx))
+            });
+        }
+    } else {
+        let ret = operator.apply(quote_spanned!(span => *data), quote_spanned!(span => other), span);
+        quote_spanned! {
+            span => methods.#method(#key, |ctx, data, other: Self| {
+                #prelude
+                let ret = #ret;
+                Ok(#to_lua::to_lua(ret, ctx))
             });
         }
     }
 }
 
-fn unary_operator_method(name: TokenStream2, rlua_enum: TokenStream2, operator: TokenStream2) -> TokenStream2 {
+/// Generates an operator metamethod whose right hand operand may be any of the
+/// given types, each of which is tried in turn. The result of the operator is
+/// converted according to its own type, which need not be `Self`
+fn mixed_operator_method(
+    registration: &Registration,
+    operator: &Operator,
+    operands: &[syn::Type],
+    self_name: &str,
+    prelude: TokenStream2,
+    span: Span,
+    options: &MetaMethodsOptions,
+) -> TokenStream2 {
+    let Registration { method, key, .. } = registration;
+    let rlua = &options.paths.rlua;
+    let to_lua = options.paths.trait_to_lua();
+    let from_lua = options.paths.trait_from_lua();
+    let expected = operands
+        .iter()
+        .map(|ty| match type_string(ty) {
+            name if name == "Self" => self_name.to_string(),
+            name => name,
+        })
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let attempts = operands.iter().map(|ty| {
+        if !options.by_ref {
+            let ret = operator.apply(quote_spanned!(ty.span() => *data), quote_spanned!(ty.span() => other), span);
+            quote_spanned! {
+                ty.span() => if let Ok(other) = <#ty as #from_lua>::from_lua(other.clone(), ctx) {
+                    let ret = #ret;
+                    return #to_lua::to_lua(ret, ctx);
+                }
+            }
+        } else if type_string(ty) == "Self" {
+            // Operands of the type itself are borrowed rather than cloned
+            let ret = operator.apply(quote_spanned!(ty.span() => data), quote_spanned!(ty.span() => other), span);
+            quote_spanned! {
+                ty.span() => if let #rlua::Value::UserData(ud) = &other {
+                    if let Ok(other) = ud.borrow::<Self>() {
+                        let other: &Self = &other;
+                        let ret = #ret;
+                        return #to_lua::to_lua(ret, ctx);
+                    }
+                }
+            }
+        } else {
+            let ret = operator.apply(quote_spanned!(ty.span() => data), quote_spanned!(ty.span() => other), span);
+            quote_spanned! {
+                ty.span() => if let Ok(other) = <#ty as #from_lua>::from_lua(other.clone(), ctx) {
+                    let other = &other;
+                    let ret = #ret;
+                    return #to_lua::to_lua(ret, ctx);
+                }
+            }
+        }
+    });
+    quote_spanned! {
+        span => methods.#method(#key, |ctx, data, other: #rlua::Value| {
+            #prelude
+            #( #attempts )*
+            Err(#rlua::ExternalError::to_lua_err(format!("unsupported operand: expected {}, got {}", #expected, other.type_name())))
+        });
+    }
+}
+
+fn unary_operator_method(registration: &Registration, operator: TokenStream2, prelude: TokenStream2, span: Span, options: &MetaMethodsOptions) -> TokenStream2 {
+    let Registration { method, key, .. } = registration;
+    let to_lua = options.paths.trait_to_lua();
+    let operator = respan(operator, span);
+    let operand = if options.by_ref { quote!(data) } else { quote!(*data) };
+    quote_spanned! {
+        span => methods.#method(#key, |ctx, data, ()| {
+            #prelude
+            let ret = #operator #operand;
+            Ok(#to_lua::to_lua(ret, ctx))
+        });
+    }
+}
+
+/// Generates an ordering metamethod from `PartialOrd::partial_cmp`, which is true
+/// if the ordering matches the given pattern, and raises a Lua error if the
+/// values have no ordering
+fn comparison_method(registration: &Registration, ordering: TokenStream2, prelude: TokenStream2, span: Span, options: &MetaMethodsOptions) -> TokenStream2 {
+    let Registration { method, key, .. } = registration;
+    let rlua = &options.paths.rlua;
+    // With the by_ref option, the other operand is borrowed rather than cloned
+    let (other_ty, other) = if options.by_ref {
+        (quote!(#rlua::AnyUserData), quote!(&*other.borrow::<Self>()?))
+    } else {
+        (quote!(Self), quote!(&other))
+    };
+    quote_spanned! {
+        span => methods.#method(#key, |_, data, other: #other_ty| {
+            #prelude
+            match ::std::cmp::PartialOrd::partial_cmp(&*data, #other) {
+                Some(ordering) => Ok(matches!(ordering, #ordering)),
+                None => {
+                    Err(#rlua::ExternalError::to_lua_err("values are not comparable"))
+                }
+            }
+        });
+    }
+}
+
+/// Generates an expression converting the Lua value in `value` into the inner
+/// value of a newtype, which is taken from the userdata if the value is of the
+/// newtype itself, or converted from the Lua value otherwise
+fn newtype_operand(value: TokenStream2, inner: &syn::Type, paths: &CratePaths) -> TokenStream2 {
+    let rlua = &paths.rlua;
+    let from_lua = paths.trait_from_lua();
     quote! {
-        fn #name<'lua, M: ::rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-            methods.add_meta_method(::rlua::MetaMethod::#rlua_enum, |ctx, data, ()| {
-                use ::rlua::ToLua;
-                let ret = #operator *data;
-                Ok(ret.to_lua(ctx))
+        match #value {
+            #rlua::Value::UserData(ud) => {
+                let inner = ud.borrow::<Self>().ok().map(|data| ::std::clone::Clone::clone(&data.0));
+                match inner {
+                    Some(inner) => inner,
+                    None => <#inner as #from_lua>::from_lua(#rlua::Value::UserData(ud), ctx)?,
+                }
+            }
+            value => <#inner as #from_lua>::from_lua(value, ctx)?,
+        }
+    }
+}
+
+/// A Lua chunk which looks up a key on the delegate of a userdata. Functions
+/// found on the delegate are wrapped so that calling them as methods of the
+/// userdata passes the delegate as `self` instead
+const DELEGATE_LOOKUP: &str = "\
+    local ud, inner, key = ...
+    local value = inner[key]
+    if type(value) ~= 'function' then
+        return value
+    end
+    return function(this, ...)
+        if rawequal(this, ud) then
+            return value(inner, ...)
+        end
+        return value(this, ...)
+    end";
+
+/// Generates an expression converting a clone of the given field of `data` into
+/// Lua, evaluating to an `rlua::Result` of the `rlua::Value`. Conversions are
+/// spanned at the field's type, so that a type which cannot be converted into
+/// Lua is reported there
+fn to_lua_field(field: &syn::Field, options: &MetaMethodsOptions) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    let to_lua = options.paths.trait_to_lua();
+    let ident = field.ident.as_ref().unwrap();
+    if let Some(conversion) = ecosystem::to_lua_conversion(&field.ty, &quote!(value), rlua) {
+        return quote_spanned! {
+            field.ty.span() => {
+                let value = ::std::clone::Clone::clone(&data.#ident);
+                #conversion
+            }
+        };
+    }
+    match smart_pointer_target(&field.ty) {
+        Some(_) => quote_spanned! {
+            field.ty.span() => #to_lua::to_lua(::std::clone::Clone::clone(&*data.#ident), ctx)
+        },
+        None => quote_spanned!(field.ty.span() => #to_lua::to_lua(::std::clone::Clone::clone(&data.#ident), ctx)),
+    }
+}
+
+/// Generates a block assigning the `rlua::Value` in `value` to the given field
+/// of `data` under the given key, evaluating to an `rlua::Result<()>`
+fn assign_field(
+    key: &str,
+    field: &syn::Field,
+    field_options: &FieldOptions,
+    access: &FieldAccessOptions,
+    options: &MetaMethodsOptions,
+) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    let from_lua = options.paths.trait_from_lua();
+    let ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+    // Read-only fields are still matched, so that assigning to one raises a
+    // more helpful error than an unknown index
+    if field_options.readonly {
+        let message = format!("field {} is read-only", key);
+        return quote! {{
+            Err(#rlua::ExternalError::to_lua_err(#message))
+        }};
+    }
+    let on_set = access.on_set.as_ref().map(|on_set| {
+        quote_spanned!(on_set.span() => #on_set(data, #key);)
+    });
+    let validation = field_options.validation(&quote!(value), key, rlua);
+    let conversion = ecosystem::from_lua_conversion(ty, &quote!(value), rlua)
+        .or_else(|| checked_integer_conversion(ty, &quote!(value), "field", key, rlua));
+    let value = match smart_pointer_target(ty) {
+        _ if conversion.is_some() => quote!(#conversion?),
+        Some(target) => quote!(<#ty>::new(<#target as #from_lua>::from_lua(value, ctx)?)),
+        None => quote!(<#ty as #from_lua>::from_lua(value, ctx)?),
+    };
+    quote_spanned! {
+        ty.span() => {
+            let value = #value;
+            #validation
+            data.#ident = value;
+            #on_set
+            Ok(())
+        }
+    }
+}
+
+/// Generates expressions looking up the key in `index_str` on each of the given
+/// flattened fields of `data`, evaluating to an `Option` of the value read if
+/// the field's type has a field of that name. Flattened types must register
+/// Index themselves
+fn flattened_gets(flattened: &[&syn::Field]) -> Vec<TokenStream2> {
+    flattened
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().unwrap();
+            let ty = &field.ty;
+            quote_spanned!(ty.span() => <#ty>::rudeboy_get_field(&data.#ident, ctx, index_str)?)
+        })
+        .collect()
+}
+
+/// Generates expressions assigning the `rlua::Value` in `value` to the key in
+/// `index_str` on each of the given flattened fields of `data`, evaluating to
+/// whether the field's type has a field of that name. Flattened types must
+/// register NewIndex themselves
+fn flattened_sets(flattened: &[&syn::Field]) -> Vec<TokenStream2> {
+    flattened
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().unwrap();
+            let ty = &field.ty;
+            quote_spanned! {
+                ty.span() => <#ty>::rudeboy_set_field(&mut data.#ident, ctx, index_str, ::std::clone::Clone::clone(&value))?
+            }
+        })
+        .collect()
+}
+
+/// The code generated for a single metamethod
+struct MetaMethodCode {
+    /// The `RudeboyMetaMethods` function which registers the metamethod, or
+    /// None if the trait has no such function, in which case the metamethod is
+    /// registered directly by `generate_metamethods`
+    hook: Option<TokenStream2>,
+    /// The statement registering the metamethod on `methods`
+    registration: TokenStream2,
+}
+
+impl MetaMethodCode {
+    fn hooked(hook: TokenStream2, registration: TokenStream2) -> Self {
+        MetaMethodCode {
+            hook: Some(hook),
+            registration,
+        }
+    }
+}
+
+/// The parameter requesting that metamethods be inferred from the type definition
+const INFER_IDENT: &str = "Infer";
+
+/// The parameter requesting that operators be applied to the inner value of a
+/// newtype
+const NEWTYPE_IDENT: &str = "newtype";
+
+/// The parameter requesting that floor division and bitwise operators be
+/// registered as named methods, since Lua 5.1 has no metamethods for them
+const LUA51_IDENT: &str = "lua51";
+
+/// The parameter requesting that operators be applied to references to the
+/// operands, so that the type need not be `Copy`
+const BY_REF_IDENT: &str = "by_ref";
+
+/// The parameter requesting that operators also be registered as named methods,
+/// e.g. `a:add(b)`
+const OPERATOR_METHODS_IDENT: &str = "OperatorMethods";
+
+/// The option of Eq listing the fields to compare, e.g. `Eq(fields(id))`
+const EQ_FIELDS_IDENT: &str = "fields";
+
+/// The option of Eq listing the other exported types compared with, e.g.
+/// `Eq(with_types(Handle))`
+const EQ_WITH_TYPES_IDENT: &str = "with_types";
+
+/// The option of Pow and IDiv naming the method applying them, e.g.
+/// `Pow(method = "powf")`
+const OPERATOR_METHOD_IDENT: &str = "method";
+
+/// A Lua chunk which takes a sequence and returns the values expected from
+/// `__pairs`: a function which steps through the index/value pairs of the
+/// sequence, along with its initial state
+const PAIRS_CHUNK: &str = "\
+    local items = ...
+    local function step(items, i)
+        i = i + 1
+        local value = items[i]
+        if value ~= nil then
+            return i, value
+        end
+    end
+    return step, items, 0";
+
+/// Returns the right hand operand types given to a binary operator, e.g. the
+/// types in `Mul(Self, f64)`. Types which are not plain paths may be given as
+/// strings, e.g. `Mul("Vec<f64>")`
+fn operand_types(list: &syn::MetaList) -> Result<Vec<syn::Type>, TokenStream2> {
+    use syn::{Meta, NestedMeta};
+    // The method applying Pow or IDiv is parsed separately
+    let nested = list.nested.iter().filter(|nested| !is_operator_method(list, nested)).collect::<Vec<_>>();
+    if nested.is_empty() && list.nested.len() > nested.len() {
+        return Ok(Vec::new());
+    } else if nested.is_empty() {
+        return Err(quote_spanned! {
+            list.span() => compile_error!("Expected the types of the operands, e.g. Mul(f64)");
+        });
+    }
+    nested
+        .into_iter()
+        .map(|nested| match nested {
+            NestedMeta::Meta(Meta::Path(p)) => Ok(syn::parse_quote!(#p)),
+            NestedMeta::Lit(syn::Lit::Str(s)) => s.parse().map_err(|e| e.to_compile_error()),
+            _ => Err(quote_spanned! {
+                nested.span() => compile_error!("Expected the type of an operand");
+            }),
+        })
+        .collect()
+}
+
+/// Whether the given option of a binary operator is the method applying Pow or
+/// IDiv, e.g. `method = "powf"`, rather than the type of an operand
+fn is_operator_method(list: &syn::MetaList, nested: &syn::NestedMeta) -> bool {
+    use syn::{Meta, NestedMeta};
+    MetaMethod::try_parse(&list.path).is_ok_and(|mm| matches!(mm, MetaMethod::Pow(_) | MetaMethod::IDiv(_)))
+        && matches!(nested, NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(OPERATOR_METHOD_IDENT))
+}
+
+/// Returns the method applying Pow or IDiv named in its options, e.g. `powf`
+/// in `Pow(f64, method = "powf")`
+fn operator_method_option(list: &syn::MetaList) -> Result<Option<syn::Ident>, TokenStream2> {
+    use syn::{Meta, NestedMeta};
+    let mut method = None;
+    for nested in &list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(lit),
+                ..
+            })) if path.is_ident(OPERATOR_METHOD_IDENT) => method = Some(lit.parse().map_err(|e| e.to_compile_error())?),
+            nested if is_operator_method(list, nested) => {
+                return Err(quote_spanned! {
+                    nested.span() => compile_error!("Expected the name of a method, e.g. Pow(method = \"powf\")");
+                })
+            }
+            _ => (),
+        }
+    }
+    Ok(method)
+}
+
+/// Returns the fields compared by Eq and the other types it compares with,
+/// e.g. `id` and `name` in `Eq(fields(id, name))`, and `Handle` in
+/// `Eq(with_types(Handle))`
+fn eq_options(list: &syn::MetaList) -> Result<(Option<Vec<syn::Ident>>, Vec<syn::Type>), TokenStream2> {
+    use syn::{Meta, NestedMeta};
+    if list.nested.is_empty() {
+        return Err(quote_spanned! {
+            list.span() => compile_error!("Expected the fields to compare or the types to compare with, e.g. Eq(fields(id)) or Eq(with_types(Handle))");
+        });
+    }
+    let mut fields = None;
+    let mut types = Vec::new();
+    for nested in &list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(EQ_FIELDS_IDENT) && !l.nested.is_empty() => {
+                let idents = l
+                    .nested
+                    .iter()
+                    .map(|nested| match nested {
+                        NestedMeta::Meta(Meta::Path(p)) if p.get_ident().is_some() => Ok(p.get_ident().unwrap().clone()),
+                        _ => Err(quote_spanned! {
+                            nested.span() => compile_error!("Expected the name of a field");
+                        }),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                fields.get_or_insert_with(Vec::new).extend(idents);
+            }
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(EQ_WITH_TYPES_IDENT) && !l.nested.is_empty() => {
+                types.extend(operand_types(l)?);
+            }
+            _ => {
+                return Err(quote_spanned! {
+                    nested.span() => compile_error!("Expected the fields to compare or the types to compare with, e.g. Eq(fields(id)) or Eq(with_types(Handle))");
+                })
+            }
+        }
+    }
+    Ok((fields, types))
+}
+
+/// Generates an Eq metamethod which compares the other operand with
+/// `PartialEq` if it is a userdata of the same type, using only the given
+/// fields if any, or of one of the given other types, using the impl of
+/// `PartialEq` with that type. It is false for any other operand
+fn userdata_eq_method(
+    registration: &Registration,
+    fields: Option<&[syn::Ident]>,
+    types: &[syn::Type],
+    prelude: TokenStream2,
+    span: Span,
+    options: &MetaMethodsOptions,
+) -> TokenStream2 {
+    let Registration { method, key, .. } = registration;
+    let rlua = &options.paths.rlua;
+    let same = match fields {
+        // Comparisons are spanned at the field names, so that a field which is
+        // not PartialEq is reported there
+        Some(fields) => {
+            let comparisons = fields.iter().map(|f| quote_spanned!(f.span() => && data.#f == other.#f));
+            quote!(true #( #comparisons )*)
+        }
+        None => quote_spanned!(span => ::std::cmp::PartialEq::eq(data, &*other)),
+    };
+    // Comparisons with other types are spanned at them, so that a missing impl
+    // of PartialEq is reported there
+    let others = types.iter().filter(|ty| type_string(ty) != "Self").map(|ty| {
+        quote_spanned! {
+            ty.span() => if other.is::<#ty>() {
+                let other = other.borrow::<#ty>()?;
+                return Ok(::std::cmp::PartialEq::<#ty>::eq(data, &*other));
+            }
+        }
+    });
+    quote_spanned! {
+        span => methods.#method(#key, |_, data, other: #rlua::AnyUserData| {
+            #prelude
+            if other.is::<Self>() {
+                let other = other.borrow::<Self>()?;
+                return Ok(#same);
+            }
+            #( #others )*
+            Ok(false)
+        });
+    }
+}
+
+/// The serde field option excluding a field, mirrored by `use_serde_attrs`
+const SERDE_SKIP_IDENT: &str = "skip";
+
+/// The serde field option naming a field, mirrored by `use_serde_attrs`
+const SERDE_RENAME_IDENT: &str = "rename";
+
+/// Returns the options given in the `#[serde(...)]` attributes among the given
+/// attributes. Attributes which cannot be parsed are left for serde to report
+fn serde_options(attrs: &[syn::Attribute]) -> impl Iterator<Item = syn::NestedMeta> + '_ {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::List(l)) => Some(l.nested),
+            _ => None,
+        })
+        .flatten()
+}
+
+/// Returns the `key = value` options of the `#[serde(...)]` attributes
+fn serde_name_values(attrs: &[syn::Attribute]) -> impl Iterator<Item = syn::MetaNameValue> + '_ {
+    serde_options(attrs).filter_map(|nested| match nested {
+        syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => Some(nv),
+        _ => None,
+    })
+}
+
+/// Returns the flag options of the `#[serde(...)]` attributes, e.g. `skip`
+fn serde_paths(attrs: &[syn::Attribute]) -> impl Iterator<Item = syn::Path> + '_ {
+    serde_options(attrs).filter_map(|nested| match nested {
+        syn::NestedMeta::Meta(syn::Meta::Path(p)) => Some(p),
+        _ => None,
+    })
+}
+
+/// Whether the given type is a zero-sized marker, i.e. `PhantomData<T>` or
+/// `PhantomPinned`, which holds no value to access and is skipped by Index and
+/// NewIndex
+fn is_marker(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) => p
+            .path
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "PhantomData" || s.ident == "PhantomPinned"),
+        _ => false,
+    }
+}
+
+/// Returns the type pointed to if the given type is a `Box<T>`, `Rc<T>`, or
+/// `Arc<T>`, whose fields are accessed through the pointer by Index and
+/// NewIndex, since the pointer itself cannot be converted
+fn smart_pointer_target(ty: &syn::Type) -> Option<&syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Box" && segment.ident != "Rc" && segment.ident != "Arc" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            syn::GenericArgument::Type(target) => Some(target),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The map wrapped by a newtype given MapIndex
+struct MapTypes<'a> {
+    key: &'a syn::Type,
+    value: &'a syn::Type,
+    /// The bounds the key and hasher need for the map to be looked up in and
+    /// inserted into, which generic types are given
+    key_bounds: Vec<TokenStream2>,
+}
+
+/// Returns the key and value types of the `HashMap` or `BTreeMap` wrapped by
+/// the given struct, or an error if it is not a newtype of one
+fn map_types(ast: &syn::DeriveInput) -> Result<MapTypes<'_>, TokenStream2> {
+    let error = || {
+        quote_spanned! {
+            ast.span() => compile_error!("MapIndex metamethod can only be applied to a newtype of a HashMap or BTreeMap");
+        }
+    };
+    let segment = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unnamed(fields),
+            ..
+        }) if fields.unnamed.len() == 1 => match &fields.unnamed[0].ty {
+            syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last().ok_or_else(error)?,
+            _ => return Err(error()),
+        },
+        _ => return Err(error()),
+    };
+    let args: Vec<_> = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => return Err(error()),
+    };
+    match (segment.ident.to_string().as_str(), args.as_slice()) {
+        ("HashMap", [key, value]) => Ok(MapTypes {
+            key,
+            value,
+            key_bounds: vec![quote!(#key: ::std::hash::Hash + ::std::cmp::Eq)],
+        }),
+        ("HashMap", [key, value, hasher]) => Ok(MapTypes {
+            key,
+            value,
+            key_bounds: vec![
+                quote!(#key: ::std::hash::Hash + ::std::cmp::Eq),
+                quote!(#hasher: ::std::hash::BuildHasher),
+            ],
+        }),
+        ("BTreeMap", [key, value]) => Ok(MapTypes {
+            key,
+            value,
+            key_bounds: vec![quote!(#key: ::std::cmp::Ord)],
+        }),
+        _ => Err(error()),
+    }
+}
+
+/// Returns the element type of the `Vec` wrapped by the given struct, or an
+/// error if it is not a newtype of one
+fn collection_element(ast: &syn::DeriveInput) -> Result<&syn::Type, TokenStream2> {
+    let error = || {
+        quote_spanned! {
+            ast.span() => compile_error!("Collection metamethod can only be applied to a newtype of a Vec");
+        }
+    };
+    let segment = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unnamed(fields),
+            ..
+        }) if fields.unnamed.len() == 1 => match &fields.unnamed[0].ty {
+            syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last().ok_or_else(error)?,
+            _ => return Err(error()),
+        },
+        _ => return Err(error()),
+    };
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if segment.ident == "Vec" && args.args.len() == 1 => match &args.args[0] {
+            syn::GenericArgument::Type(element) => Ok(element),
+            _ => Err(error()),
+        },
+        _ => Err(error()),
+    }
+}
+
+/// Returns the expressions converting an element of a map or list wrapped by a
+/// newtype, held in `value`, into Lua and back, each evaluating to an
+/// `rlua::Result`. `container` names the kind of container in range errors
+fn element_conversions(
+    ty: &syn::Type,
+    container: &str,
+    ast: &syn::DeriveInput,
+    options: &MetaMethodsOptions,
+) -> (TokenStream2, TokenStream2) {
+    let rlua = &options.paths.rlua;
+    let to_lua = options.paths.trait_to_lua();
+    let from_lua = options.paths.trait_from_lua();
+    let name = ast.ident.to_string();
+    let to_lua_value = ecosystem::to_lua_conversion(ty, &quote!(value), rlua)
+        .unwrap_or_else(|| quote_spanned!(ty.span() => #to_lua::to_lua(value, ctx)));
+    let from_lua_value = ecosystem::from_lua_conversion(ty, &quote!(value), rlua)
+        .or_else(|| checked_integer_conversion(ty, &quote!(value), container, &name, rlua))
+        .unwrap_or_else(|| quote_spanned!(ty.span() => <#ty as #from_lua>::from_lua(value, ctx)));
+    (to_lua_value, from_lua_value)
+}
+
+/// Returns the code looking up and assigning properties of the type's getters
+/// and setters in the Index and NewIndex of a map or list wrapped by a newtype,
+/// for the `rlua::Value` keys in `key`. String keys naming a property take
+/// precedence over the contents of the map or list
+fn key_properties(options: &MetaMethodsOptions) -> (TokenStream2, TokenStream2) {
+    if !options.properties {
+        return (quote!(), quote!());
+    }
+    let rlua = &options.paths.rlua;
+    (
+        quote! {
+            if let #rlua::Value::String(index) = &key {
+                if let Some(value) = Self::rudeboy_get_property(data, ctx, index.to_str()?)? {
+                    return Ok(value);
+                }
+            }
+        },
+        quote! {
+            if let #rlua::Value::String(index) = &key {
+                if Self::rudeboy_set_property(data, ctx, index.to_str()?, ::std::clone::Clone::clone(&value))? {
+                    return Ok(());
+                }
+            }
+        },
+    )
+}
+
+/// Returns an expression evaluating to the `rlua::Value` which the type's
+/// extension table holds under the `rlua::Value` in `key`, which is nil if the
+/// key is not in the table or there is none, or if the type has no extensions
+fn extension_lookup(key: TokenStream2, options: &MetaMethodsOptions) -> TokenStream2 {
+    if options.extensions {
+        quote!(Self::rudeboy_extension(ctx, #key)?)
+    } else {
+        let rlua = &options.paths.rlua;
+        quote!(#rlua::Value::Nil)
+    }
+}
+
+/// The option of ToString giving the format of the string, e.g.
+/// `ToString(format = "{name} ({id})")`
+const FORMAT_IDENT: &str = "format";
+
+/// A field interpolated by the format given to ToString, along with its format
+/// spec, e.g. `.2` in `{x:.2}`
+struct FormatField {
+    member: syn::Member,
+    ty: syn::Type,
+    spec: String,
+}
+
+/// Parses the format given to ToString, returning the format string with its
+/// field names removed, e.g. `"{} ({})"` for `"{name} ({id})"`, and the fields
+/// to be passed to it. Fields are named as in a struct literal, i.e. by index
+/// for tuple structs, and each must implement `Display`, or `Debug` with `:?`
+fn tostring_format(format: &syn::LitStr, ast: &syn::DeriveInput) -> Result<(String, Vec<FormatField>), TokenStream2> {
+    let error = |message: String| {
+        quote_spanned! {
+            format.span() => compile_error!(#message);
+        }
+    };
+    let fields = match &ast.data {
+        syn::Data::Struct(s) => &s.fields,
+        _ => return Err(error("ToString format can only be applied to structs".to_string())),
+    };
+    let value = format.value();
+    let mut chars = value.chars().peekable();
+    let mut positional = String::new();
+    let mut interpolated = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                positional.push_str("{{");
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                positional.push_str("}}");
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(error("Unterminated `{` in ToString format".to_string())),
+                    }
+                }
+                let (name, spec) = match placeholder.split_once(':') {
+                    Some((name, spec)) => (name.trim(), spec.to_string()),
+                    None => (placeholder.trim(), String::new()),
+                };
+                let field = fields.iter().enumerate().find(|(i, field)| match &field.ident {
+                    Some(ident) => ident == name,
+                    None => i.to_string() == name,
+                });
+                let (index, field) = match field {
+                    Some(field) => field,
+                    None => return Err(error(format!("No field named `{}`", name))),
+                };
+                let member = match &field.ident {
+                    Some(ident) => syn::Member::Named(ident.clone()),
+                    None => syn::Member::Unnamed(syn::Index::from(index)),
+                };
+                if spec.is_empty() {
+                    positional.push_str("{}");
+                } else {
+                    positional.push_str(&format!("{{:{}}}", spec));
+                }
+                interpolated.push(FormatField {
+                    member,
+                    ty: field.ty.clone(),
+                    spec,
+                });
+            }
+            '}' => return Err(error("Unmatched `}` in ToString format".to_string())),
+            c => positional.push(c),
+        }
+    }
+    Ok((positional, interpolated))
+}
+
+/// Options for the metamethods which access fields, i.e. Index and NewIndex
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+struct FieldAccessOptions {
+    /// The names of fields which are not accessible
+    skip: Vec<syn::LitStr>,
+    /// The rule used to convert field names into Lua keys, if any
+    rename_all: Option<RenameRule>,
+    /// Whether keys are matched regardless of case
+    case_insensitive: bool,
+    /// Whether the `skip`, `rename`, and `rename_all` options of `#[serde(...)]`
+    /// attributes are mirrored
+    use_serde_attrs: bool,
+    /// The function called with the receiver and the key after each assignment
+    /// through NewIndex
+    on_set: Option<syn::Path>,
+}
+
+impl FieldAccessOptions {
+    const SKIP_IDENT: &'static str = "skip";
+    const RENAME_ALL_IDENT: &'static str = "rename_all";
+    const CASE_INSENSITIVE_IDENT: &'static str = "case_insensitive";
+    const USE_SERDE_ATTRS_IDENT: &'static str = "use_serde_attrs";
+    const ON_SET_IDENT: &'static str = "on_set";
+
+    fn try_parse(list: &syn::MetaList) -> Result<FieldAccessOptions, TokenStream2> {
+        use syn::{Meta, NestedMeta};
+        let mut options = FieldAccessOptions::default();
+        for nested in &list.nested {
+            let nv = match nested {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::CASE_INSENSITIVE_IDENT) => {
+                    options.case_insensitive = true;
+                    continue;
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::USE_SERDE_ATTRS_IDENT) => {
+                    options.use_serde_attrs = true;
+                    continue;
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) => nv,
+                _ => {
+                    return Err(quote_spanned! {
+                        nested.span() => compile_error!("Expected case_insensitive, use_serde_attrs, or an option of the form key = value");
+                    })
+                }
+            };
+            if nv.path.is_ident(Self::SKIP_IDENT) {
+                match &nv.lit {
+                    syn::Lit::Str(s) => options.skip.push(s.clone()),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing a field name");
+                        })
+                    }
+                }
+            } else if nv.path.is_ident(Self::RENAME_ALL_IDENT) {
+                options.rename_all = Some(RenameRule::try_parse(&nv.lit)?);
+            } else if nv.path.is_ident(Self::ON_SET_IDENT) {
+                options.on_set = Some(function_path(&nv.lit)?);
+            } else {
+                return Err(quote_spanned! {
+                    nv.path.span() => compile_error!("Expected one of: skip, rename_all, case_insensitive, use_serde_attrs, on_set");
+                });
+            }
+        }
+        Ok(options)
+    }
+
+    /// Returns the fields of the given type which are accessible along with
+    /// their Lua keys, or an error if a skipped field does not exist
+    fn accessible_fields<'a>(
+        &self,
+        ast: &syn::DeriveInput,
+        fields: &[&'a syn::Field],
+    ) -> Result<Vec<(String, &'a syn::Field)>, TokenStream2> {
+        let is_named = |f: &syn::Field, name: &syn::LitStr| f.ident.as_ref().unwrap() == &name.value();
+        if let Some(unknown) = self.skip.iter().find(|s| !fields.iter().any(|f| is_named(f, s))) {
+            let message = format!("No field named `{}`", unknown.value());
+            return Err(quote_spanned! {
+                unknown.span() => compile_error!(#message);
             });
         }
+        // Rules given to Index itself take precedence over serde's
+        let mut rename_all = self.rename_all;
+        if self.use_serde_attrs && rename_all.is_none() {
+            for nv in serde_name_values(&ast.attrs) {
+                if nv.path.is_ident(Self::RENAME_ALL_IDENT) {
+                    rename_all = Some(RenameRule::try_parse(&nv.lit)?);
+                }
+            }
+        }
+        let mut accessible = Vec::new();
+        for f in fields {
+            if self.skip.iter().any(|s| is_named(f, s)) || is_marker(&f.ty) || FieldOptions::try_parse(f)?.flatten {
+                continue;
+            }
+            let ident = f.ident.as_ref().unwrap().to_string();
+            let mut key = match rename_all {
+                Some(rule) => rule.apply_to_words(&split_words(&ident)),
+                None => ident,
+            };
+            if self.use_serde_attrs {
+                if serde_paths(&f.attrs).any(|p| p.is_ident(SERDE_SKIP_IDENT)) {
+                    continue;
+                }
+                for nv in serde_name_values(&f.attrs).filter(|nv| nv.path.is_ident(SERDE_RENAME_IDENT)) {
+                    if let syn::Lit::Str(s) = &nv.lit {
+                        key = s.value();
+                    }
+                }
+            }
+            accessible.push((key, *f));
+        }
+        if self.case_insensitive {
+            for (i, (key, field)) in accessible.iter().enumerate() {
+                if let Some((_, other)) = accessible[..i].iter().find(|(k, _)| k.to_lowercase() == key.to_lowercase()) {
+                    let message = format!(
+                        "Fields `{}` and `{}` have the same key when case is ignored",
+                        other.ident.as_ref().unwrap(),
+                        field.ident.as_ref().unwrap()
+                    );
+                    return Err(quote_spanned! {
+                        field.span() => compile_error!(#message);
+                    });
+                }
+            }
+        }
+        Ok(accessible)
+    }
+
+    /// Returns the fields of the given type marked with `#[rudeboy(flatten)]`
+    /// which are not skipped, whose own fields are looked up by keys matching
+    /// no other field
+    fn flattened_fields<'a>(&self, fields: &[&'a syn::Field]) -> Result<Vec<&'a syn::Field>, TokenStream2> {
+        let mut flattened = Vec::new();
+        for field in fields {
+            let options = FieldOptions::try_parse(field)?;
+            if !options.flatten {
+                continue;
+            }
+            if options.delegate || options.readonly || options.validate.is_some() {
+                return Err(quote_spanned! {
+                    field.span() => compile_error!("flatten cannot be combined with delegate, readonly, or validate");
+                });
+            }
+            let ident = field.ident.as_ref().unwrap();
+            if !options.skip && !self.skip.iter().any(|s| ident == &s.value()) {
+                flattened.push(*field);
+            }
+        }
+        Ok(flattened)
+    }
+
+    /// Returns the string a key is matched with, which is lowercased when keys
+    /// are matched regardless of case
+    fn match_key(&self, key: &str) -> String {
+        if self.case_insensitive {
+            key.to_lowercase()
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Returns the statement binding `index_key` to the index being looked up,
+    /// normalized in the same way as the keys it is matched against
+    fn bind_index_key(&self) -> TokenStream2 {
+        if self.case_insensitive {
+            quote!(let index_key = index_str.to_lowercase();)
+        } else {
+            quote!(let index_key = index_str;)
+        }
     }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 enum MetaMethod {
     Add,
     Eq,
-    Index,
+    Index(FieldAccessOptions),
+    NewIndex(FieldAccessOptions),
     Sub,
     Mul,
     Div,
     Mod,
+    /// Divides and rounds down with the named method, or else with `/`
+    IDiv(Option<syn::Ident>),
+    /// Raises the value to a power with `num_traits::Pow`, or with the `pow`
+    /// method or the named one
+    Pow(Option<syn::Ident>),
     Unm,
     BAnd,
     BOr,
@@ -46,16 +1037,33 @@ enum MetaMethod {
     Shr,
     Lt,
     Le,
+    /// Iterates over the value with `IntoIterator`, or over the iterator
+    /// returned by the named method
+    Pairs(Option<syn::Ident>),
+    /// Registers a NewIndex raising an error for any assignment
+    Freeze,
+    /// Registers an Index and a NewIndex looking up and inserting keys in the
+    /// map wrapped by a newtype
+    MapIndex,
+    /// Registers an integer Index and NewIndex, a Len, and a Pairs stepping
+    /// through the elements of the list wrapped by a newtype
+    Collection,
+    /// Converts the value with `Display`, or interpolates its fields into the
+    /// given format
+    ToString(Option<syn::LitStr>),
 }
 
 impl MetaMethod {
     const ADD_IDENT: &'static str = "Add";
     const EQUALS_IDENT: &'static str = "Eq";
     const INDEX_IDENT: &'static str = "Index";
+    const NEW_INDEX_IDENT: &'static str = "NewIndex";
     const SUB_IDENT: &'static str = "Sub";
     const MUL_IDENT: &'static str = "Mul";
     const DIV_IDENT: &'static str = "Div";
     const MOD_IDENT: &'static str = "Mod";
+    const IDIV_IDENT: &'static str = "IDiv";
+    const POW_IDENT: &'static str = "Pow";
     const UNM_IDENT: &'static str = "Unm";
     const BAND_IDENT: &'static str = "BAnd";
     const BOR_IDENT: &'static str = "BOr";
@@ -65,165 +1073,1568 @@ impl MetaMethod {
     const SHR_IDENT: &'static str = "Shr";
     const LT_IDENT: &'static str = "Lt";
     const LE_IDENT: &'static str = "Le";
+    const PAIRS_IDENT: &'static str = "Pairs";
+    const FREEZE_IDENT: &'static str = "Freeze";
+    const MAP_INDEX_IDENT: &'static str = "MapIndex";
+    const COLLECTION_IDENT: &'static str = "Collection";
+    const TO_STRING_IDENT: &'static str = "ToString";
+
+    /// Returns the identifier used to specify this metamethod
+    fn ident(&self) -> &'static str {
+        match self {
+            MetaMethod::Add => Self::ADD_IDENT,
+            MetaMethod::Eq => Self::EQUALS_IDENT,
+            MetaMethod::Index(_) => Self::INDEX_IDENT,
+            MetaMethod::NewIndex(_) => Self::NEW_INDEX_IDENT,
+            MetaMethod::Sub => Self::SUB_IDENT,
+            MetaMethod::Mul => Self::MUL_IDENT,
+            MetaMethod::Div => Self::DIV_IDENT,
+            MetaMethod::Mod => Self::MOD_IDENT,
+            MetaMethod::IDiv(_) => Self::IDIV_IDENT,
+            MetaMethod::Pow(_) => Self::POW_IDENT,
+            MetaMethod::Unm => Self::UNM_IDENT,
+            MetaMethod::BAnd => Self::BAND_IDENT,
+            MetaMethod::BOr => Self::BOR_IDENT,
+            MetaMethod::BXor => Self::BXOR_IDENT,
+            MetaMethod::BNot => Self::BNOT_IDENT,
+            MetaMethod::Shl => Self::SHL_IDENT,
+            MetaMethod::Shr => Self::SHR_IDENT,
+            MetaMethod::Lt => Self::LT_IDENT,
+            MetaMethod::Le => Self::LE_IDENT,
+            MetaMethod::Pairs(_) => Self::PAIRS_IDENT,
+            MetaMethod::Freeze => Self::FREEZE_IDENT,
+            MetaMethod::MapIndex => Self::MAP_INDEX_IDENT,
+            MetaMethod::Collection => Self::COLLECTION_IDENT,
+            MetaMethod::ToString(_) => Self::TO_STRING_IDENT,
+        }
+    }
+
+    /// Whether the path names the metamethod with the given identifier. Names are
+    /// matched case-insensitively, with or without the leading `__` used by
+    /// Lua, so that e.g. `Add`, `add`, and `__add` are all accepted
+    fn is_named(path: &syn::Path, ident: &str) -> bool {
+        match path.get_ident() {
+            Some(i) => {
+                let name = i.to_string();
+                let name = name.strip_prefix("__").unwrap_or(&name);
+                name.eq_ignore_ascii_case(ident)
+            }
+            None => false,
+        }
+    }
 
     fn try_parse(path: &syn::Path) -> Result<MetaMethod, TokenStream2> {
-        if path.is_ident(Self::ADD_IDENT) {
+        if Self::is_named(path, Self::ADD_IDENT) {
             Ok(MetaMethod::Add)
-        } else if path.is_ident(Self::EQUALS_IDENT) {
+        } else if Self::is_named(path, Self::EQUALS_IDENT) {
             Ok(MetaMethod::Eq)
-        } else if path.is_ident(Self::INDEX_IDENT) {
-            Ok(MetaMethod::Index)
-        } else if path.is_ident(Self::SUB_IDENT) {
+        } else if Self::is_named(path, Self::INDEX_IDENT) {
+            Ok(MetaMethod::Index(FieldAccessOptions::default()))
+        } else if Self::is_named(path, Self::NEW_INDEX_IDENT) {
+            Ok(MetaMethod::NewIndex(FieldAccessOptions::default()))
+        } else if Self::is_named(path, Self::SUB_IDENT) {
             Ok(MetaMethod::Sub)
-        } else if path.is_ident(Self::MUL_IDENT) {
+        } else if Self::is_named(path, Self::MUL_IDENT) {
             Ok(MetaMethod::Mul)
-        } else if path.is_ident(Self::DIV_IDENT) {
+        } else if Self::is_named(path, Self::DIV_IDENT) {
             Ok(MetaMethod::Div)
-        } else if path.is_ident(Self::MOD_IDENT) {
+        } else if Self::is_named(path, Self::MOD_IDENT) {
             Ok(MetaMethod::Mod)
-        } else if path.is_ident(Self::UNM_IDENT) {
+        } else if Self::is_named(path, Self::IDIV_IDENT) {
+            Ok(MetaMethod::IDiv(None))
+        } else if Self::is_named(path, Self::POW_IDENT) {
+            Ok(MetaMethod::Pow(None))
+        } else if Self::is_named(path, Self::UNM_IDENT) {
             Ok(MetaMethod::Unm)
-        } else if path.is_ident(Self::BAND_IDENT) {
+        } else if Self::is_named(path, Self::BAND_IDENT) {
             Ok(MetaMethod::BAnd)
-        } else if path.is_ident(Self::BOR_IDENT) {
+        } else if Self::is_named(path, Self::BOR_IDENT) {
             Ok(MetaMethod::BOr)
-        } else if path.is_ident(Self::BXOR_IDENT) {
+        } else if Self::is_named(path, Self::BXOR_IDENT) {
             Ok(MetaMethod::BXor)
-        } else if path.is_ident(Self::BNOT_IDENT) {
+        } else if Self::is_named(path, Self::BNOT_IDENT) {
             Ok(MetaMethod::BNot)
-        } else if path.is_ident(Self::SHL_IDENT) {
+        } else if Self::is_named(path, Self::SHL_IDENT) {
             Ok(MetaMethod::Shl)
-        } else if path.is_ident(Self::SHR_IDENT) {
+        } else if Self::is_named(path, Self::SHR_IDENT) {
             Ok(MetaMethod::Shr)
-        } else if path.is_ident(Self::LT_IDENT) {
+        } else if Self::is_named(path, Self::LT_IDENT) {
             Ok(MetaMethod::Lt)
-        } else if path.is_ident(Self::LE_IDENT) {
+        } else if Self::is_named(path, Self::LE_IDENT) {
             Ok(MetaMethod::Le)
+        } else if Self::is_named(path, Self::PAIRS_IDENT) {
+            Ok(MetaMethod::Pairs(None))
+        } else if Self::is_named(path, Self::FREEZE_IDENT) {
+            Ok(MetaMethod::Freeze)
+        } else if Self::is_named(path, Self::MAP_INDEX_IDENT) {
+            Ok(MetaMethod::MapIndex)
+        } else if Self::is_named(path, Self::COLLECTION_IDENT) {
+            Ok(MetaMethod::Collection)
+        } else if Self::is_named(path, Self::TO_STRING_IDENT) {
+            Ok(MetaMethod::ToString(None))
         } else {
             Err(quote_spanned! {
                 path.span() => compile_error!("Expected a valid metamethod identifier");
+            })
+        }
+    }
+
+    /// Parses a metamethod given with options, e.g. `Index(skip = "secret")` or
+    /// `Pairs(iter)`
+    fn try_parse_list(list: &syn::MetaList) -> Result<MetaMethod, TokenStream2> {
+        use syn::{Meta, NestedMeta};
+        let mut metamethod = Self::try_parse(&list.path)?;
+        let binary = metamethod.is_binary_operator();
+        match &mut metamethod {
+            MetaMethod::Index(options) => {
+                *options = FieldAccessOptions::try_parse(list)?;
+                if let Some(on_set) = &options.on_set {
+                    return Err(quote_spanned! {
+                        on_set.span() => compile_error!("on_set is only supported by NewIndex");
+                    });
+                }
             }
-            .into())
-        }
-    }
-    
-    fn get_method(&self, ast: &syn::DeriveInput) -> TokenStream2 {
-        match &self {
-            MetaMethod::Add => operator_method(quote!(generate_add), quote!(Add), quote!(+)),
-            MetaMethod::Eq =>
-                operator_method(quote!(generate_eq), quote!(Eq), quote!(==)),
-            MetaMethod::Index => {
-                let struct_ =
-                    match &ast.data {
-                        syn::Data::Struct(s) => s,
-                        _ => return quote_spanned! {
-                            ast.span() => compile_error!("Index metamethod can only be applied to structs");
-                        }
-                        .into(),
-                    };
+            MetaMethod::NewIndex(options) => *options = FieldAccessOptions::try_parse(list)?,
+            MetaMethod::ToString(format) => {
+                *format = match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                    [NestedMeta::Meta(Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    }))] if path.is_ident(FORMAT_IDENT) => Some(lit.clone()),
+                    _ => {
+                        return Err(quote_spanned! {
+                            list.span() => compile_error!("Expected a format string, e.g. ToString(format = \"{name} ({id})\")");
+                        })
+                    }
+                }
+            }
+            MetaMethod::Pow(method) | MetaMethod::IDiv(method) => *method = operator_method_option(list)?,
+            MetaMethod::Pairs(method) => {
+                *method = match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                    [NestedMeta::Meta(Meta::Path(p))] if p.get_ident().is_some() => p.get_ident().cloned(),
+                    _ => {
+                        return Err(quote_spanned! {
+                            list.span() => compile_error!("Expected the name of a method, e.g. Pairs(iter)");
+                        })
+                    }
+                }
+            }
+            // The operand types of binary operators and the fields compared by
+            // Eq are collected separately
+            _ if binary => (),
+            MetaMethod::Eq => (),
+            _ => {
+                let message = format!("{} does not take any options", metamethod.ident());
+                return Err(quote_spanned! {
+                    list.span() => compile_error!(#message);
+                });
+            }
+        }
+        Ok(metamethod)
+    }
 
-                let fields = &struct_.fields;
+    /// Whether this metamethod is an arithmetic or bitwise operator taking a
+    /// right hand operand, whose types may be given
+    fn is_binary_operator(&self) -> bool {
+        matches!(
+            self,
+            MetaMethod::Add
+                | MetaMethod::Sub
+                | MetaMethod::Mul
+                | MetaMethod::Div
+                | MetaMethod::Mod
+                | MetaMethod::IDiv(_)
+                | MetaMethod::Pow(_)
+                | MetaMethod::BAnd
+                | MetaMethod::BOr
+                | MetaMethod::BXor
+                | MetaMethod::Shl
+                | MetaMethod::Shr
+        )
+    }
 
-                let mut bad_struct = true;
-                if let syn::Fields::Named(_) = fields {
-                    bad_struct = false;
-                }
+    /// Returns the key this metamethod is registered under, e.g. `__add`, or the
+    /// name of the method standing in for it under Lua 5.1
+    fn lua_name(&self, options: &MetaMethodsOptions) -> String {
+        if *self == MetaMethod::Freeze {
+            "__newindex".to_string()
+        } else if *self == MetaMethod::MapIndex || *self == MetaMethod::Collection {
+            "__index".to_string()
+        } else if options.lua51 && self.is_lua53_operator() {
+            self.ident().to_lowercase()
+        } else {
+            format!("__{}", self.ident().to_lowercase())
+        }
+    }
+
+    /// Whether this metamethod is floor division or a bitwise operator, which
+    /// were added in Lua 5.3 and which Lua 5.1 lacks
+    fn is_lua53_operator(&self) -> bool {
+        matches!(
+            self,
+            MetaMethod::IDiv(_)
+                | MetaMethod::BAnd
+                | MetaMethod::BOr
+                | MetaMethod::BXor
+                | MetaMethod::BNot
+                | MetaMethod::Shl
+                | MetaMethod::Shr
+        )
+    }
+
+    /// Whether this metamethod is an arithmetic, bitwise, or comparison operator
+    fn is_operator(&self) -> bool {
+        self.is_binary_operator() || matches!(self, MetaMethod::Unm | MetaMethod::BNot | MetaMethod::Eq | MetaMethod::Lt | MetaMethod::Le)
+    }
+
+    /// Returns the registration of a method named after this metamethod, e.g.
+    /// `add`, as used under Lua 5.1 and by the OperatorMethods parameter
+    fn named_registration(&self) -> Registration {
+        Registration {
+            method: quote!(add_method),
+            function: quote!(add_function),
+            key: self.ident().to_lowercase().into_token_stream(),
+        }
+    }
+
+    /// Returns where this metamethod is registered. Under Lua 5.1, which has no
+    /// floor division or bitwise operators, their metamethods are registered as
+    /// methods named after them instead, e.g. `a:band(b)`
+    fn registration(&self, options: &MetaMethodsOptions) -> Registration {
+        let rlua = &options.paths.rlua;
+        if options.lua51 && self.is_lua53_operator() {
+            self.named_registration()
+        } else {
+            let variant = syn::Ident::new(self.ident(), proc_macro2::Span::call_site());
+            Registration {
+                method: quote!(add_meta_method),
+                function: quote!(add_meta_function),
+                key: quote!(#rlua::MetaMethod::#variant),
+            }
+        }
+    }
+
+    /// Returns the `std::ops` trait implementing this metamethod, if it is an
+    /// arithmetic or bitwise operator. Pow is implemented by `num_traits::Pow`
+    /// with the num_traits feature, unless it names a method
+    fn ops_trait(&self) -> Option<TokenStream2> {
+        Some(match self {
+            MetaMethod::Add => quote!(::std::ops::Add),
+            MetaMethod::Sub => quote!(::std::ops::Sub),
+            MetaMethod::Mul => quote!(::std::ops::Mul),
+            MetaMethod::Div => quote!(::std::ops::Div),
+            MetaMethod::Mod => quote!(::std::ops::Rem),
+            MetaMethod::Pow(None) if cfg!(feature = "num_traits") => quote!(::num_traits::Pow),
+            MetaMethod::Unm => quote!(::std::ops::Neg),
+            MetaMethod::BAnd => quote!(::std::ops::BitAnd),
+            MetaMethod::BOr => quote!(::std::ops::BitOr),
+            MetaMethod::BXor => quote!(::std::ops::BitXor),
+            MetaMethod::BNot => quote!(::std::ops::Not),
+            MetaMethod::Shl => quote!(::std::ops::Shl),
+            MetaMethod::Shr => quote!(::std::ops::Shr),
+            _ => return None,
+        })
+    }
+
+    /// Returns how Pow or IDiv is applied to its operands: by the method it
+    /// names, or else by `num_traits::Pow` with the num_traits feature or the
+    /// `pow` method for Pow
+    fn method_operator(&self) -> Option<Operator> {
+        Some(match self {
+            MetaMethod::Pow(Some(method)) | MetaMethod::IDiv(Some(method)) => Operator::Method(method.clone()),
+            MetaMethod::Pow(None) if cfg!(feature = "num_traits") => Operator::Function(quote!(::num_traits::Pow::pow)),
+            MetaMethod::Pow(None) => Operator::Method(quote::format_ident!("pow")),
+            _ => return None,
+        })
+    }
 
-                if fields.is_empty() {
-                    bad_struct = true;
+    /// Returns a static assertion that the type implements the `std::ops` trait
+    /// of this operator, spanned at the given span where the metamethod was
+    /// requested, so that a missing impl is reported there rather than inside
+    /// the generated method. Generic types are bounded by the trait instead
+    fn operator_assertion(&self, ast: &syn::DeriveInput, span: Span, options: &MetaMethodsOptions) -> Option<TokenStream2> {
+        let ops_trait = self.ops_trait()?;
+        if !ast.generics.params.is_empty() {
+            return None;
+        }
+        let name = &ast.ident;
+        let assert = quote::format_ident!("assert_{}", self.ident().to_lowercase(), span = span);
+        let newtype = match &ast.data {
+            syn::Data::Struct(syn::DataStruct {
+                fields: syn::Fields::Unnamed(fields),
+                ..
+            }) if options.newtype && fields.unnamed.len() == 1 => Some(&fields.unnamed[0].ty),
+            _ => None,
+        };
+        let (function, args) = if let Some(inner) = newtype {
+            let ops_trait = if self.is_binary_operator() { quote!(#ops_trait<T, Output = T>) } else { quote!(#ops_trait<Output = T>) };
+            (
+                quote!(fn #assert<T: ::std::clone::Clone + #ops_trait>() {}),
+                vec![quote!(#inner)],
+            )
+        } else if !self.is_binary_operator() {
+            let function = if options.by_ref {
+                quote!(fn #assert<T: ?Sized>() where for<'rudeboy> &'rudeboy T: #ops_trait {})
+            } else {
+                quote!(fn #assert<T: ::std::marker::Copy + #ops_trait>() {})
+            };
+            (function, vec![quote!(#name)])
+        } else {
+            let function = if options.by_ref {
+                quote!(fn #assert<T: ?Sized, R>() where for<'rudeboy> &'rudeboy T: #ops_trait<&'rudeboy R> {})
+            } else {
+                quote!(fn #assert<T: ::std::marker::Copy + #ops_trait<R>, R>() {})
+            };
+            let args = match options.operands.get(self) {
+                Some(operands) => operands
+                    .iter()
+                    .map(|ty| match type_string(ty).as_str() {
+                        "Self" => quote!(#name, #name),
+                        _ => quote!(#name, #ty),
+                    })
+                    .collect(),
+                None => vec![quote!(#name, #name)],
+            };
+            (function, args)
+        };
+        let args = args.into_iter().map(|args| respan(args, span));
+        Some(quote_spanned! {
+            span => const _: () = {
+                #function
+                #( let _ = #assert::<#args>; )*
+            };
+        })
+    }
+
+    /// Returns the bounds this metamethod places on a generic type, so that
+    /// its impl of `RudeboyMetaMethods` only requires what the metamethods it
+    /// was given use, e.g. `Self: Add` only if Add was given
+    fn bounds(&self, ast: &syn::DeriveInput, options: &MetaMethodsOptions) -> Result<Vec<TokenStream2>, TokenStream2> {
+        let to_lua = options.paths.trait_to_lua();
+        let from_lua = options.paths.trait_from_lua();
+        let rlua = &options.paths.rlua;
+        if options.newtype {
+            if let syn::Data::Struct(syn::DataStruct {
+                fields: syn::Fields::Unnamed(fields),
+                ..
+            }) = &ast.data
+            {
+                let inner = &fields.unnamed[0].ty;
+                let operator = match (self.ops_trait(), self) {
+                    (Some(ops_trait), _) if self.is_binary_operator() => quote!(#ops_trait<#inner, Output = #inner>),
+                    (Some(ops_trait), _) => quote!(#ops_trait<Output = #inner>),
+                    (None, MetaMethod::Eq) => quote!(::std::cmp::PartialEq),
+                    (None, MetaMethod::Lt | MetaMethod::Le) => quote!(::std::cmp::PartialOrd),
+                    _ => quote!(),
+                };
+                if !operator.is_empty() {
+                    return Ok(vec![
+                        quote!(#inner: ::std::clone::Clone + #operator),
+                        quote!(for<'lua> #inner: #from_lua<'lua>),
+                        quote!(for<'lua> Self: #to_lua<'lua>),
+                    ]);
                 }
+            }
+        }
+        let converted = |ty: &TokenStream2| quote!(for<'lua> #ty: #to_lua<'lua>);
+        Ok(match self {
+            MetaMethod::Unm | MetaMethod::BNot => {
+                let ops_trait = self.ops_trait().unwrap();
+                if options.by_ref {
+                    vec![
+                        quote!(for<'rudeboy> &'rudeboy Self: #ops_trait),
+                        quote!(for<'rudeboy, 'lua> <&'rudeboy Self as #ops_trait>::Output: #to_lua<'lua>),
+                    ]
+                } else {
+                    vec![
+                        quote!(Self: ::std::marker::Copy + #ops_trait),
+                        converted(&quote!(<Self as #ops_trait>::Output)),
+                    ]
+                }
+            }
+            // A method applying Pow or IDiv cannot be bounded, being inherent
+            MetaMethod::IDiv(method) | MetaMethod::Pow(method) if self.ops_trait().is_none() => {
+                let (span, message) = match method {
+                    Some(method) => (method.span(), format!("{} cannot name a method on a generic type", self.ident())),
+                    None => (ast.ident.span(), "Pow requires the num_traits feature on a generic type".to_string()),
+                };
+                return Err(quote_spanned! {
+                    span => compile_error!(#message);
+                });
+            }
+            _ if self.is_binary_operator() => {
+                let ops_trait = self.ops_trait().unwrap();
+                let self_ty: syn::Type = syn::parse_quote!(Self);
+                let operands = options.operands.get(self).map_or_else(|| vec![self_ty], Clone::clone);
+                let mut bounds = Vec::new();
+                for ty in &operands {
+                    let is_self = type_string(ty) == "Self";
+                    if !is_self {
+                        bounds.push(quote!(for<'lua> #ty: #from_lua<'lua>));
+                    }
+                    if options.by_ref {
+                        bounds.push(quote!(for<'rudeboy> &'rudeboy Self: #ops_trait<&'rudeboy #ty>));
+                        bounds.push(quote!(
+                            for<'rudeboy, 'lua> <&'rudeboy Self as #ops_trait<&'rudeboy #ty>>::Output: #to_lua<'lua>
+                        ));
+                    } else {
+                        bounds.push(quote!(Self: ::std::marker::Copy + #ops_trait<#ty>));
+                        bounds.push(converted(&quote!(<Self as #ops_trait<#ty>>::Output)));
+                    }
+                }
+                bounds
+            }
+            MetaMethod::Eq => {
+                let mut bounds: Vec<_> = match &options.eq_fields {
+                    Some(eq_fields) => self
+                        .named_fields(ast)?
+                        .into_iter()
+                        .filter(|f| eq_fields.iter().any(|e| f.ident.as_ref() == Some(e)))
+                        .map(|f| {
+                            let ty = &f.ty;
+                            quote!(#ty: ::std::cmp::PartialEq)
+                        })
+                        .collect(),
+                    None if options.by_ref || !options.eq_types.is_empty() => vec![quote!(Self: ::std::cmp::PartialEq)],
+                    None => vec![quote!(Self: ::std::cmp::PartialEq + ::std::clone::Clone)],
+                };
+                for ty in options.eq_types.iter().filter(|ty| type_string(ty) != "Self") {
+                    bounds.push(quote!(Self: ::std::cmp::PartialEq<#ty>));
+                    bounds.push(quote!(#ty: #rlua::UserData + 'static));
+                }
+                bounds
+            }
+            MetaMethod::Lt | MetaMethod::Le if options.by_ref => vec![quote!(Self: ::std::cmp::PartialOrd)],
+            MetaMethod::Lt | MetaMethod::Le => vec![quote!(Self: ::std::cmp::PartialOrd + ::std::clone::Clone)],
+            MetaMethod::Index(access) => {
+                let fields = self.named_fields(ast)?;
+                let mut tys: Vec<_> = access
+                    .accessible_fields(ast, &fields)?
+                    .into_iter()
+                    .map(|(_, f)| smart_pointer_target(&f.ty).unwrap_or(&f.ty))
+                    .filter(|ty| ecosystem::lua_type(ty).is_none())
+                    .collect();
+                for field in &fields {
+                    if FieldOptions::try_parse(field)?.delegate {
+                        tys.push(&field.ty);
+                    }
+                }
+                tys.into_iter()
+                    .flat_map(|ty| vec![quote!(#ty: ::std::clone::Clone), converted(&quote!(#ty))])
+                    .collect()
+            }
+            MetaMethod::NewIndex(access) => {
+                let mut bounds = Vec::new();
+                for (_, field) in access.accessible_fields(ast, &self.named_fields(ast)?)? {
+                    let field_options = FieldOptions::try_parse(field)?;
+                    let ty = smart_pointer_target(&field.ty).unwrap_or(&field.ty);
+                    if !field_options.skip && !field_options.readonly && ecosystem::lua_type(ty).is_none() {
+                        bounds.push(quote!(for<'lua> #ty: #from_lua<'lua>));
+                    }
+                }
+                bounds
+            }
+            MetaMethod::Pairs(None) => vec![
+                quote!(Self: ::std::clone::Clone + ::std::iter::IntoIterator),
+                converted(&quote!(<Self as ::std::iter::IntoIterator>::Item)),
+            ],
+            MetaMethod::MapIndex => {
+                let MapTypes { key, value, key_bounds } = map_types(ast)?;
+                let mut bounds = key_bounds;
+                bounds.push(quote!(for<'lua> #key: #from_lua<'lua>));
+                if ecosystem::lua_type(value).is_none() {
+                    bounds.push(quote!(#value: ::std::clone::Clone));
+                    bounds.push(converted(&quote!(#value)));
+                    bounds.push(quote!(for<'lua> #value: #from_lua<'lua>));
+                }
+                bounds
+            }
+            MetaMethod::Collection => {
+                let element = collection_element(ast)?;
+                if ecosystem::lua_type(element).is_some() {
+                    Vec::new()
+                } else {
+                    vec![
+                        quote!(#element: ::std::clone::Clone),
+                        converted(&quote!(#element)),
+                        quote!(for<'lua> #element: #from_lua<'lua>),
+                    ]
+                }
+            }
+            MetaMethod::ToString(None) => vec![quote!(Self: ::std::fmt::Display)],
+            MetaMethod::ToString(Some(format)) => tostring_format(format, ast)?
+                .1
+                .into_iter()
+                .map(|FormatField { ty, spec, .. }| match spec.ends_with('?') {
+                    true => quote!(#ty: ::std::fmt::Debug),
+                    false => quote!(#ty: ::std::fmt::Display),
+                })
+                .collect(),
+            // The item type of an iterator returned by a method is unknown
+            MetaMethod::Pairs(Some(method)) => {
+                return Err(quote_spanned! {
+                    method.span() => compile_error!("Pairs cannot name a method on a generic type");
+                })
+            }
+            _ => Vec::new(),
+        })
+    }
+
+    /// Returns the named fields of the given struct, or an error if this
+    /// metamethod has been applied to anything else
+    fn named_fields<'a>(&self, ast: &'a syn::DeriveInput) -> Result<Vec<&'a syn::Field>, TokenStream2> {
+        let struct_ = match &ast.data {
+            syn::Data::Struct(s) => s,
+            _ => {
+                let message = format!("{} metamethod can only be applied to structs", self.ident());
+                return Err(quote_spanned! {
+                    ast.span() => compile_error!(#message);
+                });
+            }
+        };
+
+        let fields = &struct_.fields;
+        let bad_struct = !matches!(fields, syn::Fields::Named(_)) || fields.is_empty();
+        if bad_struct {
+            let message = format!(
+                "{} metamethod can only be applied to structs with named fields",
+                self.ident()
+            );
+            return Err(quote_spanned! {
+                fields.span() => compile_error!(#message);
+            });
+        }
+
+        Ok(fields.iter().collect())
+    }
 
-                if bad_struct {
-                    return quote_spanned! {
-                        fields.span() => compile_error!("Index metamethod can only be applied to structs with named fields");
+    /// Generates the associated function reading (for Index) or assigning (for
+    /// NewIndex) the field with a given key, which this type's Index and
+    /// NewIndex call, as do those of types flattening it, or None for other
+    /// metamethods
+    fn field_accessor(&self, ast: &syn::DeriveInput, options: &MetaMethodsOptions) -> Result<Option<TokenStream2>, TokenStream2> {
+        let rlua = &options.paths.rlua;
+        Ok(match self {
+            MetaMethod::Index(access) => {
+                let fields = self.named_fields(ast)?;
+                let (keys, values): (Vec<_>, Vec<_>) = access
+                    .accessible_fields(ast, &fields)?
+                    .into_iter()
+                    .map(|(key, f)| (access.match_key(&key), to_lua_field(f, options)))
+                    .unzip();
+                let flattened = flattened_gets(&access.flattened_fields(&fields)?);
+                let bind_index_key = access.bind_index_key();
+                Some(quote! {
+                    #[doc(hidden)]
+                    #[allow(unused_variables)]
+                    pub fn rudeboy_get_field<'lua>(
+                        data: &Self,
+                        ctx: #rlua::Context<'lua>,
+                        index_str: &str,
+                    ) -> #rlua::Result<Option<#rlua::Value<'lua>>> {
+                        #bind_index_key
+                        #(
+                            if index_key == #keys {
+                                return #values.map(Some);
+                            }
+                        )*
+                        #(
+                            if let Some(value) = #flattened {
+                                return Ok(Some(value));
+                            }
+                        )*
+                        Ok(None)
+                    }
+                })
+            }
+            MetaMethod::NewIndex(access) => {
+                let fields = self.named_fields(ast)?;
+                let mut keys = Vec::new();
+                let mut assignments = Vec::new();
+                for (key, field) in access.accessible_fields(ast, &fields)? {
+                    let field_options = FieldOptions::try_parse(field)?;
+                    if field_options.skip {
+                        continue;
                     }
-                    .into();
+                    assignments.push(assign_field(&key, field, &field_options, access, options));
+                    keys.push(access.match_key(&key));
+                }
+                let flattened = flattened_sets(&access.flattened_fields(&fields)?);
+                let bind_index_key = access.bind_index_key();
+                let on_set = access.on_set.as_ref().map(|on_set| {
+                    quote_spanned!(on_set.span() => #on_set(data, index_str);)
+                });
+                Some(quote! {
+                    #[doc(hidden)]
+                    #[allow(unused_variables)]
+                    pub fn rudeboy_set_field<'lua>(
+                        data: &mut Self,
+                        ctx: #rlua::Context<'lua>,
+                        index_str: &str,
+                        value: #rlua::Value<'lua>,
+                    ) -> #rlua::Result<bool> {
+                        #bind_index_key
+                        #(
+                            if index_key == #keys {
+                                return #assignments.map(|()| true);
+                            }
+                        )*
+                        #(
+                            if #flattened {
+                                #on_set
+                                return Ok(true);
+                            }
+                        )*
+                        Ok(false)
+                    }
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// Generates an operator or comparison metamethod for a newtype, which is
+    /// applied to the inner values of the operands, or None if this metamethod
+    /// is not an operator or comparison
+    fn get_newtype_method(
+        &self,
+        ast: &syn::DeriveInput,
+        prelude: &TokenStream2,
+        registration: &Registration,
+        span: Span,
+        paths: &CratePaths,
+    ) -> Result<Option<MetaMethodCode>, TokenStream2> {
+        let inner = match &ast.data {
+            syn::Data::Struct(syn::DataStruct {
+                fields: syn::Fields::Unnamed(fields),
+                ..
+            }) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => {
+                return Err(quote_spanned! {
+                    ast.span() => compile_error!("newtype can only be applied to structs with a single unnamed field");
+                })
+            }
+        };
+        let rlua = &paths.rlua;
+        let to_lua = paths.trait_to_lua();
+        let incomparable = quote! {{
+            return Err(#rlua::ExternalError::to_lua_err("values are not comparable"));
+        }};
+        let Registration { method, function, key } = registration;
+        let (hook, unary, result) = match self {
+            MetaMethod::Add => (quote!(generate_add), false, quote!(Self(lhs + rhs))),
+            MetaMethod::Sub => (quote!(generate_sub), false, quote!(Self(lhs - rhs))),
+            MetaMethod::Mul => (quote!(generate_mul), false, quote!(Self(lhs * rhs))),
+            MetaMethod::Div => (quote!(generate_div), false, quote!(Self(lhs / rhs))),
+            MetaMethod::Mod => (quote!(generate_mod), false, quote!(Self(lhs % rhs))),
+            MetaMethod::IDiv(_) | MetaMethod::Pow(_) => {
+                let result = self.method_operator().unwrap().apply(quote!(lhs), quote!(rhs), span);
+                (quote!(), false, quote!(Self(#result)))
+            }
+            MetaMethod::BAnd => (quote!(generate_band), false, quote!(Self(lhs & rhs))),
+            MetaMethod::BOr => (quote!(generate_bor), false, quote!(Self(lhs | rhs))),
+            MetaMethod::BXor => (quote!(generate_bxor), false, quote!(Self(lhs ^ rhs))),
+            MetaMethod::Shl => (quote!(generate_shl), false, quote!(Self(lhs << rhs))),
+            MetaMethod::Shr => (quote!(generate_shr), false, quote!(Self(lhs >> rhs))),
+            MetaMethod::Eq => (quote!(generate_eq), false, quote!(lhs == rhs)),
+            MetaMethod::Lt => (quote!(generate_lt), false, quote! {
+                match ::std::cmp::PartialOrd::partial_cmp(&lhs, &rhs) {
+                    Some(ordering) => ordering == ::std::cmp::Ordering::Less,
+                    None => #incomparable,
+                }
+            }),
+            MetaMethod::Le => (quote!(generate_le), false, quote! {
+                match ::std::cmp::PartialOrd::partial_cmp(&lhs, &rhs) {
+                    Some(ordering) => ordering != ::std::cmp::Ordering::Greater,
+                    None => #incomparable,
                 }
+            }),
+            MetaMethod::Unm => (quote!(generate_unm), true, quote!(Self(-operand))),
+            MetaMethod::BNot => (quote!(generate_bnot), true, quote!(Self(!operand))),
+            _ => return Ok(None),
+        };
+        // Binary operators are registered as functions, since Lua calls the
+        // metamethod of whichever operand has one, so the newtype may be on
+        // either side, e.g. `5 + meters`
+        let registration = if unary {
+            quote_spanned! {
+                span => methods.#method(#key, |ctx, data, ()| {
+                    #prelude
+                    let operand: #inner = ::std::clone::Clone::clone(&data.0);
+                    #to_lua::to_lua(#result, ctx)
+                });
+            }
+        } else {
+            let lhs = newtype_operand(quote!(lhs), inner, paths);
+            let rhs = newtype_operand(quote!(rhs), inner, paths);
+            quote_spanned! {
+                span => methods.#function(#key, |ctx, (lhs, rhs): (#rlua::Value, #rlua::Value)| {
+                    #prelude
+                    let lhs: #inner = #lhs;
+                    let rhs: #inner = #rhs;
+                    #to_lua::to_lua(#result, ctx)
+                });
+            }
+        };
+        // RudeboyMetaMethods has no hooks for IDiv and Pow
+        Ok(Some(match self {
+            MetaMethod::IDiv(_) | MetaMethod::Pow(_) => MetaMethodCode { hook: None, registration },
+            _ => MetaMethodCode::hooked(hook, registration),
+        }))
+    }
 
-                let field_names: Vec<_> =
-                    fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
-                quote! {
-                    fn generate_index<'lua, M: ::rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-                        methods.add_meta_method(::rlua::MetaMethod::Index, |ctx, data, index: ::rlua::String| {
-                            use ::rlua::ToLua;
+    /// Generates the code registering this metamethod, or if `named` is set, the
+    /// method named after it. Code which requires the type to implement a trait
+    /// is spanned at the given span, where the metamethod was requested, so that
+    /// a missing impl is reported there
+    fn get_method(&self, ast: &syn::DeriveInput, span: Span, options: &MetaMethodsOptions, named: bool) -> Result<MetaMethodCode, TokenStream2> {
+        let prelude = if options.trace {
+            let name = &ast.ident;
+            trace_span(&quote!(#name), self.ident(), &[])
+        } else {
+            quote!()
+        };
+        let registration = if named {
+            self.named_registration()
+        } else {
+            self.registration(options)
+        };
+        if options.newtype {
+            if let Some(code) = self.get_newtype_method(ast, &prelude, &registration, span, &options.paths)? {
+                return Ok(code);
+            }
+        }
+        let rlua = &options.paths.rlua;
+        let from_lua = options.paths.trait_from_lua();
+        let apply_operator = |operator: &Operator| match options.operands.get(self) {
+            Some(operands) => {
+                let self_name = ast.ident.to_string();
+                mixed_operator_method(&registration, operator, operands, &self_name, prelude.clone(), span, options)
+            }
+            None => operator_method(&registration, operator, prelude.clone(), span, options),
+        };
+        let binary_operator = |operator| apply_operator(&Operator::Infix(operator));
+        Ok(match &self {
+            MetaMethod::Add => MetaMethodCode::hooked(quote!(generate_add), binary_operator(quote!(+))),
+            MetaMethod::Eq if options.eq_fields.is_none() && options.eq_types.is_empty() => {
+                MetaMethodCode::hooked(quote!(generate_eq), operator_method(&registration, &Operator::Infix(quote!(==)), prelude, span, options))
+            }
+            MetaMethod::Eq => {
+                if let Some(eq_fields) = &options.eq_fields {
+                    let fields = self.named_fields(ast)?;
+                    if let Some(unknown) = eq_fields.iter().find(|f| !fields.iter().any(|field| field.ident.as_ref() == Some(f))) {
+                        let message = format!("No field named `{}`", unknown);
+                        return Err(quote_spanned! {
+                            unknown.span() => compile_error!(#message);
+                        });
+                    }
+                }
+                let fields = options.eq_fields.as_deref();
+                MetaMethodCode::hooked(quote!(generate_eq), userdata_eq_method(&registration, fields, &options.eq_types, prelude, span, options))
+            }
+            MetaMethod::Index(_) => {
+                let fields = self.named_fields(ast)?;
+                let mut delegate = None;
+                for field in &fields {
+                    if FieldOptions::try_parse(field)?.delegate {
+                        if delegate.is_some() {
+                            return Err(quote_spanned! {
+                                field.span() => compile_error!("Only one field may be marked as a delegate");
+                            });
+                        }
+                        delegate = Some(*field);
+                    }
+                }
+                // Keys which name no field may name a property read by a getter.
+                // Field values are returned along with any conversion error, so
+                // properties are too
+                let (property, delegate_property) = if options.properties {
+                    (
+                        quote! {
+                            if let Some(value) = Self::rudeboy_get_property(data, ctx, index_str)? {
+                                return Ok(Ok(value));
+                            }
+                        },
+                        quote! {
+                            if let Some(value) = Self::rudeboy_get_property(&data, ctx, index_str)? {
+                                return Ok(value);
+                            }
+                        },
+                    )
+                } else {
+                    (quote!(), quote!())
+                };
+                // Keys which name nothing else may name an extension, looked up
+                // before the delegate
+                let extension = extension_lookup(quote!(#rlua::Value::String(::std::clone::Clone::clone(&index))), options);
+                let delegate_extension = options.extensions.then(|| {
+                    quote! {
+                        let extension = #extension;
+                        if !matches!(extension, #rlua::Value::Nil) {
+                            return Ok(extension);
+                        }
+                    }
+                });
+                let lookup = cached_function("rudeboy_delegate_lookup", DELEGATE_LOOKUP, rlua);
+                let registration = match delegate.map(|field| to_lua_field(field, options)) {
+                    None => quote! {
+                        methods.add_meta_method(#rlua::MetaMethod::Index, |ctx, data, index: #rlua::String| {
+                            #prelude
                             let index_str = index.to_str()?;
-                            #(
-                                if index_str == stringify!(#field_names) {
-                                    Ok(data.#field_names.clone().to_lua(ctx))
-                                } else
-                            )*
-                            {
-                                use ::rlua::ExternalError;
-                                Err(format!("No such index: {}", index_str).to_lua_err())
+                            match Self::rudeboy_get_field(data, ctx, index_str) {
+                                Ok(Some(value)) => return Ok(Ok(value)),
+                                Ok(None) => (),
+                                Err(error) => return Ok(Err(error)),
+                            }
+                            #property
+                            match #extension {
+                                #rlua::Value::Nil => Err(#rlua::ExternalError::to_lua_err(format!("No such index: {}", index_str))),
+                                value => Ok(Ok(value)),
+                            }
+                        });
+                    },
+                    // Unknown keys are looked up on the delegate, which requires
+                    // the userdata itself so that methods found on the delegate
+                    // can be called with the delegate in place of the userdata
+                    Some(delegate) => quote! {
+                        methods.add_meta_function(#rlua::MetaMethod::Index, |ctx, (ud, index): (#rlua::AnyUserData, #rlua::String)| {
+                            #prelude
+                            let inner = {
+                                let data = ud.borrow::<Self>()?;
+                                let index_str = index.to_str()?;
+                                if let Some(value) = Self::rudeboy_get_field(&data, ctx, index_str)? {
+                                    return Ok(value);
+                                }
+                                #delegate_property
+                                #delegate_extension
+                                #delegate?
+                            };
+                            let lookup: #rlua::Function = #lookup?;
+                            lookup.call::<_, #rlua::Value>((ud, inner, index))
+                        });
+                    },
+                };
+                MetaMethodCode::hooked(quote!(generate_index), registration)
+            },
+            MetaMethod::NewIndex(access) => {
+                let on_set = access.on_set.as_ref().map(|on_set| {
+                    quote_spanned!(on_set.span() => #on_set(data, index_str);)
+                });
+                // Keys which name no field may name a property assigned by a setter
+                let property = if options.properties {
+                    quote! {
+                        if Self::rudeboy_set_property(data, ctx, index_str, value)? {
+                            #on_set
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    quote!()
+                };
+                // The trait has no hook for __newindex, and assignment mutates
+                // the receiver, so it is registered with add_meta_method_mut
+                MetaMethodCode {
+                    hook: None,
+                    registration: quote! {
+                        methods.add_meta_method_mut(#rlua::MetaMethod::NewIndex, |ctx, data, (index, value): (#rlua::String, #rlua::Value)| {
+                            #prelude
+                            let index_str = index.to_str()?;
+                            if Self::rudeboy_set_field(data, ctx, index_str, ::std::clone::Clone::clone(&value))? {
+                                return Ok(());
+                            }
+                            #property
+                            Err(#rlua::ExternalError::to_lua_err(format!("No such index: {}", index_str)))
+                        });
+                    },
+                }
+            },
+            MetaMethod::Freeze => MetaMethodCode {
+                hook: None,
+                registration: quote! {
+                    methods.add_meta_method(#rlua::MetaMethod::NewIndex, |_, _, _: (#rlua::Value, #rlua::Value)| -> #rlua::Result<()> {
+                        #prelude
+                        Err(#rlua::ExternalError::to_lua_err("attempt to modify read-only object"))
+                    });
+                },
+            },
+            MetaMethod::MapIndex => {
+                let MapTypes { key, value, .. } = map_types(ast)?;
+                let (to_lua_value, from_lua_value) = element_conversions(value, "map", ast, options);
+                let (get_property, set_property) = key_properties(options);
+                // The key is converted into the map's key type, so is kept for
+                // looking up an extension
+                let extension = extension_lookup(quote!(rudeboy_key), options);
+                let keep_key = options.extensions.then(|| quote!(let rudeboy_key = ::std::clone::Clone::clone(&key);));
+                // Missing keys read as nil, and assigning nil removes a key, as
+                // with a Lua table
+                MetaMethodCode {
+                    hook: None,
+                    registration: quote! {
+                        methods.add_meta_method(#rlua::MetaMethod::Index, |ctx, data, key: #rlua::Value| {
+                            #prelude
+                            #get_property
+                            #keep_key
+                            let key = <#key as #from_lua>::from_lua(key, ctx)?;
+                            match data.0.get(&key) {
+                                Some(value) => {
+                                    let value = ::std::clone::Clone::clone(value);
+                                    #to_lua_value
+                                }
+                                None => Ok(#extension),
+                            }
+                        });
+                        methods.add_meta_method_mut(#rlua::MetaMethod::NewIndex, |ctx, data, (key, value): (#rlua::Value, #rlua::Value)| {
+                            #prelude
+                            #set_property
+                            let key = <#key as #from_lua>::from_lua(key, ctx)?;
+                            if let #rlua::Value::Nil = value {
+                                data.0.remove(&key);
+                            } else {
+                                let value = #from_lua_value?;
+                                data.0.insert(key, value);
+                            }
+                            Ok(())
+                        });
+                    },
+                }
+            },
+            MetaMethod::Collection => {
+                let element = collection_element(ast)?;
+                let (to_lua_value, from_lua_value) = element_conversions(element, "list", ast, options);
+                let (get_property, set_property) = key_properties(options);
+                let extension = extension_lookup(quote!(key), options);
+                let pairs = cached_function("rudeboy_pairs", PAIRS_CHUNK, rlua);
+                // Lua's indices start at 1, and keys which are not integers
+                // index nothing
+                let position = quote! {
+                    let index = match &key {
+                        #rlua::Value::Integer(i) => Some(*i),
+                        #rlua::Value::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+                        _ => None,
+                    };
+                    let position = index.and_then(|i| <usize as ::std::convert::TryFrom<i64>>::try_from(i.checked_sub(1)?).ok());
+                };
+                // As with a Lua sequence, assigning just past the end appends
+                // an element, and assigning nil to the last element removes it
+                MetaMethodCode {
+                    hook: None,
+                    registration: quote! {
+                        methods.add_meta_method(#rlua::MetaMethod::Index, |ctx, data, key: #rlua::Value| {
+                            #prelude
+                            #get_property
+                            #position
+                            match position.and_then(|i| data.0.get(i)) {
+                                Some(value) => {
+                                    let value = ::std::clone::Clone::clone(value);
+                                    #to_lua_value
+                                }
+                                None => Ok(#extension),
+                            }
+                        });
+                        methods.add_meta_method_mut(#rlua::MetaMethod::NewIndex, |ctx, data, (key, value): (#rlua::Value, #rlua::Value)| {
+                            #prelude
+                            #set_property
+                            #position
+                            let len = data.0.len();
+                            let nil = matches!(value, #rlua::Value::Nil);
+                            match position {
+                                Some(i) if nil && i + 1 == len => {
+                                    data.0.pop();
+                                }
+                                Some(_) if nil => {
+                                    return Err(#rlua::ExternalError::to_lua_err("only the last element of a list can be removed"));
+                                }
+                                Some(i) if i < len => data.0[i] = #from_lua_value?,
+                                Some(i) if i == len => data.0.push(#from_lua_value?),
+                                _ => {
+                                    return Err(#rlua::ExternalError::to_lua_err(match index {
+                                        Some(i) => format!("index {} out of bounds for list of length {}", i, len),
+                                        None => ::std::string::String::from("list index must be an integer"),
+                                    }));
+                                }
                             }
+                            Ok(())
+                        });
+                        methods.add_meta_method(#rlua::MetaMethod::Len, |_, data, ()| {
+                            #prelude
+                            Ok(data.0.len())
                         });
+                        methods.add_meta_method(#rlua::MetaMethod::Pairs, |ctx, data, ()| {
+                            #prelude
+                            let items = data
+                                .0
+                                .iter()
+                                .map(|value| {
+                                    let value = ::std::clone::Clone::clone(value);
+                                    #to_lua_value
+                                })
+                                .collect::<#rlua::Result<::std::vec::Vec<_>>>()?;
+                            let items = ctx.create_sequence_from(items)?;
+                            #pairs?.call::<_, #rlua::MultiValue>(items)
+                        });
+                    },
+                }
+            },
+            MetaMethod::ToString(format) => {
+                // Format strings are checked when the generated code compiles,
+                // so errors in the given format's specs are reported at it
+                let string = match format {
+                    Some(format) => {
+                        let (positional, fields) = tostring_format(format, ast)?;
+                        let positional = syn::LitStr::new(&positional, format.span());
+                        let members = fields.into_iter().map(|field| field.member);
+                        quote_spanned!(format.span() => format!(#positional, #( &data.#members ),*))
                     }
+                    None => quote_spanned!(span => ::std::string::ToString::to_string(data)),
+                };
+                MetaMethodCode {
+                    hook: None,
+                    registration: quote! {
+                        methods.add_meta_method(#rlua::MetaMethod::ToString, |_, data, ()| {
+                            #prelude
+                            Ok(#string)
+                        });
+                    },
                 }
             },
-            MetaMethod::Sub => operator_method(quote!(generate_sub), quote!(Sub), quote!(-)),
-            MetaMethod::Mul => operator_method(quote!(generate_mul), quote!(Mul), quote!(*)),
-            MetaMethod::Div => operator_method(quote!(generate_div), quote!(Div), quote!(/)),
-            MetaMethod::Mod => operator_method(quote!(generate_mod), quote!(Mod), quote!(%)),
-            MetaMethod::Unm => unary_operator_method(quote!(generate_unm), quote!(Unm), quote!(-)),
-            MetaMethod::BAnd => operator_method(quote!(generate_band), quote!(BAnd), quote!(&)),
-            MetaMethod::BOr => operator_method(quote!(generate_bor), quote!(BOr), quote!(|)),
-            MetaMethod::BXor => operator_method(quote!(generate_bxor), quote!(BXor), quote!(^)),
-            MetaMethod::BNot => unary_operator_method(quote!(generate_bnot), quote!(BNot), quote!(!)),
-            MetaMethod::Shl => operator_method(quote!(generate_shl), quote!(Shl), quote!(<<)),
-            MetaMethod::Shr => operator_method(quote!(generate_shr), quote!(Shr), quote!(>>)),
-            MetaMethod::Lt => operator_method(quote!(generate_lt), quote!(Lt), quote!(<)),
-            MetaMethod::Le => operator_method(quote!(generate_le), quote!(Le), quote!(<=)),
+            MetaMethod::Sub => MetaMethodCode::hooked(quote!(generate_sub), binary_operator(quote!(-))),
+            MetaMethod::Mul => MetaMethodCode::hooked(quote!(generate_mul), binary_operator(quote!(*))),
+            MetaMethod::Div => MetaMethodCode::hooked(quote!(generate_div), binary_operator(quote!(/))),
+            MetaMethod::Mod => MetaMethodCode::hooked(quote!(generate_mod), binary_operator(quote!(%))),
+            // RudeboyMetaMethods has no hooks for IDiv and Pow
+            MetaMethod::IDiv(_) | MetaMethod::Pow(_) => MetaMethodCode {
+                hook: None,
+                registration: apply_operator(&self.method_operator().unwrap()),
+            },
+            MetaMethod::Unm => MetaMethodCode::hooked(quote!(generate_unm), unary_operator_method(&registration, quote!(-), prelude, span, options)),
+            MetaMethod::BAnd => MetaMethodCode::hooked(quote!(generate_band), binary_operator(quote!(&))),
+            MetaMethod::BOr => MetaMethodCode::hooked(quote!(generate_bor), binary_operator(quote!(|))),
+            MetaMethod::BXor => MetaMethodCode::hooked(quote!(generate_bxor), binary_operator(quote!(^))),
+            MetaMethod::BNot => MetaMethodCode::hooked(quote!(generate_bnot), unary_operator_method(&registration, quote!(!), prelude, span, options)),
+            MetaMethod::Shl => MetaMethodCode::hooked(quote!(generate_shl), binary_operator(quote!(<<))),
+            MetaMethod::Shr => MetaMethodCode::hooked(quote!(generate_shr), binary_operator(quote!(>>))),
+            MetaMethod::Lt => MetaMethodCode::hooked(quote!(generate_lt), comparison_method(&registration, quote!(::std::cmp::Ordering::Less), prelude, span, options)),
+            MetaMethod::Pairs(method) => {
+                let items = match method {
+                    // Items are cloned so that methods iterating by reference,
+                    // such as iter(), can be used
+                    Some(method) => quote!(data.#method().map(::std::clone::Clone::clone)),
+                    None => quote!(::std::iter::IntoIterator::into_iter(::std::clone::Clone::clone(data))),
+                };
+                let to_lua = options.paths.trait_to_lua();
+                let items = quote_spanned! {
+                    span => let items = ctx.create_sequence_from(
+                        #items
+                            .map(|item| #to_lua::to_lua(item, ctx))
+                            .collect::<#rlua::Result<::std::vec::Vec<_>>>()?,
+                    )?;
+                };
+                let pairs = cached_function("rudeboy_pairs", PAIRS_CHUNK, rlua);
+                MetaMethodCode {
+                    hook: None,
+                    registration: quote! {
+                        methods.add_meta_method(#rlua::MetaMethod::Pairs, |ctx, data, ()| {
+                            #prelude
+                            #items
+                            #pairs?.call::<_, #rlua::MultiValue>(items)
+                        });
+                    },
+                }
+            },
+            MetaMethod::Le => MetaMethodCode::hooked(quote!(generate_le), comparison_method(&registration, quote!(::std::cmp::Ordering::Less | ::std::cmp::Ordering::Equal), prelude, span, options)),
+        })
+    }
+}
+
+/// Infers the metamethods to generate for the given type definition from the
+/// standard traits it derives (`PartialEq` and `PartialOrd`) and from the
+/// `std::ops` traits named in a `#[rudeboy_ops(...)]` attribute
+fn infer_metamethods(di: &syn::DeriveInput) -> Result<Vec<(MetaMethod, Span, Vec<syn::Type>)>, TokenStream2> {
+    use syn::{Meta, NestedMeta};
+    let mut metamethods = Vec::new();
+    for attr in &di.attrs {
+        let is_derive = attr.path.is_ident("derive");
+        if !is_derive && !attr.path.is_ident(RUDEBOY_OPS_ATTR) {
+            continue;
+        }
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(l)) => l,
+            Ok(_) if is_derive => continue,
+            Ok(_) => {
+                return Err(quote_spanned! {
+                    attr.span() => compile_error!("Expected an attribute of the form #[rudeboy_ops(...)]");
+                })
+            }
+            Err(e) => return Err(e.to_compile_error()),
+        };
+        for nested in &list.nested {
+            // Operator traits may list their right hand operand types, e.g.
+            // `Mul(f64)`
+            let (trait_name, operands) = match nested {
+                NestedMeta::Meta(Meta::Path(p)) => (p.segments.last().map(|s| s.ident.to_string()), Vec::new()),
+                NestedMeta::Meta(Meta::List(l)) if !is_derive => {
+                    (l.path.segments.last().map(|s| s.ident.to_string()), operand_types(l)?)
+                }
+                _ => (None, Vec::new()),
+            };
+            let inferred: &[MetaMethod] = match trait_name.as_deref() {
+                Some("PartialEq") if is_derive => &[MetaMethod::Eq],
+                Some("PartialOrd") if is_derive => &[MetaMethod::Lt, MetaMethod::Le],
+                _ if is_derive => &[],
+                Some("Add") => &[MetaMethod::Add],
+                Some("Sub") => &[MetaMethod::Sub],
+                Some("Mul") => &[MetaMethod::Mul],
+                Some("Div") => &[MetaMethod::Div],
+                Some("Rem") => &[MetaMethod::Mod],
+                Some("Neg") => &[MetaMethod::Unm],
+                Some("BitAnd") => &[MetaMethod::BAnd],
+                Some("BitOr") => &[MetaMethod::BOr],
+                Some("BitXor") => &[MetaMethod::BXor],
+                Some("Not") => &[MetaMethod::BNot],
+                Some("Shl") => &[MetaMethod::Shl],
+                Some("Shr") => &[MetaMethod::Shr],
+                _ => {
+                    return Err(quote_spanned! {
+                        nested.span() => compile_error!("Expected the name of an operator trait from std::ops");
+                    })
+                }
+            };
+            if !operands.is_empty() && !inferred.iter().all(MetaMethod::is_binary_operator) {
+                return Err(quote_spanned! {
+                    nested.span() => compile_error!("Only binary operators take operand types");
+                });
+            }
+            metamethods.extend(inferred.iter().map(|mm| (mm.clone(), nested.span(), operands.clone())));
         }
     }
+    Ok(metamethods)
+}
+
+/// Options given alongside the metamethods in the metamethods attribute
+struct MetaMethodsOptions {
+    /// Instrument generated metamethods with `tracing` spans
+    trace: bool,
+    /// Infer additional metamethods from the type's derives and operator hints
+    infer: bool,
+    /// Apply operators and comparisons to the inner value of a newtype
+    newtype: bool,
+    /// Register floor division and bitwise operators as named methods, for Lua
+    /// 5.1
+    lua51: bool,
+    /// Apply operators and comparisons to references to the operands
+    by_ref: bool,
+    /// Also register each operator as a method named after it
+    operator_methods: bool,
+    /// The fields compared by Eq, if not the whole value
+    eq_fields: Option<Vec<syn::Ident>>,
+    /// The other exported types Eq compares with
+    eq_types: Vec<syn::Type>,
+    /// The types accepted as the right hand operand of binary operators, for
+    /// those whose operand is not `Self`
+    operands: HashMap<MetaMethod, Vec<syn::Type>>,
+    /// Index falls back to the properties read by getters of the type's
+    /// methods, which are generated alongside it
+    properties: bool,
+    /// Index falls back to the type's extension table, which is generated
+    /// alongside it
+    extensions: bool,
+    /// The paths to the rudeboy and rlua crates
+    paths: CratePaths,
 }
 
 fn attrs_to_metamethods(
     attrs: Vec<&syn::NestedMeta>,
-) -> Result<HashSet<MetaMethod>, TokenStream2> {
-    let mut metamethods = HashSet::new();
+    paths: CratePaths,
+) -> Result<(HashMap<MetaMethod, Span>, MetaMethodsOptions), TokenStream2> {
+    let mut metamethods = HashMap::new();
+    let mut options = MetaMethodsOptions {
+        trace: false,
+        infer: false,
+        newtype: false,
+        lua51: cfg!(feature = "lua51"),
+        by_ref: false,
+        operator_methods: false,
+        eq_fields: None,
+        eq_types: Vec::new(),
+        operands: HashMap::new(),
+        properties: false,
+        extensions: false,
+        paths,
+    };
     for attr in attrs {
+        if options.paths.try_parse_option(attr)? {
+            continue;
+        }
         use syn::{Meta, NestedMeta};
-        metamethods.insert(match attr {
+        let metamethod = match attr {
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(TRACE_IDENT) => {
+                check_trace_feature(p.span())?;
+                options.trace = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(INFER_IDENT) => {
+                options.infer = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(NEWTYPE_IDENT) => {
+                options.newtype = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(LUA51_IDENT) => {
+                options.lua51 = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(BY_REF_IDENT) => {
+                options.by_ref = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(OPERATOR_METHODS_IDENT) => {
+                options.operator_methods = true;
+                continue;
+            }
             NestedMeta::Meta(Meta::Path(p)) => MetaMethod::try_parse(p)?,
+            NestedMeta::Meta(Meta::List(l)) => {
+                let metamethod = MetaMethod::try_parse_list(l)?;
+                if metamethod.is_binary_operator() {
+                    let operands = operand_types(l)?;
+                    if !operands.is_empty() {
+                        options.operands.insert(metamethod.clone(), operands);
+                    }
+                } else if metamethod == MetaMethod::Eq {
+                    (options.eq_fields, options.eq_types) = eq_options(l)?;
+                }
+                metamethod
+            }
             _ => {
                 return Err(quote_spanned! {
                     attr.span() => compile_error!("Expected a valid metamethod identifier");
+                })
+            }
+        };
+        metamethods.insert(metamethod, attr.span());
+    }
+    Ok((metamethods, options))
+}
+
+/// Whether any of the given metamethod parameters satisfies the predicate
+fn lists(attrs: &[&syn::NestedMeta], predicate: fn(&MetaMethod) -> bool) -> bool {
+    use syn::{Meta, NestedMeta};
+    attrs.iter().any(|attr| match attr {
+        NestedMeta::Meta(Meta::Path(p)) => MetaMethod::try_parse(p).is_ok_and(|mm| predicate(&mm)),
+        NestedMeta::Meta(Meta::List(l)) => MetaMethod::try_parse_list(l).is_ok_and(|mm| predicate(&mm)),
+        _ => false,
+    })
+}
+
+/// Whether Index, or MapIndex or Collection which register it, is among the
+/// given metamethod parameters
+pub(crate) fn lists_index(attrs: &[&syn::NestedMeta]) -> bool {
+    lists(attrs, |mm| matches!(mm, MetaMethod::Index(_) | MetaMethod::MapIndex | MetaMethod::Collection))
+}
+
+/// Whether NewIndex, or one of Freeze, MapIndex, or Collection which register
+/// it, is among the given metamethod parameters
+pub(crate) fn lists_new_index(attrs: &[&syn::NestedMeta]) -> bool {
+    lists(attrs, |mm| {
+        matches!(mm, MetaMethod::NewIndex(_) | MetaMethod::Freeze | MetaMethod::MapIndex | MetaMethod::Collection)
+    })
+}
+
+/// Returns warnings for the given metamethods which are likely misconfigured,
+/// such as an Index whose fields are all skipped, which would otherwise only be
+/// noticed when a script uses them
+fn suspicious_metamethods(
+    di: &syn::DeriveInput,
+    metamethods: &HashMap<MetaMethod, Span>,
+    options: &MetaMethodsOptions,
+) -> Result<Vec<TokenStream2>, TokenStream2> {
+    let mut warnings = Vec::new();
+    for (mm, span) in metamethods {
+        match mm {
+            MetaMethod::Index(access) | MetaMethod::NewIndex(access) if !options.properties => {
+                let fields = match mm.named_fields(di) {
+                    Ok(fields) => fields,
+                    Err(_) => continue,
+                };
+                let mut reachable = false;
+                for field in &fields {
+                    let field_options = FieldOptions::try_parse(field)?;
+                    reachable |= field_options.flatten || field_options.delegate;
+                }
+                for (_, field) in access.accessible_fields(di, &fields)? {
+                    let skipped = matches!(mm, MetaMethod::NewIndex(_)) && FieldOptions::try_parse(field)?.skip;
+                    reachable |= !skipped;
                 }
-                .into())
+                if !reachable {
+                    let message = format!("{} has no fields to access, as every field of this type is skipped", mm.ident());
+                    warnings.push(warning(*span, &message));
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(warnings)
+}
+
+/// Generates the impl of `RudeboyMetaMethods` for the given type definition,
+/// using the given crate paths unless they are overridden in the parameters.
+/// If `properties` is set, Index and NewIndex also access the properties of the
+/// type's getters and setters, and if `extensions` is set, Index falls back to
+/// the type's extension table
+pub(crate) fn metamethods_impl(
+    di: &syn::DeriveInput,
+    attrs: Vec<&syn::NestedMeta>,
+    paths: CratePaths,
+    properties: bool,
+    extensions: bool,
+) -> Result<TokenStream2, TokenStream2> {
+    let name = &di.ident;
+    let (mut metamethods, mut options) = attrs_to_metamethods(attrs, paths)?;
+    options.properties = properties;
+    options.extensions = extensions;
+    let mut warnings = Vec::new();
+    if options.infer {
+        let inferred = infer_metamethods(di)?;
+        if inferred.is_empty() {
+            warnings.push(warning(
+                name.span(),
+                "No metamethods could be inferred. Note that #[derive(...)] must come after this attribute to be seen",
+            ));
+        }
+        for (metamethod, span, operands) in inferred {
+            if !operands.is_empty() && !metamethods.contains_key(&metamethod) {
+                options.operands.insert(metamethod.clone(), operands);
             }
+            metamethods.entry(metamethod).or_insert(span);
+        }
+    }
+    if options.newtype {
+        if let Some(operands) = options.operands.values().next() {
+            return Err(quote_spanned! {
+                operands[0].span() => compile_error!("Operand types cannot be combined with newtype");
+            });
+        }
+        if let Some(fields) = &options.eq_fields {
+            return Err(quote_spanned! {
+                fields[0].span() => compile_error!("Eq fields cannot be combined with newtype");
+            });
+        }
+        if let Some(ty) = options.eq_types.first() {
+            return Err(quote_spanned! {
+                ty.span() => compile_error!("Eq types cannot be combined with newtype");
+            });
+        }
+    }
+    // `/` truncates integers and does not round floats, whereas `//` floors
+    if let Some(span) = metamethods.get(&MetaMethod::IDiv(None)) {
+        return Err(quote_spanned! {
+            *span => compile_error!("IDiv requires the method applying floor division, e.g. IDiv(method = \"div_floor\")");
         });
     }
-    Ok(metamethods)
+    if let Some(span) = metamethods.get(&MetaMethod::Freeze) {
+        if metamethods.keys().any(|mm| matches!(mm, MetaMethod::NewIndex(_))) {
+            return Err(quote_spanned! {
+                *span => compile_error!("Freeze cannot be combined with NewIndex");
+            });
+        }
+    }
+    if let Some(span) = metamethods.get(&MetaMethod::MapIndex) {
+        let conflicting = metamethods.keys().any(|mm| {
+            matches!(mm, MetaMethod::Index(_) | MetaMethod::NewIndex(_) | MetaMethod::Freeze | MetaMethod::Collection)
+        });
+        if conflicting {
+            return Err(quote_spanned! {
+                *span => compile_error!("MapIndex cannot be combined with Index, NewIndex, Freeze, or Collection");
+            });
+        }
+    }
+    if let Some(span) = metamethods.get(&MetaMethod::Collection) {
+        let conflicting = metamethods.keys().any(|mm| {
+            matches!(mm, MetaMethod::Index(_) | MetaMethod::NewIndex(_) | MetaMethod::Freeze | MetaMethod::Pairs(_))
+        });
+        if conflicting {
+            return Err(quote_spanned! {
+                *span => compile_error!("Collection cannot be combined with Index, NewIndex, Freeze, or Pairs");
+            });
+        }
+    }
+    let mut names: Vec<_> = metamethods.keys().map(|mm| mm.ident()).collect();
+    names.sort_unstable();
+    let manifest = write_manifest(
+        name.span(),
+        &name.to_string(),
+        "metamethods",
+        Json::Object(vec![
+            ("type", Json::str(name.to_string())),
+            ("metamethods", Json::Array(names.into_iter().map(Json::str).collect())),
+        ]),
+    );
+    let mut lua_names: Vec<_> = metamethods.keys().map(|mm| mm.lua_name(&options)).collect();
+    // MapIndex and Collection register NewIndex as well as Index, and
+    // Collection registers Len and Pairs too
+    if metamethods.contains_key(&MetaMethod::MapIndex) || metamethods.contains_key(&MetaMethod::Collection) {
+        lua_names.push("__newindex".to_string());
+    }
+    if metamethods.contains_key(&MetaMethod::Collection) {
+        lua_names.extend(["__len".to_string(), "__pairs".to_string()]);
+    }
+    // The fields which may be read or assigned through Index and NewIndex
+    let mut fields = Vec::new();
+    for mm in metamethods.keys() {
+        if let MetaMethod::Index(access) | MetaMethod::NewIndex(access) = mm {
+            let keys = access.accessible_fields(di, &mm.named_fields(di)?)?;
+            fields.extend(keys.into_iter().map(|(key, _)| key));
+        }
+    }
+    fields.sort_unstable();
+    fields.dedup();
+    let rudeboy = &options.paths.rudeboy;
+    let rlua = &options.paths.rlua;
+    let codes = metamethods
+        .iter()
+        .map(|(mm, span)| mm.get_method(di, *span, &options, false))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Operators are also registered as named methods if requested, unless they
+    // already are one under Lua 5.1
+    let mut operator_methods = Vec::new();
+    if options.operator_methods {
+        let mut operators: Vec<_> = metamethods
+            .iter()
+            .filter(|(mm, _)| mm.is_operator() && !(options.lua51 && mm.is_lua53_operator()))
+            .collect();
+        operators.sort_unstable_by_key(|(mm, _)| mm.ident());
+        for (mm, span) in operators {
+            lua_names.push(mm.ident().to_lowercase());
+            operator_methods.push(mm.get_method(di, *span, &options, true)?.registration);
+        }
+    }
+    lua_names.sort_unstable();
+
+    let mut hooks = Vec::new();
+    let mut hook_fns = Vec::new();
+    let mut unhooked = Vec::new();
+    for MetaMethodCode { hook, registration } in codes {
+        match hook {
+            Some(hook) => {
+                hook_fns.push(quote! {
+                    fn #hook<'lua, M: #rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                        #registration
+                    }
+                });
+                hooks.push(hook);
+            }
+            None => unhooked.push(registration),
+        }
+    }
+    unhooked.extend(operator_methods);
+
+    // Metamethods without a hook in the trait are registered by overriding
+    // generate_metamethods, which must then call the hooks itself
+    let generate_metamethods = if unhooked.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            fn generate_metamethods<'lua, M: #rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                #( Self::#hooks(methods); )*
+                #( #unhooked )*
+            }
+        }
+    };
+
+    // Generic types are only given the bounds of the metamethods generated for
+    // them, on top of those needed by rlua to register anything at all
+    let (impl_generics, ty_generics, where_clause) = di.generics.split_for_impl();
+    let mut generics = di.generics.clone();
+    if !generics.params.is_empty() {
+        let mut bounds = vec![quote!(Self: 'static + #rlua::UserData)];
+        for mm in metamethods.keys() {
+            bounds.extend(mm.bounds(di, &options)?);
+        }
+        let predicates = &mut generics.make_where_clause().predicates;
+        for bound in bounds {
+            predicates.push(syn::parse2(bound).map_err(|e| e.to_compile_error())?);
+        }
+    }
+    let (_, _, bounded_where_clause) = generics.split_for_impl();
+    // The field accessors convert fields as Index and NewIndex do, and so need
+    // the same bounds
+    let mut field_accessors = Vec::new();
+    for mm in metamethods.keys() {
+        field_accessors.extend(mm.field_accessor(di, &options)?);
+    }
+    let field_accessors = if field_accessors.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            impl #impl_generics #name #ty_generics #bounded_where_clause {
+                #( #field_accessors )*
+            }
+        }
+    };
+    let assertions = metamethods.iter().filter_map(|(mm, span)| mm.operator_assertion(di, *span, &options));
+
+    warnings.extend(suspicious_metamethods(di, &metamethods, &options)?);
+
+    let generated = options.paths.allow_lints(quote! {
+        #( #assertions )*
+
+        const _: () = {
+            impl #impl_generics #rudeboy::RudeboyMetaMethods for #name #ty_generics #bounded_where_clause {
+                #( #hook_fns )*
+                #generate_metamethods
+            }
+        };
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// The keys of the metamethods registered on this type's userdata
+            /// by rudeboy, along with any methods standing in for them
+            pub const LUA_METAMETHODS: &'static [&'static str] = &[#( #lua_names ),*];
+
+            /// The fields which may be read or assigned through this type's
+            /// Index and NewIndex metamethods
+            pub const LUA_FIELDS: &'static [&'static str] = &[#( #fields ),*];
+        }
+
+        #field_accessors
+    });
+    Ok(quote! {
+        #( #warnings )*
+
+        #manifest
+
+        #generated
+    })
+}
+
+/// Converts a struct or enum item into a `DeriveInput`
+pub(crate) fn item_to_derive_input(item: &syn::Item) -> Result<syn::DeriveInput, TokenStream2> {
+    match item {
+        syn::Item::Struct(s) => Ok(syn::DeriveInput::from(s.clone())),
+        syn::Item::Enum(e) => Ok(syn::DeriveInput::from(e.clone())),
+        _ => Err(quote_spanned! {
+            item.span() => compile_error!("metamethods can only be applied to structs and enums");
+        }),
+    }
 }
 
 pub(crate) fn impl_metamethods_attr_macro(
     item: syn::Item,
     attrs: Vec<&syn::NestedMeta>,
 ) -> TokenStream2 {
-    let di = match &item {
-        syn::Item::Struct(s) => syn::DeriveInput::from(s.clone()),
-        syn::Item::Enum(e) => syn::DeriveInput::from(e.clone()),
-        _ => {
-            return quote_spanned! {
-                item.span() => compile_error!("metamethods can only be applied to structs and enums");
-            }
-            .into()
-        }
+    let di = match item_to_derive_input(&item) {
+        Ok(di) => di,
+        Err(e) => return e,
     };
-    let name = &di.ident;
-    let metamethods: Vec<_> = match attrs_to_metamethods(attrs) {
-        Ok(mms) => mms,
+    let metamethods_impl = match metamethods_impl(&di, attrs, CratePaths::default(), false, false) {
+        Ok(mi) => mi,
         Err(e) => return e,
-    }
-    .iter()
-    .map(|mm| mm.get_method(&di))
-    .collect();
+    };
 
+    let item = strip_helper_attrs(&item);
     quote! {
         #item
 
-        impl ::rudeboy::RudeboyMetaMethods for #name {
-            #( #metamethods )*
-        }
+        #metamethods_impl
     }
 }
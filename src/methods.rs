@@ -1,9 +1,21 @@
+use crate::attrs::{cfg_attrs, doc_lines, function_path, strip_helper_attrs, MethodOptions, ParamOptions};
+use crate::chunks::cached_function;
+use crate::constants::{constants_table_body, Constant};
+use crate::diagnostics::warning;
+use crate::ecosystem;
+use crate::ldoc::{lua_type, write_ldoc, LDocFunction};
+use crate::manifest::{method_manifest, type_string, write_manifest, Json};
+use crate::names::LuaNames;
+use crate::numbers::{checked_integer_conversion, IntegerRepr};
+use crate::paths::CratePaths;
+use crate::stats::{check_stats_feature, record_stats, stats_code, STATS_IDENT};
+use crate::trace::{check_trace_feature, trace_span, TRACE_IDENT};
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, quote_spanned};
-use syn;
 use syn::spanned::Spanned;
 
-enum Params<'a> {
+#[derive(Clone)]
+pub(crate) enum Params<'a> {
     None,
     One {
         name: &'a syn::Ident,
@@ -11,14 +23,651 @@ enum Params<'a> {
     },
     Multi {
         names: Vec<&'a syn::Ident>,
-        tys: Vec<Box<syn::Type>>,
+        tys: Vec<syn::Type>,
     },
 }
 
-struct MethodInfo<'a> {
+/// Options given as parameters to the methods attribute
+#[derive(Default)]
+pub(crate) struct MethodsOptions {
+    /// Skip methods which cannot be exported, instead of failing
+    pub lenient: bool,
+    /// Convert panics in exported methods into Lua errors
+    pub catch_panic: bool,
+    /// Instrument exported methods with `tracing` spans
+    pub trace: bool,
+    /// Count the calls to and time spent in each exported method
+    pub stats: bool,
+    /// The paths to the rudeboy and rlua crates
+    pub paths: CratePaths,
+    /// The name of the method returning a namespace table holding the exported
+    /// methods, if they should be grouped under one
+    pub namespace: Option<String>,
+    /// Generate a `help` method describing the exported methods
+    pub help: bool,
+    /// Export methods which move self by calling them on a clone of the data
+    pub builder: bool,
+    /// Also add each method to the class table as a function taking the
+    /// userdata as its first argument
+    pub dot_call: bool,
+    /// Read methods which could be getters as properties, as if marked with
+    /// `#[rudeboy(getter)]`
+    pub auto_props: bool,
+    /// Reject arguments which rlua would otherwise coerce into numeric
+    /// parameters, i.e. fractional numbers for integers and strings for either
+    pub strict_numbers: bool,
+    /// How `i64` and `u64` parameters and return values are represented in Lua
+    pub integers: IntegerRepr,
+    /// The function converting errors returned by methods into an
+    /// `rlua::Error`, unless overridden on a method
+    pub map_err: Option<syn::Path>,
+    /// The error types returned by methods implement `std::error::Error`, and
+    /// are raised with their message and those of their sources
+    pub std_error: bool,
+    /// The function given the warnings of deprecated methods, rather than
+    /// printing them to stderr
+    pub on_deprecated: Option<syn::Path>,
+    /// The traits whose exported methods, as implemented for the type, are
+    /// also registered
+    pub from_traits: Vec<syn::Path>,
+    /// The prefix added to every Lua name registered on the userdata, e.g. to
+    /// tell debugging methods apart
+    pub prefix: String,
+    /// The function deciding whether the host grants a capability, which
+    /// methods requiring one are only registered if it does
+    pub capabilities: Option<syn::Path>,
+}
+
+impl MethodsOptions {
+    const LENIENT_IDENT: &'static str = "lenient";
+    const CATCH_PANIC_IDENT: &'static str = "catch_panic";
+    const NAMESPACE_IDENT: &'static str = "namespace";
+    const HELP_IDENT: &'static str = "help";
+    const STRICT_NUMBERS_IDENT: &'static str = "strict_numbers";
+    const DOT_CALL_IDENT: &'static str = "dot_call";
+    const BUILDER_IDENT: &'static str = "builder";
+    const AUTO_PROPS_IDENT: &'static str = "auto_props";
+    const INTEGERS_IDENT: &'static str = "integers";
+    const ON_DEPRECATED_IDENT: &'static str = "on_deprecated";
+    const FROM_TRAIT_IDENT: &'static str = "from_trait";
+    const PREFIX_IDENT: &'static str = "prefix";
+    const CAPABILITIES_IDENT: &'static str = "capabilities";
+
+    pub(crate) fn try_parse(attrs: Vec<&syn::NestedMeta>) -> Result<MethodsOptions, TokenStream2> {
+        let mut options = MethodsOptions::default();
+        for attr in attrs {
+            if options.paths.try_parse_option(attr)? {
+                continue;
+            }
+            use syn::{Meta, NestedMeta};
+            match attr {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::LENIENT_IDENT) => {
+                    options.lenient = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::CATCH_PANIC_IDENT) => {
+                    options.catch_panic = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::HELP_IDENT) => options.help = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::STRICT_NUMBERS_IDENT) => {
+                    options.strict_numbers = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::DOT_CALL_IDENT) => options.dot_call = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::BUILDER_IDENT) => options.builder = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(Self::AUTO_PROPS_IDENT) => options.auto_props = true,
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(MethodOptions::STD_ERROR_IDENT) => {
+                    options.std_error = true
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(MethodOptions::MAP_ERR_IDENT) => {
+                    options.map_err = Some(function_path(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::CAPABILITIES_IDENT) => {
+                    options.capabilities = Some(function_path(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::ON_DEPRECATED_IDENT) => {
+                    options.on_deprecated = Some(function_path(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::FROM_TRAIT_IDENT) => {
+                    options.from_traits.push(match &nv.lit {
+                        syn::Lit::Str(s) => s.parse().map_err(|e| e.to_compile_error())?,
+                        lit => {
+                            return Err(quote_spanned! {
+                                lit.span() => compile_error!("Expected a string containing the path of a trait");
+                            })
+                        }
+                    })
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::PREFIX_IDENT) => match &nv.lit {
+                    syn::Lit::Str(s) => options.prefix = s.value(),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the prefix");
+                        })
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::INTEGERS_IDENT) => {
+                    options.integers = IntegerRepr::try_parse(&nv.lit)?
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::NAMESPACE_IDENT) => {
+                    match &nv.lit {
+                        syn::Lit::Str(s) => options.namespace = Some(s.value()),
+                        lit => {
+                            return Err(quote_spanned! {
+                                lit.span() => compile_error!("Expected a string containing the namespace name");
+                            })
+                        }
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(TRACE_IDENT) => {
+                    check_trace_feature(p.span())?;
+                    options.trace = true
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident(STATS_IDENT) => {
+                    check_stats_feature(p.span())?;
+                    options.stats = true
+                }
+                _ => {
+                    return Err(quote_spanned! {
+                        attr.span() => compile_error!("Expected a valid methods parameter");
+                    })
+                }
+            }
+        }
+        // The namespace is registered on the userdata in place of the methods
+        if let Some(namespace) = &mut options.namespace {
+            namespace.insert_str(0, &options.prefix);
+        }
+        Ok(options)
+    }
+
+    /// The Lua name of the help method
+    fn help_name(&self) -> String {
+        format!("{}{}", self.prefix, Self::HELP_IDENT)
+    }
+
+    /// The prefix of the Lua names of methods, which namespaced methods do not
+    /// need, as the namespace is prefixed instead
+    fn method_prefix(&self) -> &str {
+        match self.namespace {
+            Some(_) => "",
+            None => &self.prefix,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MethodInfo<'a> {
     pub name: &'a syn::Ident,
+    pub lua_name: String,
+    pub cfgs: Vec<TokenStream2>,
     pub is_mut: bool,
+    /// The method takes `self` by value, and is called on a clone of the data
+    pub consumes_self: bool,
+    /// The method is an `unsafe fn`, which is called in an `unsafe` block
+    pub is_unsafe: bool,
+    /// The method is read as a property through the Index metamethod
+    pub getter: bool,
+    /// The property assigned through the NewIndex metamethod by the method
+    pub setter: Option<String>,
+    pub metamethod: Option<syn::Ident>,
+    pub returns_iterator: bool,
+    /// The error type of the `Result` returned by the method, if any, which is
+    /// raised in Lua rather than returned
+    pub result_error: Option<ResultError>,
+    /// The method returns a userdata, either of this type or of another type
+    /// marked with the userdata option, which is created with `create_userdata`
+    pub returns_userdata: bool,
+    /// The method returns a `Cow<str>`, possibly in a `Result` or an `Option`,
+    /// which has no Lua conversion of its own
+    pub returns_cow: bool,
+    /// The method returns a `HashSet` or `BTreeSet`, possibly in a `Result` or
+    /// an `Option`, which rlua cannot convert, and which is converted into a
+    /// sequence of its elements
+    pub returns_set: bool,
+    /// The method returns an `Option`, possibly in a `Result`, whose value is
+    /// converted as if it had been returned directly, and `None` into nil
+    pub returns_option: bool,
     pub params: Params<'a>,
+    pub validations: Vec<ParamOptions>,
+    pub output: syn::ReturnType,
+    pub docs: Vec<String>,
+    /// The message of the warning given the first time the method is called
+    pub deprecated: Option<String>,
+    /// The results of the method are cached by its arguments
+    pub memoize: bool,
+    /// The capability which the host must grant for the method to be
+    /// registered
+    pub capability: Option<String>,
+}
+
+impl MethodInfo<'_> {
+    /// Whether the method is a getter or setter, accessed as a property rather
+    /// than registered as a method
+    fn is_property(&self) -> bool {
+        self.getter || self.setter.is_some()
+    }
+
+    /// Whether the method returns a type converted by one of the ecosystem
+    /// features, possibly in a `Result` or an `Option`
+    fn returns_ecosystem_type(&self) -> bool {
+        returned_value_type(&self.output).and_then(ecosystem::lua_type).is_some()
+    }
+
+    /// The condition under which the method is registered, i.e. that the
+    /// host's capabilities function grants the capability it requires, if any
+    fn capability_check(&self, options: &MethodsOptions) -> Option<TokenStream2> {
+        let capability = self.capability.as_ref()?;
+        let capabilities = options.capabilities.as_ref()?;
+        Some(quote_spanned!(capabilities.span() => #capabilities(#capability)))
+    }
+
+    /// Wraps the given registration statements so that they only run if the
+    /// method's capability is granted
+    pub(crate) fn gated(&self, code: TokenStream2, options: &MethodsOptions) -> TokenStream2 {
+        match self.capability_check(options) {
+            Some(check) => quote!(if #check { #code }),
+            None => code,
+        }
+    }
+}
+
+/// A Lua chunk which takes a sequence and returns an iterator function over its
+/// values, for use in a generic `for` loop
+const ITERATOR_CHUNK: &str = "\
+    local items = ...
+    local i = 0
+    return function()
+        i = i + 1
+        return items[i]
+    end";
+
+/// Whether the given method returns an iterator, i.e. `impl Iterator<...>`,
+/// `Box<dyn Iterator<...>>`, or a type named `IntoIter` such as
+/// `std::vec::IntoIter<T>`
+fn returns_iterator(signature: &syn::Signature) -> bool {
+    fn is_iterator_bound(bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::Token![+]>) -> bool {
+        bounds.iter().any(|b| match b {
+            syn::TypeParamBound::Trait(t) => t.path.segments.last().is_some_and(|s| s.ident == "Iterator"),
+            _ => false,
+        })
+    }
+
+    let ty = match &signature.output {
+        syn::ReturnType::Type(_, ty) => ty.as_ref(),
+        syn::ReturnType::Default => return false,
+    };
+    match ty {
+        syn::Type::ImplTrait(t) => is_iterator_bound(&t.bounds),
+        syn::Type::Path(p) => match p.path.segments.last() {
+            Some(s) if s.ident == "IntoIter" => true,
+            Some(s) if s.ident == "Box" => match &s.arguments {
+                syn::PathArguments::AngleBracketed(args) => args.args.iter().any(|a| match a {
+                    syn::GenericArgument::Type(syn::Type::TraitObject(t)) => is_iterator_bound(&t.bounds),
+                    _ => false,
+                }),
+                _ => false,
+            },
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether the given segment of an error type is a `Box<dyn Error>`, possibly
+/// with further bounds such as `Send + Sync`
+fn is_dyn_error(segment: &syn::PathSegment) -> bool {
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().any(|a| match a {
+            syn::GenericArgument::Type(syn::Type::TraitObject(t)) => t.bounds.iter().any(|b| match b {
+                syn::TypeParamBound::Trait(t) => t.path.segments.last().is_some_and(|s| s.ident == "Error"),
+                _ => false,
+            }),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// The error type of a method returning a `Result`, which determines how an
+/// error is raised in Lua
+#[derive(Clone)]
+pub(crate) enum ResultError {
+    /// An `rlua::Error`, which is forwarded as is
+    Lua,
+    /// A type implementing `std::error::Error`, or a `Box<dyn Error>` if
+    /// `boxed`, which is raised with its message and those of its sources
+    Std { boxed: bool },
+    /// An `anyhow::Error`, which is raised with its message and context chain
+    Anyhow,
+    /// Any other type, e.g. a `String` or `&str`, which is returned along with
+    /// nil as rlua does
+    Returned,
+    /// Any type, which is converted into an `rlua::Error` by the given function
+    Mapped(syn::Path),
+}
+
+impl ResultError {
+    /// Returns the kind of error returned by a method with the given return
+    /// type, or None if it does not return a `Result`. Only a `Box<dyn Error>`
+    /// is known to implement `std::error::Error`, unless `std_error` is given
+    /// for the method or its impl block
+    fn parse(output: &syn::ReturnType, std_error: bool, paths: &CratePaths) -> Option<ResultError> {
+        let has_segment = |path: &syn::Path, ident: &str| path.segments.iter().any(|s| s.ident == ident);
+        let other = if std_error { ResultError::Std { boxed: false } } else { ResultError::Returned };
+        let path = match output {
+            syn::ReturnType::Type(_, ty) => match ty.as_ref() {
+                syn::Type::Path(p) => &p.path,
+                _ => return None,
+            },
+            syn::ReturnType::Default => return None,
+        };
+        let segment = match path.segments.last() {
+            Some(segment) if segment.ident == "Result" => segment,
+            _ => return None,
+        };
+        let args: Vec<_> = match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => args.args.iter().collect(),
+            syn::PathArguments::None => Vec::new(),
+            syn::PathArguments::Parenthesized(_) => return None,
+        };
+        let error = match args.as_slice() {
+            [syn::GenericArgument::Type(_), syn::GenericArgument::Type(error)] => error,
+            // Aliases such as `rlua::Result<T>` or `io::Result<T>` are named
+            // after the crate or module defining them, and a bare `Result<T>`
+            // is taken to be imported from rlua, as is conventional
+            _ if paths.is_rlua_item(path) => return Some(ResultError::Lua),
+            _ if has_segment(path, "anyhow") => return Some(ResultError::Anyhow),
+            _ if std_error => return Some(other),
+            _ if path.leading_colon.is_none() && path.segments.len() == 1 => return Some(ResultError::Lua),
+            _ => return Some(other),
+        };
+        let error_path = match error {
+            syn::Type::Path(p) => &p.path,
+            syn::Type::Reference(_) => return Some(ResultError::Returned),
+            _ => return Some(other),
+        };
+        let last = error_path.segments.last();
+        Some(match last.map(|s| s.ident.to_string()).as_deref() {
+            Some("String") => ResultError::Returned,
+            Some("Error") if paths.is_rlua_item(error_path) => ResultError::Lua,
+            Some("Error") if has_segment(error_path, "anyhow") => ResultError::Anyhow,
+            Some("Box") if last.is_some_and(is_dyn_error) => ResultError::Std { boxed: true },
+            _ => other,
+        })
+    }
+
+    /// Returns the kind of error returned by the given method, taking into
+    /// account its map_err and std_error options and the defaults given for the
+    /// impl block
+    pub(crate) fn try_parse(
+        signature: &syn::Signature,
+        map_err: Option<syn::Path>,
+        default_map_err: Option<&syn::Path>,
+        std_error: bool,
+        paths: &CratePaths,
+    ) -> Result<Option<ResultError>, TokenStream2> {
+        let error = Self::parse(&signature.output, std_error, paths);
+        if let Some(map_err) = map_err {
+            return match error {
+                Some(_) => Ok(Some(ResultError::Mapped(map_err))),
+                None => Err(quote_spanned! {
+                    map_err.span() => compile_error!("map_err can only be used on methods returning a Result");
+                }),
+            };
+        }
+        match (error, default_map_err) {
+            // rlua errors need no conversion, even when a default is given
+            (Some(ResultError::Lua), _) => Ok(Some(ResultError::Lua)),
+            (Some(_), Some(map_err)) => Ok(Some(ResultError::Mapped(map_err.clone()))),
+            (Some(ResultError::Anyhow), None) if !cfg!(feature = "anyhow") => Err(quote_spanned! {
+                signature.output.span() => compile_error!("Returning anyhow errors requires the anyhow feature of rudeboy-derive to be enabled");
+            }),
+            (error, _) => Ok(error),
+        }
+    }
+
+    /// Returns a closure converting the error into an `rlua::Error`, or None if
+    /// it already is one
+    pub(crate) fn conversion(&self, rlua: &TokenStream2) -> Option<TokenStream2> {
+        match self {
+            ResultError::Lua | ResultError::Returned => None,
+            ResultError::Mapped(map_err) => Some(quote!(#map_err)),
+            ResultError::Std { boxed } => {
+                let error = if *boxed { quote!(&*error) } else { quote!(&error) };
+                Some(quote! {
+                    |error| {
+                        let error: &dyn ::std::error::Error = #error;
+                        let mut message = ::std::string::ToString::to_string(error);
+                        let mut source = error.source();
+                        while let Some(cause) = source {
+                            message.push_str(&format!(": {}", cause));
+                            source = cause.source();
+                        }
+                        #rlua::Error::external(message)
+                    }
+                })
+            }
+            // The alternate format of an anyhow error includes its context chain
+            ResultError::Anyhow => Some(quote!(|error| #rlua::Error::external(format!("{:#}", error)))),
+        }
+    }
+}
+
+/// Whether the given return type, with `Self` replaced, is the self type or a
+/// `Result` of it
+fn returns_self(output: &syn::ReturnType, self_ty: &syn::Type) -> bool {
+    returned_value_type(output).is_some_and(|ty| type_string(ty) == type_string(self_ty))
+}
+
+/// Whether the given type is a `Cow<str>`, which is converted from Lua as an
+/// owned string and into Lua by borrowing its contents
+pub(crate) fn is_cow_str(ty: &syn::Type) -> bool {
+    let segment = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last(),
+        _ => None,
+    };
+    match segment {
+        Some(segment) if segment.ident == "Cow" => match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => args.args.iter().any(|a| {
+                matches!(a, syn::GenericArgument::Type(syn::Type::Path(p)) if p.path.is_ident("str"))
+            }),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether the given type is a `HashSet` or `BTreeSet`
+fn is_set(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) if p.qself.is_none() => {
+            p.path.segments.last().is_some_and(|s| s.ident == "HashSet" || s.ident == "BTreeSet")
+        }
+        _ => false,
+    }
+}
+
+/// Whether the given type is `rlua::Value` (or rlua's alias `LuaValue`), which
+/// parameters receive unconverted, with whatever lifetime they name
+pub(crate) fn is_lua_value(ty: &syn::Type) -> bool {
+    let path = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => &p.path,
+        _ => return false,
+    };
+    let mut segments = path.segments.iter().rev();
+    match (segments.next(), segments.next()) {
+        (Some(last), _) if last.ident == "LuaValue" => true,
+        (Some(last), Some(module)) => last.ident == "Value" && module.ident == "rlua",
+        _ => false,
+    }
+}
+
+/// Whether the given type is `rlua::MultiValue` (or rlua's alias
+/// `LuaMultiValue`), which a method's last parameter may be to receive all of
+/// the remaining arguments unconverted
+pub(crate) fn is_multi_value(ty: &syn::Type) -> bool {
+    let path = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => &p.path,
+        _ => return false,
+    };
+    let mut segments = path.segments.iter().rev();
+    match (segments.next(), segments.next()) {
+        (Some(last), _) if last.ident == "LuaMultiValue" => true,
+        (Some(last), Some(module)) => last.ident == "MultiValue" && module.ident == "rlua",
+        _ => false,
+    }
+}
+
+/// Returns the type of the value returned by a method with the given return
+/// type, which is the type of its `Ok` value if it returns a `Result`
+fn returned_type(output: &syn::ReturnType) -> Option<&syn::Type> {
+    let ty = match output {
+        syn::ReturnType::Type(_, ty) => ty.as_ref(),
+        syn::ReturnType::Default => return None,
+    };
+    match ty {
+        syn::Type::Path(p) => match p.path.segments.last() {
+            Some(segment) if segment.ident == "Result" => match &segment.arguments {
+                syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+                    Some(syn::GenericArgument::Type(t)) => Some(t),
+                    _ => None,
+                },
+                _ => Some(ty),
+            },
+            _ => Some(ty),
+        },
+        _ => Some(ty),
+    }
+}
+
+/// Returns the type held by the given type if it is an `Option`
+fn optional_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if segment.ident == "Option" => match args.args.first() {
+            Some(syn::GenericArgument::Type(t)) => Some(t),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the type of the value returned by a method with the given return
+/// type once any `Result` and `Option` around it are removed, e.g. `T` for
+/// `Result<Option<T>, E>`
+fn returned_value_type(output: &syn::ReturnType) -> Option<&syn::Type> {
+    returned_type(output).map(|ty| optional_type(ty).unwrap_or(ty))
+}
+
+/// Replaces each use of `Self` in the given tokens, e.g. a parameter type, with
+/// the given self type, so that the concrete type appears in error messages and
+/// generated documentation
+fn replace_self<T: quote::ToTokens + syn::parse::Parse + Clone>(tokens: &T, self_ty: &syn::Type) -> T {
+    fn replace(tokens: TokenStream2, self_ty: &syn::Type) -> TokenStream2 {
+        let mut out = TokenStream2::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                proc_macro2::TokenTree::Ident(i) if i == "Self" => {
+                    // An associated item must be reached through a qualified
+                    // path, e.g. `<Type<T>>::Item`
+                    let is_path = matches!(tokens.peek(), Some(proc_macro2::TokenTree::Punct(p)) if p.as_char() == ':');
+                    let replacement = if is_path { quote!(<#self_ty>) } else { quote!(#self_ty) };
+                    // The replacement keeps the span of `Self`, so that errors
+                    // are still reported at the signature
+                    out.extend(replacement.into_iter().map(|mut token| {
+                        token.set_span(i.span());
+                        token
+                    }));
+                }
+                proc_macro2::TokenTree::Group(g) => {
+                    let mut group = proc_macro2::Group::new(g.delimiter(), replace(g.stream(), self_ty));
+                    group.set_span(g.span());
+                    out.extend(std::iter::once(proc_macro2::TokenTree::Group(group)));
+                }
+                token => out.extend(std::iter::once(token)),
+            }
+        }
+        out
+    }
+    syn::parse2(replace(quote!(#tokens), self_ty)).unwrap_or_else(|_| tokens.clone())
+}
+
+/// If the given parameter type is a shared slice, e.g. `&[Self]`, returns the
+/// type of its elements
+pub(crate) fn slice_reference(ty: &syn::Type) -> Option<&syn::Type> {
+    match ty {
+        syn::Type::Reference(r) if r.mutability.is_none() => match r.elem.as_ref() {
+            syn::Type::Slice(s) => Some(&s.elem),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If the given parameter type is a reference to a type other than `str` or a
+/// slice, returns the referenced type and the reference's mutability. Such
+/// parameters are taken from Lua as a userdata of the referenced type
+pub(crate) fn userdata_reference(ty: &syn::Type) -> Option<(&syn::Type, Option<syn::Token![mut]>)> {
+    match ty {
+        syn::Type::Reference(r) => match r.elem.as_ref() {
+            syn::Type::Slice(_) => None,
+            syn::Type::Path(p) if p.path.is_ident("str") => None,
+            elem => Some((elem, r.mutability)),
+        },
+        _ => None,
+    }
+}
+
+/// Returns the concrete type an argument is converted into from Lua for a
+/// parameter of the given type. Parameters of type `impl AsRef<str>` or
+/// `impl Borrow<str>` take a `String`, those of type `impl AsRef<[T]>` or
+/// `impl Borrow<[T]>` a `Vec<T>`, and those of type `impl Into<T>` a `T`.
+/// Other `impl Trait` types cannot be exported
+fn binding_type(ty: &syn::Type) -> Result<syn::Type, TokenStream2> {
+    let bounds = match ty {
+        syn::Type::ImplTrait(t) => &t.bounds,
+        ty => return Ok(ty.clone()),
+    };
+    let unsupported = || {
+        Err(quote_spanned! {
+            ty.span() => compile_error!("Expected impl AsRef<str>, impl AsRef<[T]>, impl Borrow<str>, impl Borrow<[T]>, or impl Into<T>");
+        })
+    };
+    // Any other bounds, e.g. `+ Clone`, must be met by the concrete type
+    let segment = bounds.iter().find_map(|b| match b {
+        syn::TypeParamBound::Trait(t) => t
+            .path
+            .segments
+            .last()
+            .filter(|s| s.ident == "AsRef" || s.ident == "Borrow" || s.ident == "Into"),
+        syn::TypeParamBound::Lifetime(_) => None,
+    });
+    let segment = match segment {
+        Some(segment) => segment,
+        None => return unsupported(),
+    };
+    let target = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.iter().collect::<Vec<_>>().as_slice() {
+            [syn::GenericArgument::Type(t)] => t,
+            _ => return unsupported(),
+        },
+        _ => return unsupported(),
+    };
+    if segment.ident == "Into" {
+        return Ok(target.clone());
+    }
+    let owned = match target {
+        syn::Type::Slice(s) => {
+            let elem = &s.elem;
+            syn::parse_quote!(Vec<#elem>)
+        }
+        syn::Type::Path(p) if p.path.is_ident("str") => syn::parse_quote!(String),
+        _ => return unsupported(),
+    };
+    Ok(owned)
 }
 
 fn get_name_and_type_from_fn_arg(
@@ -41,132 +690,1641 @@ fn get_name_and_type_from_fn_arg(
     }
 }
 
-fn implitem_methods_attr_macro(ast: &syn::ItemImpl) -> TokenStream2 {
-    let mut methods = Vec::new();
+/// Returns the number of arguments a method with the given parameters requires,
+/// and the number it accepts at most, which is None if it takes a final
+/// MultiValue collecting the rest of them
+fn arity(tys: &[&syn::Type]) -> (usize, Option<usize>) {
+    let variadic = tys.last().is_some_and(|ty| is_multi_value(ty));
+    let max = if variadic { tys.len() - 1 } else { tys.len() };
+    let required = tys[..max]
+        .iter()
+        .rposition(|ty| optional_type(ty).is_none() && !is_lua_value(ty))
+        .map_or(0, |i| i + 1);
+    (required, if variadic { None } else { Some(max) })
+}
 
-    for item in &ast.items {
-        if let syn::ImplItem::Method(m) = item {
-            let signature = &m.sig;
-            let name = &signature.ident;
-            use syn::FnArg::*;
-            let receiver = match signature.receiver() {
-                Some(Receiver(rcv)) => rcv,
-                Some(Typed(_)) => {
-                    return quote_spanned! {
-                        signature.span() => compile_error!("Cannot currently handle typed receivers (i.e., a receiver other than &self or &mut self)");
+/// Generates a check that a method with the given parameters was called with
+/// a number of arguments it accepts, raising e.g. "Ship:steer: expected 2
+/// arguments (speed: number, wrap: boolean), got 1" otherwise. Trailing nils are
+/// not counted, as they are indistinguishable from missing arguments once taken
+/// directly as values, and parameters after the last one which accepts no nil
+/// are optional. Arguments are taken from `args`, or when taken directly, from
+/// the raw argument values along with any further ones in `rudeboy_rest`. The
+/// error is raised by calling `argument_error` with the message
+fn arity_check(
+    names: &[&syn::Ident],
+    tys: &[&syn::Type],
+    method: &str,
+    fast_path: bool,
+    argument_error: &TokenStream2,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    let variadic = tys.last().is_some_and(|ty| is_multi_value(ty));
+    let max = if variadic { tys.len() - 1 } else { tys.len() };
+    let (required, _) = arity(tys);
+    let params: Vec<_> = names
+        .iter()
+        .zip(tys)
+        .map(|(name, ty)| match is_multi_value(ty) {
+            true => "...".to_string(),
+            false => format!("{}: {}", name, lua_type(ty).unwrap_or_else(|| "any".to_string())),
+        })
+        .collect();
+    let arguments = |n: usize| if n == 1 { "argument" } else { "arguments" };
+    let count = if variadic {
+        format!("at least {} {}", required, arguments(required))
+    } else if required == max {
+        format!("{} {}", max, arguments(max))
+    } else {
+        format!("{} to {} arguments", required, max)
+    };
+    let expected = if params.is_empty() {
+        format!("{}: expected no arguments", method)
+    } else {
+        format!("{}: expected {} ({})", method, count, params.join(", "))
+    };
+
+    let not_nil = quote!(|value| !matches!(value, #rlua::Value::Nil));
+    let got = if !fast_path {
+        quote!(args.iter().rposition(#not_nil).map_or(0, |i| i + 1))
+    } else if names.is_empty() {
+        quote!(rudeboy_rest.iter().rposition(#not_nil).map_or(0, |i| i + 1))
+    } else {
+        let raw_args = (0..names.len()).map(|i| quote::format_ident!("rudeboy_arg{}", i));
+        let taken = names.len();
+        quote! {
+            match rudeboy_rest.iter().rposition(#not_nil) {
+                Some(i) => #taken + i + 1,
+                None => [#( &#raw_args ),*].iter().rposition(#not_nil).map_or(0, |i| i + 1),
+            }
+        }
+    };
+    let check = match (variadic, required) {
+        (true, 0) => return quote!(),
+        (true, _) => quote!(rudeboy_got < #required),
+        (false, _) if required == max => quote!(rudeboy_got != #max),
+        (false, 0) => quote!(rudeboy_got > #max),
+        (false, _) => quote!(!(#required..=#max).contains(&rudeboy_got)),
+    };
+    quote! {
+        let rudeboy_got: usize = #got;
+        if #check {
+            return Err(#argument_error(format!("{}, got {}", #expected, rudeboy_got)));
+        }
+    }
+}
+
+/// Generates the function a memoized method is registered as, which takes the
+/// given target, from which `get_ud` gets the userdata, followed by the
+/// arguments of the method. Its results are cached in a table of the userdata's
+/// caches kept in the Lua registry, which holds the userdata weakly, keyed by
+/// the arguments. Arguments other than nil, booleans, numbers, and strings
+/// cannot be compared by value, so calls taking any are not cached. The given
+/// code converts the arguments in `args_param` and calls the method on `data`
+fn memoized_function(
+    name: &syn::Ident,
+    target: &TokenStream2,
+    get_ud: &TokenStream2,
+    args_param: &TokenStream2,
+    code: &TokenStream2,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    let name = name.to_string();
+    quote! {
+        |ctx, (rudeboy_target, rudeboy_args): (#target, #rlua::MultiValue)| {
+            let rudeboy_ud: #rlua::AnyUserData = #get_ud;
+            let rudeboy_key = ::std::iter::Iterator::collect::<Option<Vec<_>>>(rudeboy_args.iter().map(|value| match value {
+                #rlua::Value::Nil => Some(::std::string::String::from("nil")),
+                #rlua::Value::Boolean(b) => Some(::std::string::ToString::to_string(b)),
+                #rlua::Value::Integer(i) => Some(format!("i{}", i)),
+                #rlua::Value::Number(n) => Some(format!("n{:?}", n)),
+                #rlua::Value::String(s) => Some(format!("s{:?}", s.as_bytes())),
+                _ => None,
+            }))
+            .map(|parts| parts.join(","));
+            let rudeboy_cache = match &rudeboy_key {
+                Some(key) => {
+                    fn get_or_create<'lua>(
+                        ctx: #rlua::Context<'lua>,
+                        table: &#rlua::Table<'lua>,
+                        key: #rlua::Value<'lua>,
+                    ) -> #rlua::Result<#rlua::Table<'lua>> {
+                        match table.get::<_, Option<#rlua::Table>>(key.clone())? {
+                            Some(inner) => Ok(inner),
+                            None => {
+                                let inner = ctx.create_table()?;
+                                table.set(key, inner.clone())?;
+                                Ok(inner)
+                            }
+                        }
                     }
-                }
-                None => {
-                    return quote_spanned! {
-                        signature.span() => compile_error!("Cannot currently handle class level methods");
+                    let caches = match ctx.named_registry_value::<_, Option<#rlua::Table>>("rudeboy_memoize")? {
+                        Some(caches) => caches,
+                        None => {
+                            let caches = ctx.create_table()?;
+                            let weak = ctx.create_table()?;
+                            weak.set("__mode", "k")?;
+                            caches.set_metatable(Some(weak));
+                            ctx.set_named_registry_value("rudeboy_memoize", caches.clone())?;
+                            caches
+                        }
+                    };
+                    let methods = get_or_create(ctx, &caches, #rlua::Value::UserData(rudeboy_ud.clone()))?;
+                    let cache = get_or_create(ctx, &methods, #rlua::Value::String(ctx.create_string(#name)?))?;
+                    if let Some(cached) = cache.get::<_, Option<#rlua::Table>>(key.as_str())? {
+                        let count: usize = cached.get("n")?;
+                        let values = (1..=count).map(|i| cached.get(i)).collect::<#rlua::Result<Vec<#rlua::Value>>>()?;
+                        return Ok(#rlua::MultiValue::from_vec(values));
                     }
+                    Some(cache)
                 }
+                None => None,
             };
-            if receiver.reference.is_none() {
-                return quote_spanned! {
-                    signature.span() => compile_error!("Cannot add a method that moves self");
-                };
+            let data = rudeboy_ud.borrow::<Self>()?;
+            let #args_param = #rlua::FromLuaMulti::from_lua_multi(rudeboy_args, ctx)?;
+            let ret = { #code }?;
+            let ret = #rlua::ToLuaMulti::to_lua_multi(ret, ctx)?;
+            if let (Some(key), Some(cache)) = (rudeboy_key, rudeboy_cache) {
+                let cached = ctx.create_table()?;
+                for (i, value) in ret.iter().enumerate() {
+                    cached.set(i + 1, value.clone())?;
+                }
+                cached.set("n", ret.len())?;
+                cache.set(key, cached)?;
             }
-            let is_mut = receiver.mutability.is_some();
+            Ok(ret)
+        }
+    }
+}
 
-            let inputs_len = signature.inputs.len();
-            let params = if inputs_len == 0 {
-                return quote_spanned! {
-                    signature.span() => compile_error!("Unexpected method with zero parameters");
-                };
-            } else if inputs_len == 1 {
-                Params::None
-            } else if inputs_len == 2 {
-                // Discard receiver
-                let mut input_iter = signature.inputs.iter();
-                let _ = input_iter.next().unwrap();
-                let input = input_iter.next().unwrap();
-                let (name, ty) = match get_name_and_type_from_fn_arg(&input) {
-                    Ok((name, ty)) => (name, ty),
-                    Err(ts) => return ts,
-                };
-                Params::One { name, ty }
+/// Wraps the given expression in `catch_unwind`, converting a panic into a Lua
+/// error carrying the panic message
+fn catch_panic(expr: TokenStream2, rlua: &TokenStream2) -> TokenStream2 {
+    quote! {
+        ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| #expr)).map_err(|payload| {
+            let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                ::std::string::String::from(*s)
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                ::std::clone::Clone::clone(s)
             } else {
-                // Discard receiver
-                let mut input_iter = signature.inputs.iter();
-                let _ = input_iter.next().unwrap();
-
-                let mut names = Vec::new();
-                let mut tys = Vec::new();
-                while let Some(input) = input_iter.next() {
-                    let (name, ty) = match get_name_and_type_from_fn_arg(&input) {
-                        Ok((name, ty)) => (name, ty),
-                        Err(ts) => return ts,
-                    };
-                    names.push(name);
-                    tys.push(ty);
-                }
-                Params::Multi { names, tys }
+                ::std::string::String::from("Unknown panic")
             };
+            #rlua::ExternalError::to_lua_err(format!("Rust panic: {}", message))
+        })
+    }
+}
 
-            methods.push(MethodInfo {
-                name,
-                is_mut,
-                params,
+fn method_info<'a>(
+    m: &'a syn::ImplItemMethod,
+    self_ty: &syn::Type,
+    methods_options: &MethodsOptions,
+) -> Result<MethodInfo<'a>, TokenStream2> {
+    let signature = &m.sig;
+    let name = &signature.ident;
+    use syn::FnArg::*;
+    let receiver = match signature.receiver() {
+        Some(Receiver(rcv)) => rcv,
+        Some(Typed(_)) => {
+            return Err(quote_spanned! {
+                signature.span() => compile_error!("Cannot currently handle typed receivers (i.e., a receiver other than &self or &mut self)");
+            })
+        }
+        None => {
+            return Err(quote_spanned! {
+                signature.span() => compile_error!("Cannot currently handle class level methods");
+            })
+        }
+    };
+    // In builder mode, methods which move self are called on a clone instead
+    let consumes_self = receiver.reference.is_none();
+    if consumes_self && !methods_options.builder {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("Cannot add a method that moves self without the builder option");
+        });
+    }
+    let options = MethodOptions::try_parse(m)?;
+    // Calling an unsafe method from Lua must be a deliberate decision
+    if let Some(unsafety) = &signature.unsafety {
+        if !options.allow_unsafe {
+            return Err(quote_spanned! {
+                unsafety.span() => compile_error!("Cannot add an unsafe method without #[rudeboy(allow_unsafe)]");
             });
         }
     }
+    // Library code has no business printing to the host's stderr, so the warning
+    // of a deprecated method goes to the host's hook, or is traced
+    if options.deprecated.is_some() && methods_options.on_deprecated.is_none() && !methods_options.trace {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A deprecated method requires the on_deprecated or trace option of the methods attribute to report its warning");
+        });
+    }
+    let is_mut = (receiver.reference.is_some() && receiver.mutability.is_some()) || options.force_mut;
+    // Properties and metamethods have a single name, given by other options
+    if let Some(alias) = options.aliases.first() {
+        if options.getter || options.setter.is_some() || options.metamethod.is_some() {
+            return Err(quote_spanned! {
+                alias.span() => compile_error!("An alias cannot be given to a getter, setter, or metamethod");
+            });
+        }
+    }
+
+    let inputs_len = signature.inputs.len();
+    let params = if inputs_len == 0 {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("Unexpected method with zero parameters");
+        });
+    } else if inputs_len == 1 {
+        Params::None
+    } else if inputs_len == 2 {
+        // Discard receiver
+        let mut input_iter = signature.inputs.iter();
+        let _ = input_iter.next().unwrap();
+        let input = input_iter.next().unwrap();
+        let (name, ty) = get_name_and_type_from_fn_arg(input)?;
+        Params::One {
+            name,
+            ty: Box::new(binding_type(&replace_self(ty.as_ref(), self_ty))?),
+        }
+    } else {
+        // Discard receiver
+        let mut input_iter = signature.inputs.iter();
+        let _ = input_iter.next().unwrap();
+
+        let mut names = Vec::new();
+        let mut tys = Vec::new();
+        for input in input_iter {
+            let (name, ty) = get_name_and_type_from_fn_arg(input)?;
+            names.push(name);
+            tys.push(binding_type(&replace_self(ty.as_ref(), self_ty))?);
+        }
+        Params::Multi { names, tys }
+    };
+
+    // The remaining arguments can only be collected once the others are taken
+    let param_tys: Vec<&syn::Type> = match &params {
+        Params::None => Vec::new(),
+        Params::One { ty, .. } => vec![ty],
+        Params::Multi { tys, .. } => tys.iter().collect(),
+    };
+    if let Some((_, ty)) = param_tys.iter().enumerate().find(|(i, ty)| is_multi_value(ty) && *i + 1 != param_tys.len()) {
+        return Err(quote_spanned! {
+            ty.span() => compile_error!("A MultiValue parameter must be the last parameter");
+        });
+    }
+    if options.setter.is_some() && param_tys.iter().any(|ty| is_multi_value(ty)) {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A setter cannot take a MultiValue parameter");
+        });
+    }
+
+    // Getters are read without arguments, so cannot take any or mutate the data
+    if options.getter && (is_mut || consumes_self || !matches!(params, Params::None) || options.metamethod.is_some()) {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A getter must take &self and no other parameters");
+        });
+    }
+    // Setters are given the assigned value
+    if options.setter.is_some()
+        && (options.getter || !is_mut || !matches!(params, Params::One { .. }) || options.metamethod.is_some())
+    {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A setter must take &mut self and one other parameter");
+        });
+    }
+
+    // Cached results are only valid while the data they were computed from is
+    // unchanged, and are returned as they are, so an iterator, or the value a
+    // builder method consuming self returns, would be shared
+    if options.memoize
+        && (is_mut
+            || consumes_self
+            || options.getter
+            || options.setter.is_some()
+            || options.metamethod.is_some()
+            || returns_iterator(signature))
+    {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A memoized method must take &self, and cannot be a getter, setter, or metamethod, or return an iterator");
+        });
+    }
+
+    if options.capability.is_some() && methods_options.capabilities.is_none() {
+        return Err(quote_spanned! {
+            signature.span() => compile_error!("A method requiring a capability needs the capabilities parameter of the methods attribute");
+        });
+    }
+
+    let output = replace_self(&signature.output, self_ty);
+    // With auto_props, every method which could be a getter is one, unless it
+    // is marked to stay a method or given names only methods have
+    let auto_prop = methods_options.auto_props
+        && !options.method
+        && !is_mut
+        && !consumes_self
+        && matches!(params, Params::None)
+        && !matches!(output, syn::ReturnType::Default)
+        && options.metamethod.is_none()
+        && options.setter.is_none()
+        && options.aliases.is_empty()
+        && !options.memoize
+        && !returns_iterator(signature);
+    let result_error = ResultError::try_parse(
+        signature,
+        options.map_err,
+        methods_options.map_err.as_ref(),
+        options.std_error || methods_options.std_error,
+        &methods_options.paths,
+    )?;
+    let validations = signature
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            Typed(t) => Some(ParamOptions::try_parse(t)),
+            Receiver(_) => None,
+        })
+        .collect::<Result<_, _>>()?;
+
+    let getter = options.getter || auto_prop;
+    let prefix = if getter { &methods_options.prefix } else { methods_options.method_prefix() };
+    Ok(MethodInfo {
+        name,
+        lua_name: format!("{}{}", prefix, name),
+        cfgs: cfg_attrs(&m.attrs),
+        is_mut,
+        consumes_self,
+        is_unsafe: signature.unsafety.is_some(),
+        getter,
+        setter: options.setter.map(|setter| format!("{}{}", methods_options.prefix, setter)),
+        deprecated: options.deprecated,
+        memoize: options.memoize,
+        capability: options.capability,
+        metamethod: options.metamethod,
+        returns_iterator: returns_iterator(signature),
+        // Errors returned along with nil are part of the converted value
+        returns_userdata: (options.userdata || returns_self(&output, self_ty))
+            && !matches!(result_error, Some(ResultError::Returned)),
+        returns_cow: returned_value_type(&output).is_some_and(is_cow_str),
+        returns_set: returned_value_type(&output).is_some_and(is_set),
+        returns_option: returned_type(&output).and_then(optional_type).is_some(),
+        result_error,
+        params,
+        validations,
+        output,
+        docs: doc_lines(&m.attrs),
+    })
+}
+
+/// Generates an expression checking that the given Lua value may be converted
+/// into the given type without coercion, evaluating to a `rlua::Result` of the
+/// value. Returns None if the type is not a primitive numeric type
+fn strict_number_check(ty: &syn::Type, value: &TokenStream2, rlua: &TokenStream2) -> Option<TokenStream2> {
+    let ident = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.get_ident()?.to_string(),
+        _ => return None,
+    };
+    let (number_arm, message) = match ident.as_str() {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => (
+            quote!(#rlua::Value::Number(n) if n.fract() == 0.0 => Ok(#rlua::Value::Number(n)),),
+            "expected an integer",
+        ),
+        "f32" | "f64" => (quote!(value @ #rlua::Value::Number(_) => Ok(value),), "expected a number"),
+        _ => return None,
+    };
+    Some(quote! {
+        match #value {
+            value @ #rlua::Value::Integer(_) => Ok(value),
+            #number_arm
+            value => Err(#rlua::Error::FromLuaConversionError {
+                from: match value {
+                    #rlua::Value::Number(_) => "fractional number",
+                    value => value.type_name(),
+                },
+                to: #ident,
+                message: Some(::std::string::String::from(#message)),
+            }),
+        }
+    })
+}
+
+/// Generates the checks of a converted argument requested by its parameter's
+/// options, which raise an error describing the failed check before the method
+/// is called. Unlike a failed conversion, this is raised as an error of the
+/// method itself, since it is the value of the argument which is rejected
+fn validation_code(
+    options: &ParamOptions,
+    name: &syn::Ident,
+    ty: &syn::Type,
+    position: usize,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    let name_str = name.to_string();
+    let fail = |message: TokenStream2| {
+        quote! {
+            return Err(#rlua::ExternalError::to_lua_err(argument_message(#position, #name_str, &#message)));
+        }
+    };
+    let is_float = matches!(ty, syn::Type::Path(p) if p.path.is_ident("f32") || p.path.is_ident("f64"));
+    // Bounds are given the parameter's type, so that one it cannot hold, e.g.
+    // 300 for a u8 or 0.5 for an integer, fails to compile. Unsuffixed integer
+    // literals are accepted for floats, e.g. `min = 0` for an f64
+    let typed = |bound: &syn::Expr| match bound {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(i), .. }) if is_float && i.suffix().is_empty() => {
+            let lit = syn::LitFloat::new(&format!("{}.0", i.base10_digits()), i.span());
+            quote!(#lit)
+        }
+        bound => quote!(#bound),
+    };
+    let mut checks = Vec::new();
+    // NaN compares false with every bound, and so would pass any range
+    if is_float && (options.min.is_some() || options.max.is_some()) {
+        let fail = fail(quote!("must be a number, got NaN"));
+        checks.push(quote_spanned! {
+            ty.span() => if #name.is_nan() { #fail }
+        });
+    }
+    if let Some((min, text)) = &options.min {
+        let message = format!("must be at least {}, got {{}}", text);
+        let fail = fail(quote!(format!(#message, #name)));
+        let bound = typed(min);
+        checks.push(quote_spanned! {
+            min.span() => {
+                let rudeboy_min: #ty = #bound;
+                if #name < rudeboy_min { #fail }
+            }
+        });
+    }
+    if let Some((max, text)) = &options.max {
+        let message = format!("must be at most {}, got {{}}", text);
+        let fail = fail(quote!(format!(#message, #name)));
+        let bound = typed(max);
+        checks.push(quote_spanned! {
+            max.span() => {
+                let rudeboy_max: #ty = #bound;
+                if #name > rudeboy_max { #fail }
+            }
+        });
+    }
+    if options.non_empty {
+        let fail = fail(quote!("must not be empty"));
+        checks.push(quote_spanned! {
+            ty.span() => if #name.is_empty() { #fail }
+        });
+    }
+    quote!(#( #checks )*)
+}
+
+/// Returns the names of everything registered on the userdata: the exported
+/// methods, or the namespace holding them, the metamethods, and the help method.
+/// Methods behind `cfg` attributes are listed whether or not they are enabled
+fn export_names(methods: &[MethodInfo], options: &MethodsOptions) -> Vec<String> {
+    let mut names = Vec::new();
+    for m in methods.iter().filter(|m| !m.is_property()) {
+        match &m.metamethod {
+            Some(metamethod) => names.push(format!("__{}", metamethod.to_string().to_lowercase())),
+            None if options.namespace.is_none() => names.push(m.lua_name.clone()),
+            None => (),
+        }
+    }
+    if let Some(namespace) = &options.namespace {
+        names.push(namespace.clone());
+    }
+    if options.help {
+        names.push(options.help_name());
+    }
+    names
+}
 
+/// Returns the Lua names of the methods registered on the userdata by the
+/// given impl block, i.e. not counting metamethods or namespaced methods, along
+/// with the number of arguments each requires and accepts at most
+fn method_arities(methods: &[MethodInfo], options: &MethodsOptions) -> Vec<TokenStream2> {
+    let mut arities = Vec::new();
+    match &options.namespace {
+        Some(namespace) => arities.push(quote!((#namespace, 0, Some(0)))),
+        None => {
+            for m in methods.iter().filter(|m| m.metamethod.is_none() && !m.is_property()) {
+                let tys: Vec<_> = match &m.params {
+                    Params::None => Vec::new(),
+                    Params::One { ty, .. } => vec![ty.as_ref()],
+                    Params::Multi { tys, .. } => tys.iter().collect(),
+                };
+                let lua_name = &m.lua_name;
+                let (required, max) = arity(&tys);
+                let max = match max {
+                    Some(max) => quote!(Some(#max)),
+                    None => quote!(None),
+                };
+                arities.push(quote!((#lua_name, #required, #max)));
+            }
+        }
+    }
+    if options.help {
+        let help = options.help_name();
+        arities.push(quote!((#help, 0, Some(1))));
+    }
+    arities
+}
+
+/// Generates a `help(name)` method, which returns the signature and doc comment
+/// of the named method, or the signatures of every method if given no name.
+/// The method is always registered on the userdata itself, even when the other
+/// methods are namespaced
+fn help_code(methods: &[MethodInfo], options: &MethodsOptions) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    let help = options.help_name();
+    let entries = methods.iter().filter(|m| m.metamethod.is_none() && !m.is_property()).map(|m| {
+        let params: Vec<_> = match &m.params {
+            Params::None => Vec::new(),
+            Params::One { name, ty } => vec![format!("{}: {}", name, type_string(ty))],
+            Params::Multi { names, tys } => names
+                .iter()
+                .zip(tys)
+                .map(|(name, ty)| format!("{}: {}", name, type_string(ty)))
+                .collect(),
+        };
+        let mut signature = format!("{}({})", m.lua_name, params.join(", "));
+        if let syn::ReturnType::Type(_, ty) = &m.output {
+            signature.push_str(&format!(" -> {}", type_string(ty)));
+        }
+        let docs = m.docs.join("\n");
+        let lua_name = &m.lua_name;
+        let cfgs = &m.cfgs;
+        let entry = m.gated(quote!(entries.push((#lua_name, #signature, #docs));), options);
+        quote! {
+            #( #cfgs )*
+            #entry
+        }
+    });
+    quote! {
+        _methods.add_method(#help, |_, _, name: Option<String>| {
+            #[allow(unused_mut)]
+            let mut entries: Vec<(&str, &str, &str)> = Vec::new();
+            #( #entries )*
+            match name {
+                None => Ok(entries.iter().map(|(_, signature, _)| *signature).collect::<Vec<_>>().join("\n")),
+                Some(name) => match entries.iter().find(|(lua_name, _, _)| *lua_name == name) {
+                    Some((_, signature, docs)) if docs.is_empty() => Ok(::std::string::String::from(*signature)),
+                    Some((_, signature, docs)) => Ok(format!("{}\n\n{}", signature, docs)),
+                    None => {
+                        Err(#rlua::ExternalError::to_lua_err(format!("No such method: {}", name)))
+                    }
+                },
+            }
+        });
+    }
+}
+
+/// Describes the methods and constants exported from the given impl block
+fn methods_manifest(ast: &syn::ItemImpl, methods: &[MethodInfo], consts: &[Constant], options: &MethodsOptions) -> Json {
+    let methods = methods.iter().map(|m| {
+        let params = match &m.params {
+            Params::None => Vec::new(),
+            Params::One { name, ty } => vec![(*name, ty.as_ref())],
+            Params::Multi { names, tys } => names.iter().copied().zip(tys).collect(),
+        };
+        let returns = match &m.output {
+            syn::ReturnType::Default => "()".to_string(),
+            syn::ReturnType::Type(_, ty) => type_string(ty),
+        };
+        method_manifest(&m.lua_name, m.metamethod.as_ref().map(|mm| mm.to_string()), params, returns)
+    });
+    Json::Object(vec![
+        ("type", Json::str(type_string(&ast.self_ty))),
+        ("namespace", options.namespace.as_ref().map_or(Json::Null, Json::str)),
+        ("methods", Json::Array(methods.collect())),
+        ("constants", Json::Array(consts.iter().map(|(c, _)| Json::str(c.to_string())).collect())),
+    ])
+}
+
+/// Writes an LDoc stub documenting the methods and constants exported from the
+/// given impl block
+fn methods_ldoc(ast: &syn::ItemImpl, methods: &[MethodInfo], consts: &[Constant], options: &MethodsOptions) -> TokenStream2 {
+    let self_ty = &ast.self_ty;
+    // The class is named after the type without its path or generic arguments,
+    // so that it is a valid Lua identifier
+    let class = match self_ty.as_ref() {
+        syn::Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+    .unwrap_or_else(|| type_string(self_ty));
+    let mut functions = Vec::new();
+    let mut namespaced = Vec::new();
+    for m in methods.iter().filter(|m| !m.is_property()) {
+        let params = match &m.params {
+            Params::None => Vec::new(),
+            Params::One { name, ty } => vec![(*name, ty.as_ref())],
+            Params::Multi { names, tys } => names.iter().copied().zip(tys).collect(),
+        };
+        let params = params
+            .into_iter()
+            .map(|(name, ty)| (name.to_string(), lua_type(ty).unwrap_or_else(|| "nil".to_string())))
+            .collect();
+        let returns = match &m.output {
+            _ if m.returns_iterator => Some("function".to_string()),
+            syn::ReturnType::Default => None,
+            syn::ReturnType::Type(_, ty) => lua_type(ty),
+        };
+        let name = match &m.metamethod {
+            Some(metamethod) => format!("__{}", metamethod.to_string().to_lowercase()),
+            None => m.lua_name.clone(),
+        };
+        let function = LDocFunction {
+            name,
+            docs: m.docs.clone(),
+            params,
+            returns,
+        };
+        if options.namespace.is_some() && m.metamethod.is_none() {
+            namespaced.push(function);
+        } else {
+            functions.push(function);
+        }
+    }
+    let namespace = options.namespace.as_deref().map(|n| (n, namespaced.as_slice()));
+    let constants: Vec<_> = consts.iter().map(|(c, _)| c.to_string()).collect();
+    write_ldoc(self_ty.span(), &class, &doc_lines(&ast.attrs), &functions, namespace, &constants)
+}
+
+/// Collects the methods of the impl block which are exported, along with the
+/// warnings for those skipped in lenient mode
+pub(crate) fn exported_methods<'a>(
+    ast: &'a syn::ItemImpl,
+    options: &MethodsOptions,
+) -> Result<(Vec<MethodInfo<'a>>, Vec<TokenStream2>), TokenStream2> {
+    let mut methods = Vec::new();
+    let mut warnings = Vec::new();
+    for item in &ast.items {
+        if let syn::ImplItem::Method(m) = item {
+            let method_options = MethodOptions::try_parse(m)?;
+            if !method_options.is_exported(m) {
+                continue;
+            }
+            match method_info(m, &ast.self_ty, options) {
+                Ok(mi) => {
+                    // Each alias registers the method again under its name
+                    let aliases: Vec<_> = method_options
+                        .aliases
+                        .iter()
+                        .map(|alias| MethodInfo {
+                            lua_name: format!("{}{}", options.method_prefix(), alias.value()),
+                            ..mi.clone()
+                        })
+                        .collect();
+                    methods.push(mi);
+                    methods.extend(aliases);
+                }
+                Err(_) if options.lenient => warnings.push(warning(
+                    m.sig.span(),
+                    &format!(
+                        "`{}` cannot be exported to Lua and was skipped",
+                        m.sig.ident
+                    ),
+                )),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok((methods, warnings))
+}
+
+/// The code registering the exported methods of a type
+struct Registrations {
+    /// Statements registering the methods and metamethods on `_methods`
+    methods: TokenStream2,
+    /// Statements adding the functions of the dot_call option to `table`
+    dot_fns: Vec<TokenStream2>,
+    /// The match arms of `rudeboy_get_property` reading each getter
+    getter_arms: Vec<TokenStream2>,
+    /// The match arms of `rudeboy_set_property` assigning each setter
+    setter_arms: Vec<TokenStream2>,
+}
+
+/// Generates the code registering the given exported methods, which errors
+/// raised by the methods name as methods of `self_ty`, and which raise the
+/// errors of wrong argument counts and failed argument conversions by calling
+/// `argument_error` with the message
+fn registrations(
+    mut methods: Vec<MethodInfo>,
+    self_ty: &syn::Type,
+    argument_error: &TokenStream2,
+    options: &MethodsOptions,
+) -> Registrations {
+    let rlua = &options.paths.rlua;
+    let from_lua = options.paths.trait_from_lua();
+    let mut meta_mqs = Vec::new();
+    let mut dot_fns = Vec::new();
+    let mut getter_arms = Vec::new();
+    let mut setter_arms = Vec::new();
     let mqs: Vec<_> = methods
         .drain(..)
-        .map(|m| {
-            let call = if m.is_mut {
+        .enumerate()
+        .filter_map(|(index, m)| {
+            let (names, tys): (Vec<_>, Vec<_>) = match &m.params {
+                Params::None => (Vec::new(), Vec::new()),
+                Params::One { name, ty } => (vec![*name], vec![ty.as_ref()]),
+                Params::Multi { names, tys } => (names.clone(), tys.iter().collect()),
+            };
+
+            let method_params = match &m.params {
+                Params::None => quote!(()),
+                Params::One { name, .. } => quote!((#name)),
+                Params::Multi { names, .. } => quote!((#(#names,)*)),
+            };
+
+            let name = m.name;
+            let lua_name = &m.lua_name;
+
+            let call = if m.consumes_self {
+                quote!(::std::clone::Clone::clone(&*data).#name #method_params)
+            } else {
+                quote!(data.#name #method_params)
+            };
+            let call = if m.is_unsafe { quote!(unsafe { #call }) } else { call };
+            let body = if m.returns_iterator {
+                // Iterators are collected, as they may borrow the data, and
+                // returned as an iterator function over the collected values
+                let items = if options.catch_panic {
+                    let call = catch_panic(call, rlua);
+                    quote!(#call?)
+                } else {
+                    call
+                };
+                let iterate = cached_function("rudeboy_iterator", ITERATOR_CHUNK, rlua);
                 quote! {
-                    _methods.add_method_mut
+                    let items = ctx.create_sequence_from(#items)?;
+                    let iterator: #rlua::Function = #iterate?.call(items)?;
+                    Ok(iterator)
                 }
             } else {
-                quote! {
-                    _methods.add_method
+                // The result of the call as an `rlua::Result`. Methods which
+                // already return a `Result` have it forwarded rather than
+                // wrapped, with the error converted into an `rlua::Error`
+                let call = match m.result_error.as_ref().and_then(|e| e.conversion(rlua)) {
+                    Some(conversion) => quote!(#call.map_err(#conversion)),
+                    None => call,
+                };
+                let raised = !matches!(m.result_error, None | Some(ResultError::Returned));
+                let result = match (options.catch_panic, raised) {
+                    (false, false) => quote!(Ok(#call)),
+                    (false, true) => call,
+                    (true, false) => catch_panic(call, rlua),
+                    (true, true) => {
+                        let call = catch_panic(call, rlua);
+                        quote!(#call.and_then(|ret| ret))
+                    }
+                };
+                // The conversion of the returned value once any `Result` and
+                // `Option` around it are removed, if it is not left to rlua
+                let integer = returned_value_type(&m.output)
+                    .and_then(|ty| options.integers.to_lua_conversion(ty, &quote!(ret), rlua));
+                let conversion = match &options.paths.to_lua {
+                    _ if integer.is_some() => integer,
+                    _ if m.returns_ecosystem_type() => {
+                        returned_value_type(&m.output).and_then(|ty| ecosystem::to_lua_conversion(ty, &quote!(ret), rlua))
+                    }
+                    // User data is created directly, since an overridden
+                    // conversion trait need not cover it
+                    _ if m.returns_userdata => Some(quote!(ctx.create_userdata(ret))),
+                    // Strings are created from the borrowed contents, which need
+                    // not be copied into an owned string first
+                    _ if m.returns_cow => Some(quote!(ctx.create_string(&*ret))),
+                    // Sets become sequences, in the set's order of iteration,
+                    // which a BTreeSet sorts
+                    _ if m.returns_set => Some(quote!(ctx.create_sequence_from(ret).map(#rlua::Value::Table))),
+                    // Return values are only converted explicitly when the trait
+                    // has been overridden, since rlua otherwise accepts any
+                    // ToLuaMulti
+                    Some(to_lua) => Some(quote!(#to_lua::to_lua(ret, ctx))),
+                    None => None,
+                };
+                let conversion = match conversion {
+                    Some(conversion) if m.returns_option => Some(quote! {
+                        match ret {
+                            Some(ret) => #conversion.map(Some),
+                            None => Ok(None),
+                        }
+                    }),
+                    conversion => conversion,
+                };
+                match conversion {
+                    // Errors returned along with nil are returned after the
+                    // converted value in the same way
+                    Some(conversion) if matches!(m.result_error, Some(ResultError::Returned)) => quote! {
+                        #result.and_then(|ret| match ret {
+                            Ok(ret) => #conversion.map(Ok),
+                            Err(error) => Ok(Err(error)),
+                        })
+                    },
+                    Some(conversion) => quote!(#result.and_then(|ret| #conversion)),
+                    None => result,
                 }
             };
+            let uses_ctx = m.returns_iterator
+                || m.returns_userdata
+                || m.returns_cow
+                || m.returns_set
+                || m.returns_ecosystem_type()
+                || returned_value_type(&m.output).is_some_and(|ty| options.integers.converts(ty))
+                || !names.is_empty()
+                || options.paths.to_lua.is_some();
+            let ctx = if uses_ctx {
+                quote!(ctx)
+            } else {
+                quote!(_)
+            };
 
-            let params_param = match &m.params {
-                Params::None => quote!(()),
-                Params::One { name, ty } => quote!(#name : #ty),
-                Params::Multi { names, tys } => quote! {
-                    ( #( #names, )* ) : ( #( #tys, )* )
-                },
+            let trace = if options.trace {
+                let param_names: Vec<_> = names.iter().map(|n| n.to_string()).collect();
+                trace_span(&quote!(#self_ty), &name.to_string(), &param_names)
+            } else {
+                quote!()
+            };
+            // Deprecated methods warn once per Lua name, however often they are
+            // called, so the warning is traced along with the call
+            let trace = match &m.deprecated {
+                Some(message) => {
+                    let warning = format!("{}:{} is deprecated: {}", type_string(self_ty), lua_name, message);
+                    let warn = match &options.on_deprecated {
+                        Some(on_deprecated) => quote_spanned!(on_deprecated.span() => #on_deprecated(#warning)),
+                        None => quote!(::tracing::warn!("{}", #warning)),
+                    };
+                    quote! {
+                        {
+                            static WARNED: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(false);
+                            if !WARNED.swap(true, ::std::sync::atomic::Ordering::Relaxed) {
+                                #warn;
+                            }
+                        }
+                        #trace
+                    }
+                }
+                None => trace,
             };
 
-            let method_params = match &m.params {
-                Params::None => quote!(()),
-                Params::One { name, .. } => quote!((#name)),
-                Params::Multi { names, .. } => quote!((#(#names,)*)),
+            // Arguments are converted one at a time, so that a failed conversion
+            // can be reported along with the method and argument it occurred in.
+            // Up to two arguments are taken directly as values, avoiding the
+            // iteration over a MultiValue needed for longer argument lists, and
+            // for a final MultiValue parameter collecting the rest of them
+            let fast_path = names.len() <= 2 && !tys.last().is_some_and(|ty| is_multi_value(ty));
+            let raw_args: Vec<_> = (0..names.len())
+                .map(|i| syn::Ident::new(&format!("rudeboy_arg{}", i), proc_macro2::Span::call_site()))
+                .collect();
+            // The number of arguments is checked against the parameters, except
+            // for metamethods, whose arguments are given by Lua itself, and
+            // properties, which are given a single value if any. Any
+            // arguments beyond those taken directly are collected to count them
+            let checks_arity = m.metamethod.is_none() && !m.is_property();
+            let (rest_pat, rest_ty) = if checks_arity && fast_path {
+                (quote!(rudeboy_rest,), quote!(#rlua::MultiValue,))
+            } else {
+                (quote!(), quote!())
+            };
+            let value_tys = raw_args.iter().map(|_| quote!(#rlua::Value));
+            let args_param = if names.is_empty() && !checks_arity {
+                quote!(())
+            } else if names.is_empty() {
+                quote!(rudeboy_rest: #rlua::MultiValue)
+            } else if names.len() == 1 && fast_path && !checks_arity {
+                quote!(#( #raw_args )*: #rlua::Value)
+            } else if fast_path {
+                quote!(( #( #raw_args, )* #rest_pat ): ( #( #value_tys, )* #rest_ty ))
+            } else {
+                quote!(args: #rlua::MultiValue)
+            };
+            let type_name = quote!(#self_ty).to_string().replace(' ', "");
+            let arity_check = if checks_arity {
+                arity_check(&names, &tys, &format!("{}:{}", type_name, lua_name), fast_path, argument_error, rlua)
+            } else {
+                quote!()
+            };
+            let conversions = names.iter().zip(&tys).zip(&raw_args).enumerate().map(|(i, ((name, ty), raw))| {
+                let position = i + 1;
+                let name_str = name.to_string();
+                let value = if fast_path {
+                    quote!(#raw)
+                } else {
+                    quote!(args.next().unwrap_or(#rlua::Value::Nil))
+                };
+                if let Some((elem, mutability)) = userdata_reference(ty) {
+                    // References are taken to the value of a userdata argument,
+                    // which is borrowed for the duration of the call
+                    let elem_str = type_string(elem);
+                    let ud = quote::format_ident!("rudeboy_ud{}", position);
+                    let guard = quote::format_ident!("rudeboy_ref{}", position);
+                    let (borrow, reference) = match mutability {
+                        Some(_) => (quote!(borrow_mut), quote!(&mut *#guard)),
+                        None => (quote!(borrow), quote!(&*#guard)),
+                    };
+                    return quote_spanned! {
+                        ty.span() =>
+                        let #ud = <#rlua::AnyUserData as #rlua::FromLua>::from_lua(#value, ctx)
+                            .map_err(|error| argument_error(#position, #name_str, match error {
+                                #rlua::Error::FromLuaConversionError { from, message, .. } => {
+                                    #rlua::Error::FromLuaConversionError { from, to: #elem_str, message }
+                                }
+                                error => error,
+                            }))?;
+                        #[allow(unused_mut)]
+                        let mut #guard = #ud.#borrow::<#elem>()
+                            .map_err(|error| argument_error(#position, #name_str, match error {
+                                #rlua::Error::UserDataTypeMismatch => #rlua::Error::FromLuaConversionError {
+                                    from: "userdata",
+                                    to: #elem_str,
+                                    message: None,
+                                },
+                                error => error,
+                            }))?;
+                        let #name = #reference;
+                    };
+                }
+                if let Some(elem) = slice_reference(ty) {
+                    // Slices are converted from a Lua sequence (or a string, for
+                    // bytes as strings) into a vector, which the slice is then
+                    // taken from
+                    let items = quote::format_ident!("rudeboy_items{}", position);
+                    let vec: syn::Type = syn::parse_quote!(Vec<#elem>);
+                    let conversion = ecosystem::from_lua_conversion(&vec, &value, rlua)
+                        .unwrap_or_else(|| quote!(<#vec as #from_lua>::from_lua(#value, ctx)));
+                    return quote_spanned! {
+                        ty.span() =>
+                        let #items = #conversion
+                            .map_err(|error| argument_error(#position, #name_str, error))?;
+                        let #name = &#items[..];
+                    };
+                }
+                if is_multi_value(ty) {
+                    return quote!(let #name = #rlua::MultiValue::from_vec(::std::iter::Iterator::collect(args));)
+                }
+                if is_lua_value(ty) {
+                    // The value is left untyped, since the lifetime named by
+                    // the parameter is not in scope here
+                    return quote!(let #name = #value;);
+                }
+                if let Some(conversion) = ecosystem::from_lua_conversion(ty, &value, rlua) {
+                    return quote_spanned! {
+                        ty.span() => let #name: #ty = #conversion
+                            .map_err(|error| argument_error(#position, #name_str, error))?;
+                    };
+                }
+                if is_cow_str(ty) {
+                    // Strings are always owned once taken from Lua
+                    return quote_spanned! {
+                        ty.span() => let #name: #ty = ::std::borrow::Cow::Owned(
+                            <String as #from_lua>::from_lua(#value, ctx)
+                                .map_err(|error| argument_error(#position, #name_str, error))?
+                        );
+                    };
+                }
+                let integer = checked_integer_conversion(ty, &quote!(value), "parameter", &name_str, rlua);
+                if let Some(parse) = options.integers.string_conversion(ty, "parameter", &name_str, rlua) {
+                    // Strings are parsed, and other values converted as usual
+                    let mut conversion = integer.unwrap_or_else(|| quote!(<#ty as #from_lua>::from_lua(value, ctx)));
+                    if options.strict_numbers {
+                        if let Some(checked) = strict_number_check(ty, &quote!(value), rlua) {
+                            conversion = quote!(#checked.and_then(|value| #conversion));
+                        }
+                    }
+                    return quote_spanned! {
+                        ty.span() => let #name: #ty = match #value {
+                            #rlua::Value::String(s) => #parse,
+                            value => #conversion,
+                        }
+                        .map_err(|error| argument_error(#position, #name_str, error))?;
+                    };
+                }
+                if options.strict_numbers {
+                    if let Some(checked) = strict_number_check(ty, &value, rlua) {
+                        let conversion = integer.unwrap_or_else(|| quote!(<#ty as #from_lua>::from_lua(value, ctx)));
+                        return quote_spanned! {
+                            ty.span() => let #name: #ty = #checked
+                                .and_then(|value| #conversion)
+                                .map_err(|error| argument_error(#position, #name_str, error))?;
+                        };
+                    }
+                }
+                if let Some(conversion) = integer {
+                    return quote_spanned! {
+                        ty.span() => let #name: #ty = {
+                            let value = #value;
+                            #conversion
+                        }
+                        .map_err(|error| argument_error(#position, #name_str, error))?;
+                    };
+                }
+                // Spanned at the parameter's type, so that a type which cannot
+                // be converted from Lua is reported there
+                quote_spanned! {
+                    ty.span() => let #name: #ty = <#ty as #from_lua>::from_lua(#value, ctx)
+                        .map_err(|error| argument_error(#position, #name_str, error))?;
+                }
+            });
+            let conversions = conversions.zip(&m.validations).zip(&names).zip(&tys).enumerate().map(
+                |(i, (((conversion, validation), name), ty))| {
+                    let checks = validation_code(validation, name, ty, i + 1, rlua);
+                    quote!(#conversion #checks)
+                },
+            );
+            let args_iter = if fast_path {
+                quote!()
+            } else {
+                quote!(let mut args = args.into_iter();)
+            };
+            let convert_args = if names.is_empty() {
+                arity_check
+            } else {
+                quote! {
+                    #arity_check
+                    let argument_message = |position: usize, name: &str, detail: &dyn ::std::fmt::Display| {
+                        format!("{}:{}: argument #{} ({}): {}", #type_name, #lua_name, position, name, detail)
+                    };
+                    let argument_error = |position: usize, name: &str, error: #rlua::Error| {
+                        let detail = match &error {
+                            #rlua::Error::FromLuaConversionError { from, to, .. } => {
+                                format!("expected {}, got {}", to, from)
+                            }
+                            error => ::std::string::ToString::to_string(error),
+                        };
+                        #argument_error(argument_message(position, name, &detail))
+                    };
+                    #args_iter
+                    #( #conversions )*
+                }
             };
 
-            let name = m.name;
+            // The registration calls are spanned at the return type, so that a
+            // type which cannot be converted into Lua is reported there
+            let return_span = match &m.output {
+                syn::ReturnType::Type(_, ty) => ty.span(),
+                syn::ReturnType::Default => name.span(),
+            };
+            let cfgs = &m.cfgs;
+            let record = |code: TokenStream2| {
+                if options.stats {
+                    record_stats(index, code, rlua)
+                } else {
+                    code
+                }
+            };
+            // Properties are read and assigned as a single value, which has no
+            // room for an error, so errors which methods would return are
+            // raised instead
+            let returned = if matches!(m.result_error, Some(ResultError::Returned)) {
+                quote! {
+                    .and_then(|ret| ret.map_err(|error| {
+                        #rlua::Error::external(::std::string::ToString::to_string(&error))
+                    }))
+                }
+            } else {
+                quote!()
+            };
+            // Properties requiring a capability are matched only if the host
+            // grants it, which is checked whenever they are accessed
+            let guard = m.capability_check(options).map(|check| quote!(if #check));
+            if m.getter {
+                // Getters are matched by the property function called from the
+                // Index metamethod, so they are converted into a single value
+                let code = record(quote! {
+                    #trace
+                    #body
+                });
+                let to_lua = quote_spanned!(return_span => #rlua::ToLua::to_lua);
+                getter_arms.push(quote! {
+                    #( #cfgs )*
+                    #lua_name #guard => {
+                        let result = { #code };
+                        result #returned .and_then(|ret| #to_lua(ret, ctx)).map(Some)
+                    }
+                });
+                None
+            } else if let Some(property) = &m.setter {
+                // Setters are matched by the property function called from the
+                // NewIndex metamethod, which passes the assigned value as the
+                // only argument
+                let code = record(quote! {
+                    #convert_args
+                    #trace
+                    #body
+                });
+                setter_arms.push(quote! {
+                    #( #cfgs )*
+                    #property #guard => {
+                        let rudeboy_arg0 = value;
+                        let result = { #code };
+                        result #returned .map(|_| true)
+                    }
+                });
+                None
+            } else if let Some(metamethod) = &m.metamethod {
+                // Metamethods are always registered on the userdata itself, even
+                // when the other methods are namespaced
+                let call = if m.is_mut {
+                    quote_spanned!(return_span => _methods.add_meta_method_mut)
+                } else {
+                    quote_spanned!(return_span => _methods.add_meta_method)
+                };
 
-            quote! {
-                #call (stringify!(#name), |_, data, #params_param| {
-                    Ok(data.#name #method_params)
+                let code = record(quote! {
+                    #convert_args
+                    #trace
+                    #body
+                });
+                let registration = m.gated(
+                    quote! {
+                        #call (#rlua::MetaMethod::#metamethod, |#ctx, data, #args_param| {
+                            #code
+                        });
+                    },
+                    options,
+                );
+                meta_mqs.push(quote! {
+                    #( #cfgs )*
+                    #registration
+                });
+                None
+            } else if m.memoize {
+                // Memoized methods are functions taking the userdata, or the
+                // namespace or class table holding it, which look up the
+                // cached results before borrowing the data
+                let code = record(quote! {
+                    #convert_args
+                    #trace
+                    #body
                 });
+                let create_function = quote_spanned!(return_span => ctx.create_function);
+                if options.dot_call {
+                    let function = memoized_function(name, &quote!(#rlua::AnyUserData), &quote!(rudeboy_target), &args_param, &code, rlua);
+                    let registration = m.gated(quote!(table.set(#lua_name, #create_function(#function)?)?;), options);
+                    dot_fns.push(quote! {
+                        #( #cfgs )*
+                        #registration
+                    });
+                }
+                Some(if options.namespace.is_some() {
+                    let function = memoized_function(
+                        name,
+                        &quote!(#rlua::Table),
+                        &quote!(rudeboy_target.get("__self")?),
+                        &args_param,
+                        &code,
+                        rlua,
+                    );
+                    let registration = m.gated(quote!(namespace.set(#lua_name, #create_function(#function)?)?;), options);
+                    quote! {
+                        #( #cfgs )*
+                        #registration
+                    }
+                } else {
+                    let function = memoized_function(name, &quote!(#rlua::AnyUserData), &quote!(rudeboy_target), &args_param, &code, rlua);
+                    let call = quote_spanned!(return_span => _methods.add_function);
+                    let registration = m.gated(quote!(#call (#lua_name, #function);), options);
+                    quote! {
+                        #( #cfgs )*
+                        #registration
+                    }
+                })
+            } else {
+                let method_registration = if options.namespace.is_some() {
+                    // Namespaced methods are plain functions stored in the namespace
+                    // table, which holds the userdata they operate on
+                    let namespace_param = if names.is_empty() {
+                        quote!((namespace, rudeboy_rest): (#rlua::Table, #rlua::MultiValue))
+                    } else if fast_path {
+                        let value_tys = raw_args.iter().map(|_| quote!(#rlua::Value));
+                        quote!((namespace, #( #raw_args, )* #rest_pat): (#rlua::Table, #( #value_tys, )* #rest_ty))
+                    } else {
+                        quote!((namespace, args): (#rlua::Table, #rlua::MultiValue))
+                    };
+                    let borrow = if m.is_mut {
+                        quote!(let mut data = ud.borrow_mut::<Self>()?;)
+                    } else {
+                        quote!(let data = ud.borrow::<Self>()?;)
+                    };
+                    let create_function = quote_spanned!(return_span => ctx.create_function);
+                    let code = record(quote! {
+                        #convert_args
+                        let ud: #rlua::AnyUserData = namespace.get("__self")?;
+                        #borrow
+                        #trace
+                        #body
+                    });
+                    let registration = m.gated(
+                        quote! {
+                            namespace.set(#lua_name, #create_function(|#ctx, #namespace_param| {
+                                #code
+                            })?)?;
+                        },
+                        options,
+                    );
+                    quote! {
+                        #( #cfgs )*
+                        #registration
+                    }
+                } else {
+                    let call = if m.is_mut {
+                        quote_spanned! {
+                            return_span => _methods.add_method_mut
+                        }
+                    } else {
+                        quote_spanned! {
+                            return_span => _methods.add_method
+                        }
+                    };
+
+                    let code = record(quote! {
+                        #convert_args
+                        #trace
+                        #body
+                    });
+                    let registration = m.gated(
+                        quote! {
+                            #call (#lua_name, |#ctx, data, #args_param| {
+                                #code
+                            });
+                        },
+                        options,
+                    );
+                    quote! {
+                        #( #cfgs )*
+                        #registration
+                    }
+                };
+                if options.dot_call {
+                    // The class table function takes the userdata explicitly, as
+                    // in `Type.method(obj, ...)`
+                    let dot_param = if names.is_empty() {
+                        quote!((ud, rudeboy_rest): (#rlua::AnyUserData, #rlua::MultiValue))
+                    } else if fast_path {
+                        let value_tys = raw_args.iter().map(|_| quote!(#rlua::Value));
+                        quote!((ud, #( #raw_args, )* #rest_pat): (#rlua::AnyUserData, #( #value_tys, )* #rest_ty))
+                    } else {
+                        quote!((ud, args): (#rlua::AnyUserData, #rlua::MultiValue))
+                    };
+                    let borrow = if m.is_mut {
+                        quote!(let mut data = ud.borrow_mut::<Self>()?;)
+                    } else {
+                        quote!(let data = ud.borrow::<Self>()?;)
+                    };
+                    let create_function = quote_spanned!(return_span => ctx.create_function);
+                    let code = record(quote! {
+                        #convert_args
+                        #borrow
+                        #trace
+                        #body
+                    });
+                    let registration = m.gated(
+                        quote! {
+                            table.set(#lua_name, #create_function(|#ctx, #dot_param| {
+                                #code
+                            })?)?;
+                        },
+                        options,
+                    );
+                    dot_fns.push(quote! {
+                        #( #cfgs )*
+                        #registration
+                    });
+                }
+                Some(method_registration)
             }
         })
         .collect();
 
+    let mqs = match &options.namespace {
+        Some(namespace) => quote! {
+            _methods.add_function(#namespace, |ctx, ud: #rlua::AnyUserData| {
+                let namespace = ctx.create_table()?;
+                namespace.set("__self", ud)?;
+                #( #mqs )*
+                Ok(namespace)
+            });
+        },
+        None => quote!( #( #mqs )* ),
+    };
+
+    Registrations {
+        methods: quote! {
+            #mqs
+            #( #meta_mqs )*
+        },
+        dot_fns,
+        getter_arms,
+        setter_arms,
+    }
+}
+
+fn implitem_methods_attr_macro(ast: &syn::ItemImpl, options: &MethodsOptions) -> TokenStream2 {
+    let consts: Vec<_> = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::ImplItem::Const(c) => Some((c.ident.clone(), cfg_attrs(&c.attrs))),
+            _ => None,
+        })
+        .collect();
+    let (methods, mut warnings) = match exported_methods(ast, options) {
+        Ok(exported) => exported,
+        Err(e) => return e,
+    };
+    if methods.is_empty() && options.from_traits.is_empty() {
+        warnings.push(warning(
+            ast.self_ty.span(),
+            "No methods are exported from this impl block",
+        ));
+    }
+
+    let mut lua_names = LuaNames::default();
+    for m in methods.iter().filter(|m| m.cfgs.is_empty() && m.metamethod.is_none() && !m.is_property()) {
+        if let Err(e) = lua_names.insert(&m.lua_name, &m.name.to_string(), m.name.span()) {
+            return e;
+        }
+    }
+
+    if options.help && options.namespace.is_none() {
+        if let Err(e) = lua_names.insert(&options.help_name(), MethodsOptions::HELP_IDENT, ast.self_ty.span()) {
+            return e;
+        }
+    }
+    let help = if options.help {
+        help_code(&methods, options)
+    } else {
+        quote!()
+    };
+
     let self_ty = &ast.self_ty;
-    quote! {
-        #ast
+    let exports = export_names(&methods, options);
+    let arities = method_arities(&methods, options);
+    let mut properties: Vec<_> = methods
+        .iter()
+        .filter_map(|m| if m.getter { Some(&m.lua_name) } else { m.setter.as_ref() })
+        .cloned()
+        .collect();
+    properties.sort_unstable();
+    properties.dedup();
+    let stats = if options.stats {
+        let names: Vec<_> = methods
+            .iter()
+            .map(|m| match &m.metamethod {
+                Some(metamethod) => format!("__{}", metamethod.to_string().to_lowercase()),
+                None => m.lua_name.clone(),
+            })
+            .collect();
+        stats_code(&names)
+    } else {
+        quote!()
+    };
+    let manifest = methods_manifest(ast, &methods, &consts, options);
+    let manifest = write_manifest(self_ty.span(), &type_string(self_ty), "methods", manifest);
+    let ldoc = methods_ldoc(ast, &methods, &consts, options);
+    let CratePaths { rudeboy, rlua, .. } = &options.paths;
+    let Registrations { methods: registered, dot_fns, getter_arms, setter_arms } =
+        registrations(methods, self_ty, &quote!(Self::rudeboy_argument_error), options);
+    // The methods of the traits are registered first, so that inherent methods
+    // of the same name take precedence
+    let from_traits = from_traits_code(options);
 
-        impl ::rudeboy::RudeboyMethods for #self_ty {
-            fn generate_methods<'lua, M: ::rlua::UserDataMethods<'lua, Self>>(_methods: &mut M) {
-                #( #mqs )*
+    let class_table_body = constants_table_body(&consts, quote!(Self::), quote!(#( #dot_fns )*));
+    // The class table is keyed by the name of the type without its generic
+    // arguments, e.g. `Matrix` for `impl Matrix<f64>`
+    let class_name = match &**self_ty {
+        syn::Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+    .unwrap_or_else(|| type_string(self_ty));
+    let (impl_generics, _, where_clause) = ast.generics.split_for_impl();
+    let ast = strip_helper_attrs(&syn::Item::Impl(ast.clone()));
+    let generated = options.paths.allow_lints(quote! {
+        const _: () = {
+            impl #impl_generics #rudeboy::RudeboyMethods for #self_ty #where_clause {
+                fn generate_methods<'lua, M: #rlua::UserDataMethods<'lua, Self>>(_methods: &mut M) {
+                    #from_traits
+                    #registered
+                    #help
+                }
+            }
+
+            /// The error raised when a method is called with the wrong number of
+            /// arguments or with one which cannot be converted, which
+            /// test_bindings tells apart from the errors raised by the methods
+            /// themselves
+            struct RudeboyArgumentError(String);
+
+            impl ::std::fmt::Debug for RudeboyArgumentError {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    ::std::fmt::Debug::fmt(&self.0, f)
+                }
             }
+
+            impl ::std::fmt::Display for RudeboyArgumentError {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    ::std::fmt::Display::fmt(&self.0, f)
+                }
+            }
+
+            impl ::std::error::Error for RudeboyArgumentError {}
+
+            impl #impl_generics #self_ty #where_clause {
+                #[doc(hidden)]
+                pub fn rudeboy_argument_error(message: String) -> #rlua::Error {
+                    #rlua::Error::external(RudeboyArgumentError(message))
+                }
+
+                #[doc(hidden)]
+                pub fn rudeboy_is_argument_error(error: &#rlua::Error) -> bool {
+                    matches!(error, #rlua::Error::ExternalError(e) if e.downcast_ref::<RudeboyArgumentError>().is_some())
+                }
+            }
+        };
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Creates a Lua table holding the associated constants exported by
+            /// rudeboy, suitable for use as this type's class table
+            pub fn class_table<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Table<'lua>> {
+                #class_table_body
+            }
+
+            /// Sets this type's class table, as created by `class_table`, in the
+            /// given table under the name of the type, e.g. to build a namespace
+            /// for a plugin without touching the Lua globals
+            pub fn add_to_table<'lua>(ctx: #rlua::Context<'lua>, table: &#rlua::Table<'lua>) -> #rlua::Result<()> {
+                table.set(#class_name, Self::class_table(ctx)?)
+            }
+
+            /// The names of the methods and metamethods registered on this
+            /// type's userdata by rudeboy
+            pub const LUA_METHODS: &'static [&'static str] = &[#( #exports ),*];
+
+            /// The properties read through this type's Index metamethod, or
+            /// assigned through its NewIndex metamethod, by calling the methods
+            /// marked as getters or setters
+            pub const LUA_PROPERTIES: &'static [&'static str] = &[#( #properties ),*];
+
+            #[doc(hidden)]
+            pub const RUDEBOY_ARITIES: &'static [(&'static str, usize, Option<usize>)] = &[#( #arities ),*];
+
+            #[doc(hidden)]
+            #[allow(unused_variables)]
+            pub fn rudeboy_get_property<'lua>(
+                data: &Self,
+                ctx: #rlua::Context<'lua>,
+                key: &str,
+            ) -> #rlua::Result<Option<#rlua::Value<'lua>>> {
+                match key {
+                    #( #getter_arms )*
+                    _ => Ok(None),
+                }
+            }
+
+            #[doc(hidden)]
+            #[allow(unused_variables)]
+            pub fn rudeboy_set_property<'lua>(
+                data: &mut Self,
+                ctx: #rlua::Context<'lua>,
+                key: &str,
+                value: #rlua::Value<'lua>,
+            ) -> #rlua::Result<bool> {
+                match key {
+                    #( #setter_arms )*
+                    _ => Ok(false),
+                }
+            }
+
+            #stats
         }
+    });
+    quote! {
+        #ast
+
+        #( #warnings )*
+
+        #manifest
+        #ldoc
+
+        #generated
     }
 }
 
-pub(crate) fn impl_methods_attr_macro(item: syn::Item) -> TokenStream2 {
-    if let syn::Item::Impl(i) = item {
-        implitem_methods_attr_macro(&i)
+pub(crate) fn impl_methods_attr_macro(item: syn::Item, options: &MethodsOptions) -> TokenStream2 {
+    match item {
+        syn::Item::Impl(i) => implitem_methods_attr_macro(&i, options),
+        syn::Item::Trait(t) => trait_methods_attr_macro(&t, options).unwrap_or_else(|e| e),
+        item => quote_spanned! {
+            item.span() => compile_error!("Methods macro can only be applied to an inherent impl block or a trait");
+        },
+    }
+}
+
+/// Returns the name of the helper trait generated for the given trait, whose
+/// function registers the trait's exported methods
+fn trait_methods_ident(ident: &syn::Ident) -> syn::Ident {
+    quote::format_ident!("Rudeboy{}Methods", ident)
+}
+
+/// Generates the statements registering the exported methods of the traits
+/// given by from_trait on `_methods`, by calling their helper traits
+fn from_traits_code(options: &MethodsOptions) -> TokenStream2 {
+    let calls = options.from_traits.iter().map(|path| {
+        let mut helper = path.clone();
+        if let Some(last) = helper.segments.last_mut() {
+            last.ident = trait_methods_ident(&last.ident);
+        }
+        quote_spanned!(path.span() => <Self as #helper>::rudeboy_generate_methods(_methods);)
+    });
+    quote!( #( #calls )* )
+}
+
+/// Generates a helper trait implemented for every type which implements the
+/// given trait and is user data, whose `rudeboy_generate_methods` function
+/// registers the trait's exported methods as implemented for the type,
+/// including default implementations
+fn trait_methods_attr_macro(ast: &syn::ItemTrait, options: &MethodsOptions) -> Result<TokenStream2, TokenStream2> {
+    if !ast.generics.params.is_empty() {
+        return Err(quote_spanned! {
+            ast.generics.span() => compile_error!("Methods macro can only be applied to traits without generic parameters");
+        });
+    }
+    // The options which generate items for a type have no type to add them to
+    if options.dot_call || options.stats || options.auto_props {
+        return Err(quote_spanned! {
+            ast.ident.span() => compile_error!("dot_call, stats, and auto_props cannot be used on a trait");
+        });
+    }
+    // The methods are read as those of an impl block for `Self`, which is the
+    // implementing type in the helper trait's function
+    let items = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::TraitItem::Method(m) => Some(syn::ImplItem::Method(syn::ImplItemMethod {
+                attrs: m.attrs.clone(),
+                vis: syn::Visibility::Inherited,
+                defaultness: None,
+                sig: m.sig.clone(),
+                block: syn::parse_quote!({}),
+            })),
+            _ => None,
+        })
+        .collect();
+    let methods_impl = syn::ItemImpl {
+        attrs: Vec::new(),
+        defaultness: None,
+        unsafety: None,
+        impl_token: Default::default(),
+        generics: Default::default(),
+        trait_: None,
+        self_ty: Box::new(syn::parse_quote!(Self)),
+        brace_token: Default::default(),
+        items,
+    };
+    let (methods, warnings) = exported_methods(&methods_impl, options)?;
+    let mut lua_names = LuaNames::default();
+    for m in &methods {
+        if m.is_property() {
+            return Err(quote_spanned! {
+                m.name.span() => compile_error!("Getters and setters cannot be exported from a trait");
+            });
+        }
+        if m.cfgs.is_empty() && m.metamethod.is_none() {
+            lua_names.insert(&m.lua_name, &m.name.to_string(), m.name.span())?;
+        }
+    }
+    let help = if options.help {
+        if options.namespace.is_none() {
+            lua_names.insert(&options.help_name(), MethodsOptions::HELP_IDENT, ast.ident.span())?;
+        }
+        help_code(&methods, options)
     } else {
-        return quote_spanned! {
-            item.span() => compile_error!("Methods macro can only be applied to an inherent impl block");
+        quote!()
+    };
+
+    let ident = &ast.ident;
+    let vis = &ast.vis;
+    let helper = trait_methods_ident(ident);
+    let from_traits = from_traits_code(options);
+    // Errors raised by the methods name them as methods of the trait
+    let rlua = &options.paths.rlua;
+    let argument_error = quote!(<String as #rlua::ExternalError>::to_lua_err);
+    let registered = registrations(methods, &syn::parse_quote!(#ident), &argument_error, options).methods;
+    let item = strip_helper_attrs(&syn::Item::Trait(ast.clone()));
+    let generated = options.paths.allow_lints(quote! {
+        #[doc(hidden)]
+        #vis trait #helper: #ident + #rlua::UserData + ::std::marker::Send + ::std::marker::Sized + 'static {
+            fn rudeboy_generate_methods<'lua, M: #rlua::UserDataMethods<'lua, Self>>(_methods: &mut M) {
+                #from_traits
+                #registered
+                #help
+            }
+        }
+
+        const _: () = {
+            impl<RudeboySelf: #ident + #rlua::UserData + ::std::marker::Send + 'static> #helper for RudeboySelf {}
         };
-    }
+    });
+    Ok(quote! {
+        #item
+
+        #( #warnings )*
+
+        #generated
+    })
 }
@@ -0,0 +1,41 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote_spanned;
+use std::collections::HashMap;
+
+/// Tracks the names under which items are exported to Lua, in order to detect
+/// two Rust items being exported under the same Lua name
+#[derive(Default)]
+pub(crate) struct LuaNames {
+    names: HashMap<String, (String, Span)>,
+}
+
+impl LuaNames {
+    /// Records that the Rust item `rust_name` is exported as `lua_name`,
+    /// returning an error at both items if the Lua name is already in use
+    pub(crate) fn insert(&mut self, lua_name: &str, rust_name: &str, span: Span) -> Result<(), TokenStream2> {
+        if let Some((other_name, other_span)) = self.names.get(lua_name) {
+            let message = format!(
+                "`{}` and `{}` are both exported to Lua as `{}`",
+                other_name, rust_name, lua_name
+            );
+            let other_span = *other_span;
+            return Err(quote_spanned! {
+                other_span => compile_error!(#message);
+            }
+            .into_iter()
+            .chain(quote_spanned! {
+                span => compile_error!(#message);
+            })
+            .collect());
+        }
+        self.names.insert(lua_name.to_string(), (rust_name.to_string(), span));
+        Ok(())
+    }
+
+    /// Returns the Lua names recorded so far, in sorted order
+    pub(crate) fn lua_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.names.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
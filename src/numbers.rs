@@ -0,0 +1,141 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+
+/// The integer types which cannot hold every Lua integer, and so are converted
+/// with a range check
+const NARROW_INTEGERS: &[&str] = &["i8", "i16", "i32", "isize", "u8", "u16", "u32", "u64", "usize"];
+
+/// If the given type is an integer type narrower than Lua's integers, returns
+/// an expression converting the `rlua::Value` in `value` into it, evaluating to
+/// an `rlua::Result` of the type. A number outside of the type's range raises
+/// an error naming the value being converted, e.g.
+/// "value 300 out of range for u8 parameter 'alpha'", and one with a fractional
+/// part is rejected rather than truncated. Other values are converted with
+/// `rlua::FromLua`, using the context `ctx`
+pub(crate) fn checked_integer_conversion(
+    ty: &syn::Type,
+    value: &TokenStream2,
+    kind: &str,
+    name: &str,
+    rlua: &TokenStream2,
+) -> Option<TokenStream2> {
+    let ident = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.get_ident()?,
+        _ => return None,
+    };
+    if !NARROW_INTEGERS.iter().any(|i| ident == i) {
+        return None;
+    }
+    let target = format!("{} {} '{}'", ident, kind, name);
+    // The bound is the power of two just past MAX, which is exact as a float,
+    // whereas MAX itself rounds up to it for 64-bit types
+    Some(quote! {{
+        let out_of_range = |value: &dyn ::std::fmt::Display| {
+            #rlua::Error::external(format!("value {} out of range for {}", value, #target))
+        };
+        let converted: #rlua::Result<#ident> = match #value {
+            #rlua::Value::Integer(i) => ::std::convert::TryFrom::try_from(i).map_err(|_| out_of_range(&i)),
+            #rlua::Value::Number(n) if n.fract() != 0.0 => {
+                Err(#rlua::Error::external(format!("value {} is not an integer for {}", n, #target)))
+            }
+            #rlua::Value::Number(n) if n >= #ident::MIN as f64 && n < #ident::MAX as f64 + 1.0 => Ok(n as #ident),
+            #rlua::Value::Number(n) => Err(out_of_range(&n)),
+            value => <#ident as #rlua::FromLua>::from_lua(value, ctx),
+        };
+        converted
+    }})
+}
+
+/// How 64-bit integers, i.e. `i64` and `u64`, are represented in Lua by
+/// exported methods. Lua 5.1 and LuaJIT have no integers, so a large value
+/// converted to a float there silently loses precision
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum IntegerRepr {
+    /// As Lua integers, which is left to rlua
+    #[default]
+    Integer,
+    /// As floats, raising an error for values which a float cannot hold
+    /// exactly
+    Float,
+    /// As floats, or as decimal strings for values which a float cannot hold
+    /// exactly. Parameters also accept such strings
+    String,
+}
+
+impl IntegerRepr {
+    const REPRS: &'static [(&'static str, IntegerRepr)] = &[
+        ("integer", IntegerRepr::Integer),
+        ("float", IntegerRepr::Float),
+        ("string", IntegerRepr::String),
+    ];
+
+    pub(crate) fn try_parse(lit: &syn::Lit) -> Result<IntegerRepr, TokenStream2> {
+        if let syn::Lit::Str(s) = lit {
+            let value = s.value();
+            for (name, repr) in Self::REPRS {
+                if value == *name {
+                    return Ok(*repr);
+                }
+            }
+        }
+        Err(quote_spanned! {
+            lit.span() => compile_error!("Expected one of: \"integer\", \"float\", \"string\"");
+        })
+    }
+
+    /// Whether values of the given type are converted by this representation
+    /// rather than by rlua
+    pub(crate) fn converts(self, ty: &syn::Type) -> bool {
+        self != IntegerRepr::Integer && wide_integer(ty).is_some()
+    }
+
+    /// If the given type is a 64-bit integer type and is not represented as a
+    /// Lua integer, returns an expression converting the owned value in `value`
+    /// into Lua, evaluating to an `rlua::Result` of the `rlua::Value`. The
+    /// expression uses the context `ctx`
+    pub(crate) fn to_lua_conversion(self, ty: &syn::Type, value: &TokenStream2, rlua: &TokenStream2) -> Option<TokenStream2> {
+        let ident = wide_integer(ty)?;
+        let inexact = match self {
+            IntegerRepr::Integer => return None,
+            IntegerRepr::Float => quote! {
+                Err(#rlua::Error::external(format!("value {} cannot be held exactly by a float", value)))
+            },
+            IntegerRepr::String => quote!(ctx.create_string(&::std::string::ToString::to_string(&value)).map(#rlua::Value::String)),
+        };
+        // Floats hold every integer of magnitude up to 2^53 exactly
+        Some(quote! {{
+            let value: #ident = #value;
+            if i128::from(value).abs() <= 1 << 53 {
+                Ok(#rlua::Value::Number(value as f64))
+            } else {
+                #inexact
+            }
+        }})
+    }
+
+    /// If the given type is a 64-bit integer type represented as a string when
+    /// a float cannot hold it, returns an expression parsing the `rlua::String`
+    /// in `s` into it, evaluating to an `rlua::Result` of the type. An invalid
+    /// string raises an error naming the value being converted, e.g.
+    /// "invalid u64 parameter 'id': abc"
+    pub(crate) fn string_conversion(self, ty: &syn::Type, kind: &str, name: &str, rlua: &TokenStream2) -> Option<TokenStream2> {
+        let ident = wide_integer(ty)?;
+        if self != IntegerRepr::String {
+            return None;
+        }
+        let message = format!("invalid {} {} '{}': {{}}", ident, kind, name);
+        Some(quote! {
+            s.to_str().and_then(|s| {
+                <#ident as ::std::str::FromStr>::from_str(s).map_err(|_| #rlua::Error::external(format!(#message, s)))
+            })
+        })
+    }
+}
+
+/// Returns the given type if it is `i64` or `u64`
+fn wide_integer(ty: &syn::Type) -> Option<&syn::Ident> {
+    match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p.path.get_ident().filter(|i| *i == "i64" || *i == "u64"),
+        _ => None,
+    }
+}
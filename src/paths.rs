@@ -0,0 +1,156 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The lints allowed on generated items unless an `allow(...)` parameter gives
+/// others. These are lints which the patterns of generated code can trigger for
+/// some of the types or options it is generated for
+const DEFAULT_ALLOWED_LINTS: &[&str] = &[
+    "non_snake_case",
+    "unused_parens",
+    "clippy::manual_range_contains",
+    "clippy::needless_borrow",
+    "clippy::needless_lifetimes",
+    "clippy::op_ref",
+    "clippy::redundant_clone",
+    "clippy::too_many_arguments",
+    "clippy::type_complexity",
+    "clippy::unnecessary_cast",
+];
+
+/// The paths to the rudeboy and rlua crates used in generated code, along with
+/// the traits used to convert values to and from Lua, and the lints allowed on
+/// the items of generated code
+#[derive(Clone)]
+pub(crate) struct CratePaths {
+    pub rudeboy: TokenStream2,
+    pub rlua: TokenStream2,
+    /// A trait with the same shape as `rlua::ToLua` to use in its place
+    pub to_lua: Option<TokenStream2>,
+    /// A trait with the same shape as `rlua::FromLua` to use in its place
+    pub from_lua: Option<TokenStream2>,
+    pub allowed_lints: Vec<syn::Path>,
+}
+
+impl Default for CratePaths {
+    fn default() -> Self {
+        CratePaths {
+            rudeboy: quote!(::rudeboy),
+            rlua: quote!(::rlua),
+            to_lua: None,
+            from_lua: None,
+            allowed_lints: DEFAULT_ALLOWED_LINTS.iter().map(|lint| syn::parse_str(lint).unwrap()).collect(),
+        }
+    }
+}
+
+impl CratePaths {
+    const CRATE_IDENT: &'static str = "crate";
+    const RLUA_IDENT: &'static str = "rlua";
+    const TO_LUA_IDENT: &'static str = "to_lua";
+    const FROM_LUA_IDENT: &'static str = "from_lua";
+    const ALLOW_IDENT: &'static str = "allow";
+
+    /// The trait used to convert values into Lua values
+    pub(crate) fn trait_to_lua(&self) -> TokenStream2 {
+        let rlua = &self.rlua;
+        self.to_lua.clone().unwrap_or_else(|| quote!(#rlua::ToLua))
+    }
+
+    /// The trait used to convert Lua values into Rust values
+    pub(crate) fn trait_from_lua(&self) -> TokenStream2 {
+        let rlua = &self.rlua;
+        self.from_lua.clone().unwrap_or_else(|| quote!(#rlua::FromLua))
+    }
+
+    /// Whether the given path names an item of the rlua crate, e.g.
+    /// `rlua::Result` or `::rlua::Error`, or of the crate given by the `rlua`
+    /// parameter if it was overridden
+    pub(crate) fn is_rlua_item(&self, path: &syn::Path) -> bool {
+        let rlua: syn::Path = match syn::parse2(self.rlua.clone()) {
+            Ok(rlua) => rlua,
+            Err(_) => return false,
+        };
+        let prefix = path.segments.iter().take(path.segments.len().saturating_sub(1));
+        prefix.map(|s| &s.ident).eq(rlua.segments.iter().map(|s| &s.ident))
+    }
+
+    /// Adds an `allow` attribute naming the allowed lints to each of the given
+    /// generated items, which leaves them unchanged if no lints are allowed
+    pub(crate) fn allow_lints(&self, items: TokenStream2) -> TokenStream2 {
+        if self.allowed_lints.is_empty() {
+            return items;
+        }
+        let mut file: syn::File = match syn::parse2(items.clone()) {
+            Ok(file) => file,
+            Err(_) => return items,
+        };
+        let lints = &self.allowed_lints;
+        let allow: syn::Attribute = syn::parse_quote!(#[allow(#( #lints ),*)]);
+        for item in file.items.iter_mut() {
+            let attrs = match item {
+                syn::Item::Const(c) => &mut c.attrs,
+                syn::Item::Enum(e) => &mut e.attrs,
+                syn::Item::Fn(f) => &mut f.attrs,
+                syn::Item::Impl(i) => &mut i.attrs,
+                syn::Item::Mod(m) => &mut m.attrs,
+                syn::Item::Static(s) => &mut s.attrs,
+                syn::Item::Struct(s) => &mut s.attrs,
+                syn::Item::Trait(t) => &mut t.attrs,
+                _ => continue,
+            };
+            attrs.insert(0, allow.clone());
+        }
+        let items = file.items;
+        quote!(#( #items )*)
+    }
+
+    /// Attempts to parse the given parameter as a path override of the form
+    /// `crate = "path::to::rudeboy"`, `rlua = "path::to::rlua"`,
+    /// `to_lua = "path::to::ToLua"`, or `from_lua = "path::to::FromLua"`, or as
+    /// the list of lints allowed on generated items, e.g.
+    /// `allow(clippy::too_many_arguments)`, which replaces the default list.
+    /// Returns whether the parameter was one of these
+    pub(crate) fn try_parse_option(&mut self, attr: &syn::NestedMeta) -> Result<bool, TokenStream2> {
+        use syn::{Meta, NestedMeta};
+        let nv = match attr {
+            NestedMeta::Meta(Meta::NameValue(nv)) => nv,
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(Self::ALLOW_IDENT) => {
+                self.allowed_lints.clear();
+                for nested in &l.nested {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(p)) => self.allowed_lints.push(p.clone()),
+                        nested => {
+                            return Err(quote_spanned! {
+                                nested.span() => compile_error!("Expected the name of a lint");
+                            })
+                        }
+                    }
+                }
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+        let target = if nv.path.is_ident(Self::CRATE_IDENT) {
+            &mut self.rudeboy
+        } else if nv.path.is_ident(Self::RLUA_IDENT) {
+            &mut self.rlua
+        } else if nv.path.is_ident(Self::TO_LUA_IDENT) {
+            self.to_lua.get_or_insert_with(TokenStream2::new)
+        } else if nv.path.is_ident(Self::FROM_LUA_IDENT) {
+            self.from_lua.get_or_insert_with(TokenStream2::new)
+        } else {
+            return Ok(false);
+        };
+        let path = match &nv.lit {
+            syn::Lit::Str(s) => s.parse::<syn::Path>().map_err(|e| e.to_compile_error())?,
+            lit => {
+                return Err(quote_spanned! {
+                    lit.span() => compile_error!("Expected a string containing a path");
+                })
+            }
+        };
+        *target = quote!(#path);
+        Ok(true)
+    }
+}
@@ -0,0 +1,64 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+
+pub(crate) const STATS_IDENT: &str = "stats";
+
+/// Checks that the stats feature is enabled, returning a compile error
+/// otherwise
+pub(crate) fn check_stats_feature(span: Span) -> Result<(), TokenStream2> {
+    if cfg!(feature = "stats") {
+        Ok(())
+    } else {
+        Err(quote_spanned! {
+            span => compile_error!("The stats parameter requires the stats feature of rudeboy-derive to be enabled");
+        })
+    }
+}
+
+/// Wraps the given statements, which evaluate to an `rlua::Result`, so that the
+/// call and the time taken are recorded in the counters of the method at the
+/// given index
+pub(crate) fn record_stats(index: usize, code: TokenStream2, rlua: &TokenStream2) -> TokenStream2 {
+    quote! {
+        let rudeboy_start = ::std::time::Instant::now();
+        #[allow(clippy::redundant_closure_call)]
+        let result = (|| -> #rlua::Result<_> { #code })();
+        let (calls, nanos) = &Self::rudeboy_stats()[#index];
+        calls.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed);
+        nanos.fetch_add(rudeboy_start.elapsed().as_nanos() as u64, ::std::sync::atomic::Ordering::Relaxed);
+        result
+    }
+}
+
+/// Generates the counters of the methods with the given Lua names, along with
+/// the `binding_stats` function reporting them. A static cannot depend on the
+/// generic parameters of the impl block, so its instantiations share counters
+pub(crate) fn stats_code(names: &[String]) -> TokenStream2 {
+    let counters = names.iter().map(|_| {
+        quote!((::std::sync::atomic::AtomicU64::new(0), ::std::sync::atomic::AtomicU64::new(0)))
+    });
+    let count = names.len();
+    quote! {
+        #[doc(hidden)]
+        fn rudeboy_stats() -> &'static [(::std::sync::atomic::AtomicU64, ::std::sync::atomic::AtomicU64); #count] {
+            static STATS: [(::std::sync::atomic::AtomicU64, ::std::sync::atomic::AtomicU64); #count] = [#( #counters ),*];
+            &STATS
+        }
+
+        /// Returns the Lua name of each exported method, along with the number
+        /// of times it has been called from Lua and the total time spent in it
+        #[allow(dead_code)]
+        pub fn binding_stats() -> Vec<(&'static str, u64, ::std::time::Duration)> {
+            const NAMES: [&str; #count] = [#( #names ),*];
+            NAMES
+                .iter()
+                .zip(Self::rudeboy_stats())
+                .map(|(name, (calls, nanos))| {
+                    let calls = calls.load(::std::sync::atomic::Ordering::Relaxed);
+                    let nanos = nanos.load(::std::sync::atomic::Ordering::Relaxed);
+                    (*name, calls, ::std::time::Duration::from_nanos(nanos))
+                })
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::paths::CratePaths;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The parameter requesting that an array of pairs be converted into a table
+/// mapping each key to its value
+const MAP_IDENT: &str = "map";
+
+/// Generates an expression evaluating to the Lua table equivalent to the given
+/// array, tuple or struct literal, whose value is found at the given path.
+/// Values other than array, tuple and struct literals are taken from the path and converted
+/// with `to_lua`. If `map` is set, the literal must be an array of pairs
+fn table_code(expr: &syn::Expr, path: TokenStream2, map: bool, paths: &CratePaths) -> Result<TokenStream2, TokenStream2> {
+    let to_lua = paths.trait_to_lua();
+    match expr {
+        syn::Expr::Reference(r) => table_code(&r.expr, path, map, paths),
+        syn::Expr::Paren(p) => table_code(&p.expr, path, map, paths),
+        syn::Expr::Group(g) => table_code(&g.expr, path, map, paths),
+        syn::Expr::Array(a) if map => {
+            let mut sets = Vec::new();
+            for (i, elem) in a.elems.iter().enumerate() {
+                let value = match elem {
+                    syn::Expr::Tuple(t) if t.elems.len() == 2 => {
+                        table_code(&t.elems[1], quote!(#path[#i].1), false, paths)?
+                    }
+                    elem => {
+                        return Err(quote_spanned! {
+                            elem.span() => compile_error!("Expected a pair of a key and a value");
+                        })
+                    }
+                };
+                sets.push(quote! {
+                    table.set(#to_lua::to_lua(::std::clone::Clone::clone(&#path[#i].0), ctx)?, #value)?;
+                });
+            }
+            Ok(quote!({
+                let table = ctx.create_table()?;
+                #( #sets )*
+                table
+            }))
+        }
+        syn::Expr::Array(a) => {
+            let values = a
+                .elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| table_code(elem, quote!(#path[#i]), false, paths))
+                .collect::<Result<Vec<_>, _>>()?;
+            let keys = 1..=values.len();
+            Ok(quote!({
+                let table = ctx.create_table()?;
+                #( table.set(#keys, #values)?; )*
+                table
+            }))
+        }
+        syn::Expr::Tuple(t) => {
+            let values = t
+                .elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| {
+                    let index = syn::Index::from(i);
+                    table_code(elem, quote!(#path.#index), false, paths)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let keys = 1..=values.len();
+            Ok(quote!({
+                let table = ctx.create_table()?;
+                #( table.set(#keys, #values)?; )*
+                table
+            }))
+        }
+        syn::Expr::Struct(s) => {
+            if let Some(rest) = &s.rest {
+                return Err(quote_spanned! {
+                    rest.span() => compile_error!("table cannot convert a struct literal with a base expression");
+                });
+            }
+            let mut sets = Vec::new();
+            for field in &s.fields {
+                let key = match &field.member {
+                    syn::Member::Named(ident) => ident.to_string(),
+                    syn::Member::Unnamed(index) => (index.index + 1).to_string(),
+                };
+                let member = &field.member;
+                let value = table_code(&field.expr, quote!(#path.#member), false, paths)?;
+                sets.push(quote!(table.set(#key, #value)?;));
+            }
+            Ok(quote!({
+                let table = ctx.create_table()?;
+                #( #sets )*
+                table
+            }))
+        }
+        // Spanned at the value, so that a type which cannot be converted into
+        // Lua is reported there
+        expr => Ok(quote_spanned! {
+            expr.span() => #to_lua::to_lua(::std::clone::Clone::clone(&#path), ctx)?
+        }),
+    }
+}
+
+pub(crate) fn impl_table_attr_macro(item: syn::Item, attrs: Vec<&syn::NestedMeta>) -> TokenStream2 {
+    let mut paths = CratePaths::default();
+    let mut map = false;
+    for attr in attrs {
+        match paths.try_parse_option(attr) {
+            Ok(true) => (),
+            Ok(false) => match attr {
+                syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident(MAP_IDENT) => map = true,
+                _ => {
+                    return quote_spanned! {
+                        attr.span() => compile_error!("Expected a valid table parameter");
+                    }
+                }
+            },
+            Err(e) => return e,
+        }
+    }
+
+    let (vis, ident, expr) = match &item {
+        syn::Item::Const(c) => (&c.vis, &c.ident, &c.expr),
+        syn::Item::Static(s) if s.mutability.is_none() => (&s.vis, &s.ident, &s.expr),
+        _ => {
+            return quote_spanned! {
+                item.span() => compile_error!("table macro can only be applied to a const or an immutable static");
+            }
+        }
+    };
+    let literal = match expr.as_ref() {
+        syn::Expr::Reference(r) => r.expr.as_ref(),
+        expr => expr,
+    };
+    if !matches!(literal, syn::Expr::Array(_) | syn::Expr::Struct(_)) {
+        return quote_spanned! {
+            expr.span() => compile_error!("Expected an array or struct literal");
+        };
+    }
+    let table = match table_code(expr, quote!(#ident), map, &paths) {
+        Ok(table) => table,
+        Err(e) => return e,
+    };
+
+    let rlua = &paths.rlua;
+    let function = quote::format_ident!("{}_table", ident.to_string().to_lowercase());
+    let doc = format!("Creates a Lua table holding the value of [`{}`]", ident);
+    let function = paths.allow_lints(quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis fn #function<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Table<'lua>> {
+            Ok(#table)
+        }
+    });
+    quote! {
+        #item
+
+        #function
+    }
+}
@@ -0,0 +1,234 @@
+use crate::attrs::function_path;
+use crate::ecosystem;
+use crate::manifest::type_string;
+use crate::methods::{exported_methods, is_cow_str, is_lua_value, is_multi_value, slice_reference, userdata_reference, MethodsOptions, Params};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The parameter naming the function which creates the value the methods are
+/// called on, rather than `Default::default`
+const VALUE_IDENT: &str = "value";
+
+/// Returns a Lua chunk which triggers the given metamethod on the user data
+/// passed as its first argument, with the other arguments as operands, or None
+/// if the metamethod cannot be triggered from Lua
+fn metamethod_chunk(metamethod: &str) -> Option<String> {
+    let binary = |op: &str| Some(format!("local data, other = ... return data {} other", op));
+    match metamethod {
+        "Add" => binary("+"),
+        "Sub" => binary("-"),
+        "Mul" => binary("*"),
+        "Div" => binary("/"),
+        "Mod" => binary("%"),
+        "Pow" => binary("^"),
+        "Concat" => binary(".."),
+        "Eq" => binary("=="),
+        "Lt" => binary("<"),
+        "Le" => binary("<="),
+        // Lua 5.1 has no syntax for integer division or bitwise operators
+        "IDiv" | "BAnd" | "BOr" | "BXor" | "Shl" | "Shr" | "BNot" if cfg!(feature = "lua51") => None,
+        "IDiv" => binary("//"),
+        "BAnd" => binary("&"),
+        "BOr" => binary("|"),
+        "BXor" => binary("~"),
+        "Shl" => binary("<<"),
+        "Shr" => binary(">>"),
+        "BNot" => Some("local data = ... return ~data".to_string()),
+        "Unm" => Some("local data = ... return -data".to_string()),
+        "Len" => Some("local data = ... return #data".to_string()),
+        "ToString" => Some("local data = ... return tostring(data)".to_string()),
+        "Call" => Some("local data = ... return data(select(2, ...))".to_string()),
+        "Index" => Some("local data, key = ... return data[key]".to_string()),
+        "NewIndex" => Some("local data, key, value = ... data[key] = value".to_string()),
+        "Pairs" => Some("local data = ... return pairs(data)".to_string()),
+        _ => None,
+    }
+}
+
+/// Generates an expression creating a default value of the given parameter
+/// type in Lua, evaluating to an `rlua::Result` of the `rlua::Value`
+fn default_argument(ty: &syn::Type, options: &MethodsOptions) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    if let Some((elem, _)) = userdata_reference(ty) {
+        return quote!(ctx.create_userdata(<#elem as ::std::default::Default>::default()).map(#rlua::Value::UserData));
+    }
+    if let Some(elem) = slice_reference(ty) {
+        let vec: syn::Type = syn::parse_quote!(Vec<#elem>);
+        return ecosystem::to_lua_conversion(&vec, &quote!(<#vec>::new()), rlua)
+            .unwrap_or_else(|| quote!(#rlua::ToLua::to_lua(<#vec>::new(), ctx)));
+    }
+    if is_lua_value(ty) {
+        return quote!(Ok(#rlua::Value::Nil));
+    }
+    if is_cow_str(ty) {
+        return quote!(ctx.create_string("").map(#rlua::Value::String));
+    }
+    let default = quote!(<#ty as ::std::default::Default>::default());
+    if let Some(conversion) = ecosystem::to_lua_conversion(ty, &default, rlua) {
+        return conversion;
+    }
+    let to_lua = options.paths.trait_to_lua();
+    quote_spanned!(ty.span() => #to_lua::to_lua(#default, ctx))
+}
+
+pub(crate) fn impl_test_bindings_attr_macro(item: syn::Item, attrs: Vec<&syn::NestedMeta>) -> TokenStream2 {
+    let mut value = None;
+    for attr in attrs {
+        match attr {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident(VALUE_IDENT) => {
+                match function_path(&nv.lit) {
+                    Ok(path) => value = Some(path),
+                    Err(e) => return e,
+                }
+            }
+            _ => {
+                return quote_spanned! {
+                    attr.span() => compile_error!("Expected a valid test_bindings parameter");
+                }
+            }
+        }
+    }
+
+    let ast = match &item {
+        syn::Item::Impl(i) => i,
+        item => {
+            return quote_spanned! {
+                item.span() => compile_error!("test_bindings macro can only be applied to an impl block");
+            }
+        }
+    };
+    // The methods attribute has not been expanded yet, so its parameters are
+    // read to find the methods it exports
+    let methods_attr = ast.attrs.iter().find(|a| a.path.segments.last().is_some_and(|s| s.ident == "methods"));
+    let nested = match methods_attr.map(|a| a.parse_meta()) {
+        Some(Ok(syn::Meta::Path(_))) => Vec::new(),
+        Some(Ok(syn::Meta::List(l))) => l.nested.into_iter().collect(),
+        Some(Ok(meta)) => {
+            return quote_spanned! {
+                meta.span() => compile_error!("Expected a valid methods attribute");
+            }
+        }
+        Some(Err(e)) => return e.to_compile_error(),
+        None => {
+            return quote_spanned! {
+                ast.span() => compile_error!("test_bindings macro must be placed above the methods attribute of an impl block");
+            }
+        }
+    };
+    let options = match MethodsOptions::try_parse(nested.iter().collect()) {
+        Ok(options) => options,
+        Err(e) => return e,
+    };
+    let methods = match exported_methods(ast, &options) {
+        Ok((methods, _)) => methods,
+        Err(e) => return e,
+    };
+
+    let rlua = &options.paths.rlua;
+    let mut calls = Vec::new();
+    for m in &methods {
+        let lua_name = &m.lua_name;
+        let (label, chunk) = match (&m.metamethod, &m.setter, &options.namespace) {
+            (Some(metamethod), _, _) => match metamethod_chunk(&metamethod.to_string()) {
+                Some(chunk) => (format!("metamethod {}", metamethod), chunk),
+                None => continue,
+            },
+            (None, Some(property), _) => (
+                format!("property {}", property),
+                format!("local data, value = ... data[\"{}\"] = value", property),
+            ),
+            (None, None, _) if m.getter => (
+                format!("property {}", lua_name),
+                format!("local data = ... return data[\"{}\"]", lua_name),
+            ),
+            (None, None, Some(namespace)) => (
+                format!("method {}", lua_name),
+                format!(
+                    "local data = ... local namespace = data[\"{}\"](data) return namespace[\"{}\"](namespace, select(2, ...))",
+                    namespace, lua_name
+                ),
+            ),
+            (None, None, None) => (
+                format!("method {}", lua_name),
+                format!("local data = ... return data[\"{}\"](data, select(2, ...))", lua_name),
+            ),
+        };
+        // A final MultiValue parameter is given no arguments
+        let args: Vec<_> = match &m.params {
+            Params::None => Vec::new(),
+            Params::One { ty, .. } => vec![ty.as_ref()],
+            Params::Multi { tys, .. } => tys.iter().collect(),
+        }
+        .into_iter()
+        .filter(|ty| !is_multi_value(ty))
+        .map(|ty| default_argument(ty, &options))
+        .collect();
+        let cfgs = &m.cfgs;
+        // Methods requiring a capability the host does not grant are not
+        // registered, so are only called if it does
+        let call = m.gated(
+            quote! {
+                let call = || -> #rlua::Result<#rlua::MultiValue> {
+                    let data = ctx.create_userdata(value())?;
+                    let args = vec![#rlua::Value::UserData(data), #( #args? ),*];
+                    ctx.load(#chunk).into_function()?.call(#rlua::MultiValue::from_vec(args))
+                };
+                if let Some(error) = call().err().as_ref().and_then(|error| binding_error(error, false)) {
+                    failures.push(format!("{}: {}", #label, error));
+                }
+            },
+            &options,
+        );
+        calls.push(quote! {
+            #( #cfgs )*
+            {
+                #call
+            }
+        });
+    }
+
+    let self_ty = &ast.self_ty;
+    let type_name = type_string(self_ty);
+    let test_name: String = type_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    let test_name = quote::format_ident!("rudeboy_bindings_{}", test_name);
+    let value = match value {
+        Some(path) => quote!(#path()),
+        None => quote!(<#self_ty as ::std::default::Default>::default()),
+    };
+    let test = options.paths.allow_lints(quote! {
+        #[cfg(test)]
+        #[test]
+        fn #test_name() {
+            // Errors raised by the methods themselves are expected for default
+            // arguments, while these mean the bindings are broken. Errors raised
+            // by Lua itself, e.g. calling a method which is not registered, are
+            // runtime errors outside of any callback
+            fn binding_error(error: &#rlua::Error, in_callback: bool) -> Option<&#rlua::Error> {
+                match error {
+                    #rlua::Error::CallbackError { cause, .. } => binding_error(cause, true),
+                    #rlua::Error::FromLuaConversionError { .. }
+                    | #rlua::Error::ToLuaConversionError { .. }
+                    | #rlua::Error::SyntaxError { .. } => Some(error),
+                    #rlua::Error::RuntimeError(_) if !in_callback => Some(error),
+                    error if <#self_ty>::rudeboy_is_argument_error(error) => Some(error),
+                    _ => None,
+                }
+            }
+            let value = || -> #self_ty { #value };
+            let mut failures: Vec<String> = Vec::new();
+            #rlua::Lua::new().context(|ctx| {
+                #( #calls )*
+            });
+            assert!(failures.is_empty(), "bindings of {} are broken:\n{}", #type_name, failures.join("\n"));
+        }
+    });
+    quote! {
+        #item
+
+        #test
+    }
+}
@@ -0,0 +1,130 @@
+use crate::attrs::{cfg_attrs, ContainerOptions, FieldOptions};
+use crate::ecosystem;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+pub(crate) fn impl_to_lua_derive(ast: syn::DeriveInput) -> TokenStream2 {
+    let options = match ContainerOptions::try_parse(&ast.attrs) {
+        Ok(options) => options,
+        Err(e) => return e,
+    };
+    let conversion = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) if !options.integers => struct_to_lua(&ast, &fields.named, &options),
+        syn::Data::Enum(e) if e.variants.iter().all(|v| v.fields.is_empty()) => enum_to_lua(&ast, e, &options),
+        syn::Data::Struct(_) if options.integers => {
+            return quote_spanned! {
+                ast.span() => compile_error!("The integers option is only supported on fieldless enums");
+            }
+        }
+        _ => {
+            return quote_spanned! {
+                ast.span() => compile_error!("RudeboyToLua can only be derived for structs with named fields or fieldless enums");
+            }
+        }
+    };
+    options.paths.allow_lints(quote! {
+        const _: () = {
+            #conversion
+        };
+    })
+}
+
+/// Generates a conversion of a fieldless enum into the name of its variant
+fn enum_to_lua(ast: &syn::DeriveInput, data: &syn::DataEnum, options: &ContainerOptions) -> TokenStream2 {
+    let rlua = &options.paths.rlua;
+    let to_lua = options.paths.trait_to_lua();
+    let name = &ast.ident;
+    let (_, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let mut impl_generics = ast.generics.clone();
+    impl_generics.params.insert(0, syn::parse_quote!('lua));
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    let arms = data.variants.iter().map(|v| {
+        let ident = &v.ident;
+        let lua_name = ident.to_string();
+        let cfgs = cfg_attrs(&v.attrs);
+        quote! {
+            #( #cfgs )*
+            #name::#ident => #lua_name,
+        }
+    });
+    quote! {
+        impl #impl_generics #to_lua<'lua> for #name #ty_generics #where_clause {
+            fn to_lua(self, ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Value<'lua>> {
+                let name: &str = match self {
+                    #( #arms )*
+                };
+                Ok(#rlua::Value::String(ctx.create_string(name)?))
+            }
+        }
+    }
+}
+
+/// Generates a conversion of a struct into a table holding its fields
+fn struct_to_lua(
+    ast: &syn::DeriveInput,
+    fields: &syn::punctuated::Punctuated<syn::Field, syn::Token![,]>,
+    options: &ContainerOptions,
+) -> TokenStream2 {
+    let paths = &options.paths;
+    let rlua = &paths.rlua;
+    let to_lua = paths.trait_to_lua();
+    let name = &ast.ident;
+
+    let mut sets = Vec::new();
+    let mut field_tys = Vec::new();
+    for field in fields {
+        let options = match FieldOptions::try_parse(field) {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+        if options.readonly || options.delegate {
+            return quote_spanned! {
+                field.span() => compile_error!("RudeboyToLua only supports the skip field option");
+            };
+        }
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let key = ident.to_string();
+        if let Some(conversion) = ecosystem::to_lua_conversion(ty, &quote!(self.#ident), rlua) {
+            sets.push(quote!(table.set(#key, #conversion?)?;));
+            continue;
+        }
+        // Spanned at the field's type, so that a type which cannot be converted
+        // into Lua is reported there
+        sets.push(quote_spanned! {
+            ty.span() => table.set(#key, <#ty as #to_lua>::to_lua(self.#ident, ctx)?)?;
+        });
+        field_tys.push(ty);
+    }
+
+    // Generic field types may only be converted if their type parameters allow
+    let mut generics = ast.generics.clone();
+    if !ast.generics.params.is_empty() {
+        let where_clause = generics.make_where_clause();
+        for ty in &field_tys {
+            where_clause.predicates.push(syn::parse_quote!(#ty: #to_lua<'lua>));
+        }
+    }
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let mut impl_generics = generics.clone();
+    impl_generics.params.insert(0, syn::parse_quote!('lua));
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #to_lua<'lua> for #name #ty_generics #where_clause {
+            fn to_lua(self, ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Value<'lua>> {
+                let table = ctx.create_table()?;
+                #( #sets )*
+                Ok(#rlua::Value::Table(table))
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+
+pub(crate) const TRACE_IDENT: &str = "trace";
+
+/// Checks that the trace feature is enabled, returning a compile error
+/// otherwise
+pub(crate) fn check_trace_feature(span: Span) -> Result<(), TokenStream2> {
+    if cfg!(feature = "trace") {
+        Ok(())
+    } else {
+        Err(quote_spanned! {
+            span => compile_error!("The trace parameter requires the trace feature of rudeboy-derive to be enabled");
+        })
+    }
+}
+
+/// Generates statements which enter a `tracing` span recording the type and
+/// name of the method being called, and a summary of its parameters
+pub(crate) fn trace_span(self_ty: &TokenStream2, method: &str, params: &[String]) -> TokenStream2 {
+    let arg_count = params.len();
+    let params = params.join(", ");
+    quote! {
+        let span = ::tracing::trace_span!(
+            "rudeboy",
+            self_type = stringify!(#self_ty),
+            method = #method,
+            arg_count = #arg_count,
+            params = #params
+        );
+        let _enter = span.enter();
+    }
+}
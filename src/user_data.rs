@@ -1,99 +1,1489 @@
+use crate::attrs::{strip_helper_attrs, FieldOptions, RLUA_META_METHODS};
+use crate::case::{split_words, RenameRule};
+use crate::ecosystem;
+use crate::manifest::{method_manifest, type_string, write_manifest, Json};
+use crate::metamethods::{item_to_derive_input, lists_index, lists_new_index, metamethods_impl};
+use crate::names::LuaNames;
+use crate::numbers::checked_integer_conversion;
+use crate::paths::CratePaths;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, quote_spanned};
 use std::collections::HashSet;
-use syn;
 use syn::spanned::Spanned;
 
 #[derive(Eq, PartialEq, Hash)]
-enum UserDataAttr {
+pub(crate) enum UserDataAttr {
     MetaMethods,
     Methods,
+    VariantPredicates(RenameRule),
+    CommonFields,
+    Getters,
+    Setters,
+    Update,
+    Cmp,
+    Default,
+    Parse,
 }
 
 impl UserDataAttr {
     const META_METHODS_IDENT: &'static str = "MetaMethods";
     const METHODS_IDENT: &'static str = "Methods";
+    const VARIANT_PREDICATES_IDENT: &'static str = "VariantPredicates";
+    const RENAME_ALL_IDENT: &'static str = "rename_all";
+    const COMMON_FIELDS_IDENT: &'static str = "CommonFields";
+    const GETTERS_IDENT: &'static str = "Getters";
+    const SETTERS_IDENT: &'static str = "Setters";
+    const UPDATE_IDENT: &'static str = "Update";
+    const CMP_IDENT: &'static str = "Cmp";
+    const DEFAULT_IDENT: &'static str = "Default";
+    const PARSE_IDENT: &'static str = "Parse";
+    const EXTENDS_IDENT: &'static str = "extends";
+    const INCLUDE_IDENT: &'static str = "include";
+    const SHARED_IDENT: &'static str = "shared";
+    const TYPE_NAME_IDENT: &'static str = "type_name";
+    const EXPORTS_IDENT: &'static str = "exports";
+    const INTROSPECT_IDENT: &'static str = "introspect";
+    const METHODS_FN: &'static str = "methods";
+    const METAMETHODS_FN: &'static str = "metamethods";
+    const EXTENSIONS_IDENT: &'static str = "extensions";
+    const SET_EXTENSIONS_FN: &'static str = "set_extensions";
 
     fn try_parse(path: &syn::Path) -> Result<UserDataAttr, TokenStream2> {
         if path.is_ident(Self::META_METHODS_IDENT) {
             Ok(UserDataAttr::MetaMethods)
         } else if path.is_ident(Self::METHODS_IDENT) {
             Ok(UserDataAttr::Methods)
+        } else if path.is_ident(Self::VARIANT_PREDICATES_IDENT) {
+            Ok(UserDataAttr::VariantPredicates(RenameRule::default()))
+        } else if path.is_ident(Self::COMMON_FIELDS_IDENT) {
+            Ok(UserDataAttr::CommonFields)
+        } else if path.is_ident(Self::GETTERS_IDENT) {
+            Ok(UserDataAttr::Getters)
+        } else if path.is_ident(Self::SETTERS_IDENT) {
+            Ok(UserDataAttr::Setters)
+        } else if path.is_ident(Self::UPDATE_IDENT) {
+            Ok(UserDataAttr::Update)
+        } else if path.is_ident(Self::CMP_IDENT) {
+            Ok(UserDataAttr::Cmp)
+        } else if path.is_ident(Self::DEFAULT_IDENT) {
+            Ok(UserDataAttr::Default)
+        } else if path.is_ident(Self::PARSE_IDENT) {
+            Ok(UserDataAttr::Parse)
         } else {
             Err(quote_spanned! {
                 path.span() => compile_error!("Expected a valid metamethod identifier");
+            })
+        }
+    }
+
+    /// Parses the options given in a `VariantPredicates(...)` list
+    fn try_parse_variant_predicates(list: &syn::MetaList) -> Result<UserDataAttr, TokenStream2> {
+        let mut rule = RenameRule::default();
+        for nested in &list.nested {
+            use syn::{Meta, NestedMeta};
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(Self::RENAME_ALL_IDENT) => {
+                    rule = RenameRule::try_parse(&nv.lit)?
+                }
+                _ => {
+                    return Err(quote_spanned! {
+                        nested.span() => compile_error!("Expected rename_all = \"...\"");
+                    })
+                }
             }
-            .into())
         }
+        Ok(UserDataAttr::VariantPredicates(rule))
     }
 
-    fn get_code(&self, name: TokenStream2) -> TokenStream2 {
-        match self {
+    fn get_code(
+        &self,
+        name: TokenStream2,
+        item: &syn::Item,
+        paths: &CratePaths,
+        lua_names: &mut LuaNames,
+        manifest: &mut Vec<Json>,
+    ) -> Result<TokenStream2, TokenStream2> {
+        let rudeboy = &paths.rudeboy;
+        Ok(match self {
+            // The trait functions are called with qualified paths, since the
+            // type may be a path with generic arguments, e.g. `module::Type<T>`
             UserDataAttr::MetaMethods => quote! {
-                use ::rudeboy::RudeboyMetaMethods;
-                #name::generate_metamethods(methods);
+                <#name as #rudeboy::RudeboyMetaMethods>::generate_metamethods(methods);
             },
             UserDataAttr::Methods => quote! {
-                use ::rudeboy::RudeboyMethods;
-                #name::generate_methods(methods);
+                <#name as #rudeboy::RudeboyMethods>::generate_methods(methods);
             },
+            UserDataAttr::VariantPredicates(rule) => {
+                let enum_ = match item {
+                    syn::Item::Enum(e) => e,
+                    _ => {
+                        return Err(quote_spanned! {
+                            item.span() => compile_error!("VariantPredicates can only be used on enums");
+                        })
+                    }
+                };
+                let mut predicates = Vec::new();
+                for v in &enum_.variants {
+                    let variant = &v.ident;
+                    let mut words = vec!["is".to_string()];
+                    words.extend(split_words(&variant.to_string()));
+                    let lua_name = rule.apply_to_words(&words);
+                    lua_names.insert(&lua_name, &variant.to_string(), variant.span())?;
+                    manifest.push(method_manifest(&lua_name, None, Vec::new(), "bool".to_string()));
+                    predicates.push(quote! {
+                        methods.add_method(#lua_name, |_, data, ()| {
+                            Ok(matches!(data, Self::#variant { .. }))
+                        });
+                    });
+                }
+                quote!( #( #predicates )* )
+            }
+            UserDataAttr::CommonFields => {
+                let (variants, fields) = common_fields(item)?;
+                let rlua = &paths.rlua;
+                let mut getters = Vec::new();
+                for (field, ty) in fields {
+                    let lua_name = format!("get_{}", field);
+                    lua_names.insert(&lua_name, &field.to_string(), field.span())?;
+                    manifest.push(method_manifest(&lua_name, None, Vec::new(), type_string(ty)));
+                    let value = quote! {
+                        match data {
+                            #( Self::#variants { #field, .. } )|* => ::std::clone::Clone::clone(#field),
+                        }
+                    };
+                    match ecosystem::to_lua_conversion(ty, &quote!(value), rlua) {
+                        Some(conversion) => getters.push(quote! {
+                            methods.add_method(#lua_name, |ctx, data, ()| {
+                                let value = #value;
+                                #conversion
+                            });
+                        }),
+                        None => getters.push(quote! {
+                            methods.add_method(#lua_name, |_, data, ()| Ok(#value));
+                        }),
+                    }
+                }
+                quote!( #( #getters )* )
+            }
+            UserDataAttr::Getters => {
+                let fields = named_fields(item, Self::GETTERS_IDENT)?;
+                let rlua = &paths.rlua;
+                let mut getters = Vec::new();
+                for (field, _) in fields.iter().filter(|(_, opts)| !opts.skip) {
+                    let ty = &field.ty;
+                    let field = field.ident.as_ref().unwrap();
+                    let lua_name = format!("get_{}", field);
+                    lua_names.insert(&lua_name, &field.to_string(), field.span())?;
+                    manifest.push(method_manifest(&lua_name, None, Vec::new(), type_string(ty)));
+                    match ecosystem::to_lua_conversion(ty, &quote!(value), rlua) {
+                        Some(conversion) => getters.push(quote! {
+                            methods.add_method(#lua_name, |ctx, data, ()| {
+                                let value = ::std::clone::Clone::clone(&data.#field);
+                                #conversion
+                            });
+                        }),
+                        None => getters.push(quote! {
+                            methods.add_method(#lua_name, |_, data, ()| {
+                                Ok(::std::clone::Clone::clone(&data.#field))
+                            });
+                        }),
+                    }
+                }
+                quote!( #( #getters )* )
+            }
+            UserDataAttr::Setters => {
+                let fields = named_fields(item, Self::SETTERS_IDENT)?;
+                let rlua = &paths.rlua;
+                let mut setters = Vec::new();
+                for (field, opts) in fields.iter().filter(|(_, opts)| !opts.skip) {
+                    let ty = &field.ty;
+                    let field = field.ident.as_ref().unwrap();
+                    let lua_name = format!("set_{}", field);
+                    lua_names.insert(&lua_name, &field.to_string(), field.span())?;
+                    // Setters of read-only fields exist only to raise an error
+                    // saying so, rather than one about calling a nil value
+                    if opts.readonly {
+                        manifest.push(method_manifest(&lua_name, None, Vec::new(), "()".to_string()));
+                        let message = format!("field {} is read-only", field);
+                        setters.push(quote! {
+                            methods.add_method(#lua_name, |_, _, _: #rlua::MultiValue| -> #rlua::Result<()> {
+                                Err(#rlua::ExternalError::to_lua_err(#message))
+                            });
+                        });
+                    } else {
+                        manifest.push(method_manifest(&lua_name, None, vec![(field, ty)], "()".to_string()));
+                        let validation = opts.validation(&quote!(value), &field.to_string(), rlua);
+                        let conversion = ecosystem::from_lua_conversion(ty, &quote!(value), rlua).or_else(|| {
+                            checked_integer_conversion(ty, &quote!(value), "field", &field.to_string(), rlua)
+                        });
+                        let (ctx, param, conversion) = match conversion {
+                            Some(conversion) => (
+                                quote!(ctx),
+                                quote!(value: #rlua::Value),
+                                quote!(let value: #ty = #conversion?;),
+                            ),
+                            None => (quote!(_), quote!(value: #ty), quote!()),
+                        };
+                        setters.push(quote! {
+                            methods.add_method_mut(#lua_name, |#ctx, data, #param| {
+                                #conversion
+                                #validation
+                                data.#field = value;
+                                Ok(())
+                            });
+                        });
+                    }
+                }
+                quote!( #( #setters )* )
+            }
+            UserDataAttr::Update => {
+                let fields = named_fields(item, Self::UPDATE_IDENT)?;
+                let from_lua = paths.trait_from_lua();
+                let rlua = &paths.rlua;
+                let writable: Vec<_> = fields.iter().filter(|(_, opts)| !opts.skip && !opts.readonly).collect();
+                let (field_names, tys): (Vec<_>, Vec<_>) =
+                    writable.iter().map(|(f, _)| (f.ident.as_ref().unwrap(), &f.ty)).unzip();
+                let keys: Vec<_> = field_names.iter().map(|f| f.to_string()).collect();
+                let conversions = tys.iter().zip(&keys).map(|(ty, key)| {
+                    ecosystem::from_lua_conversion(ty, &quote!(value), rlua)
+                        .or_else(|| checked_integer_conversion(ty, &quote!(value), "field", key, rlua))
+                        .map_or_else(|| quote!(#from_lua::from_lua(value, ctx)?), |conversion| quote!(#conversion?))
+                });
+                let validations = writable
+                    .iter()
+                    .zip(&keys)
+                    .map(|((_, opts), key)| opts.validation(&quote!(value), key, rlua));
+                let readonly_keys = fields
+                    .iter()
+                    .filter(|(_, opts)| !opts.skip && opts.readonly)
+                    .map(|(f, _)| f.ident.as_ref().unwrap().to_string());
+                let values: Vec<_> = field_names.iter().map(|f| quote::format_ident!("update_{}", f)).collect();
+                lua_names.insert("update", Self::UPDATE_IDENT, item.span())?;
+                let (ctx, value) = if field_names.is_empty() {
+                    (quote!(_), quote!(_))
+                } else {
+                    (quote!(ctx), quote!(value))
+                };
+                // Every value is converted before any field is assigned, so
+                // that an invalid table leaves the user data unchanged
+                quote! {
+                    methods.add_method_mut("update", |#ctx, data, table: #rlua::Table| {
+                        #( let mut #values: Option<#tys> = None; )*
+                        for pair in table.pairs::<#rlua::String, #rlua::Value>() {
+                            let (key, #value) = pair?;
+                            let key_str = key.to_str()?;
+                            #(
+                                if key_str == #keys {
+                                    let value = #conversions;
+                                    #validations
+                                    #values = Some(value);
+                                } else
+                            )*
+                            #(
+                                if key_str == #readonly_keys {
+                                    return Err(#rlua::ExternalError::to_lua_err(format!("field {} is read-only", key_str)));
+                                } else
+                            )*
+                            {
+                                return Err(#rlua::ExternalError::to_lua_err(format!("No such field: {}", key_str)));
+                            }
+                        }
+                        #(
+                            if let Some(value) = #values {
+                                data.#field_names = value;
+                            }
+                        )*
+                        Ok(())
+                    });
+                }
+            }
+            UserDataAttr::Cmp => {
+                let rlua = &paths.rlua;
+                lua_names.insert("cmp", Self::CMP_IDENT, item.span())?;
+                quote! {
+                    methods.add_method("cmp", |_, data, other: Self| {
+                        match ::std::cmp::PartialOrd::partial_cmp(data, &other) {
+                            Some(ordering) => Ok(ordering as i8),
+                            None => {
+                                Err(#rlua::ExternalError::to_lua_err("values are not comparable"))
+                            }
+                        }
+                    });
+                }
+            }
+            UserDataAttr::Default => {
+                lua_names.insert("default", Self::DEFAULT_IDENT, item.span())?;
+                quote! {
+                    methods.add_function("default", |_, ()| Ok(<Self as ::std::default::Default>::default()));
+                }
+            }
+            UserDataAttr::Parse => {
+                let rlua = &paths.rlua;
+                lua_names.insert("parse", Self::PARSE_IDENT, item.span())?;
+                quote! {
+                    methods.add_function("parse", |_, s: #rlua::String| {
+                        <Self as ::std::str::FromStr>::from_str(s.to_str()?)
+                            .map_err(|error| #rlua::Error::external(::std::string::ToString::to_string(&error)))
+                    });
+                }
+            }
+        })
+    }
+}
+
+/// Returns the names of the fields of the given struct, or an error naming the
+/// given parameter if the item is not a struct with named fields
+fn named_fields<'a>(
+    item: &'a syn::Item,
+    param: &str,
+) -> Result<Vec<(&'a syn::Field, FieldOptions)>, TokenStream2> {
+    match item {
+        syn::Item::Struct(syn::ItemStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields
+            .named
+            .iter()
+            .map(|f| Ok((f, FieldOptions::try_parse(f)?)))
+            .collect(),
+        _ => {
+            let message = format!("{} can only be used on structs with named fields", param);
+            Err(quote_spanned! {
+                item.span() => compile_error!(#message);
+            })
+        }
+    }
+}
+
+/// The name and type of a field
+type NamedField<'a> = (&'a syn::Ident, &'a syn::Type);
+
+/// Returns the variants of the given enum, along with the named fields, and
+/// their types, which every variant has with the same type, in the order of the
+/// first variant. Fields skipped in any variant are left out, and it is an
+/// error if the item is not an enum or no field is shared
+fn common_fields(item: &syn::Item) -> Result<(Vec<&syn::Ident>, Vec<NamedField<'_>>), TokenStream2> {
+    let enum_ = match item {
+        syn::Item::Enum(e) => e,
+        _ => {
+            return Err(quote_spanned! {
+                item.span() => compile_error!("CommonFields can only be used on enums");
+            })
+        }
+    };
+    let mut variants = Vec::new();
+    for v in &enum_.variants {
+        let mut fields = Vec::new();
+        if let syn::Fields::Named(named) = &v.fields {
+            for f in &named.named {
+                if !FieldOptions::try_parse(f)?.skip {
+                    fields.push((f.ident.as_ref().unwrap(), &f.ty));
+                }
+            }
+        }
+        variants.push(fields);
+    }
+    let common: Vec<_> = match variants.split_first() {
+        Some((first, rest)) => first
+            .iter()
+            .filter(|(field, ty)| {
+                rest.iter()
+                    .all(|fields| fields.iter().any(|(f, t)| f == field && type_string(t) == type_string(ty)))
+            })
+            .copied()
+            .collect(),
+        None => Vec::new(),
+    };
+    if common.is_empty() {
+        return Err(quote_spanned! {
+            enum_.ident.span() => compile_error!("CommonFields found no named field of the same type in every variant");
+        });
+    }
+    Ok((enum_.variants.iter().map(|v| &v.ident).collect(), common))
+}
+
+/// Generates code registering the methods and metamethods of the `inner` type on
+/// the user data being generated. The inner type's `add_methods` is given an
+/// adapter which wraps each of its methods so that it operates on the inner
+/// value obtained from `data` by the given statements, which bind it to `inner`
+fn adapter_code(
+    name: &TokenStream2,
+    inner: &TokenStream2,
+    borrow: TokenStream2,
+    borrow_mut: TokenStream2,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    adapter_code_with(name, inner, borrow, borrow_mut, quote!(<#inner as #rlua::UserData>::add_methods), rlua)
+}
+
+/// Generates code registering the methods of the `inner` type as with
+/// `adapter_code`, but by calling the given function, which takes the methods
+/// to add them to, rather than the inner type's `add_methods`
+fn adapter_code_with(
+    name: &TokenStream2,
+    inner: &TokenStream2,
+    borrow: TokenStream2,
+    borrow_mut: TokenStream2,
+    register: TokenStream2,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    quote! {
+        {
+            struct Adapter<'a, Inner>(&'a mut Inner);
+
+            impl<'a, 'lua, Inner> #rlua::UserDataMethods<'lua, #inner> for Adapter<'a, Inner>
+            where
+                Inner: #rlua::UserDataMethods<'lua, #name>,
+            {
+                fn add_method<S, A, R, M>(&mut self, name: &S, method: M)
+                where
+                    S: ?Sized + AsRef<[u8]>,
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    M: 'static + Send + Fn(#rlua::Context<'lua>, &#inner, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_method(name, move |ctx, data: &#name, args| {
+                        #borrow
+                        method(ctx, inner, args)
+                    });
+                }
+
+                fn add_method_mut<S, A, R, M>(&mut self, name: &S, mut method: M)
+                where
+                    S: ?Sized + AsRef<[u8]>,
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    M: 'static + Send + FnMut(#rlua::Context<'lua>, &mut #inner, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_method_mut(name, move |ctx, data: &mut #name, args| {
+                        #borrow_mut
+                        method(ctx, inner, args)
+                    });
+                }
+
+                fn add_function<S, A, R, F>(&mut self, name: &S, function: F)
+                where
+                    S: ?Sized + AsRef<[u8]>,
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    F: 'static + Send + Fn(#rlua::Context<'lua>, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_function(name, function);
+                }
+
+                fn add_function_mut<S, A, R, F>(&mut self, name: &S, function: F)
+                where
+                    S: ?Sized + AsRef<[u8]>,
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    F: 'static + Send + FnMut(#rlua::Context<'lua>, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_function_mut(name, function);
+                }
+
+                fn add_meta_method<A, R, M>(&mut self, meta: #rlua::MetaMethod, method: M)
+                where
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    M: 'static + Send + Fn(#rlua::Context<'lua>, &#inner, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_meta_method(meta, move |ctx, data: &#name, args| {
+                        #borrow
+                        method(ctx, inner, args)
+                    });
+                }
+
+                fn add_meta_method_mut<A, R, M>(&mut self, meta: #rlua::MetaMethod, mut method: M)
+                where
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    M: 'static + Send + FnMut(#rlua::Context<'lua>, &mut #inner, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_meta_method_mut(meta, move |ctx, data: &mut #name, args| {
+                        #borrow_mut
+                        method(ctx, inner, args)
+                    });
+                }
+
+                fn add_meta_function<A, R, F>(&mut self, meta: #rlua::MetaMethod, function: F)
+                where
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    F: 'static + Send + Fn(#rlua::Context<'lua>, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_meta_function(meta, function);
+                }
+
+                fn add_meta_function_mut<A, R, F>(&mut self, meta: #rlua::MetaMethod, function: F)
+                where
+                    A: #rlua::FromLuaMulti<'lua>,
+                    R: #rlua::ToLuaMulti<'lua>,
+                    F: 'static + Send + FnMut(#rlua::Context<'lua>, A) -> #rlua::Result<R>,
+                {
+                    self.0.add_meta_function_mut(meta, function);
+                }
+            }
+
+            #register(&mut Adapter(methods));
+        }
+    }
+}
+
+/// Generates code registering the methods and metamethods of the base type on
+/// the user data being generated, which must deref to the base type
+fn extends_code(name: &TokenStream2, base: &syn::Type, rlua: &TokenStream2) -> TokenStream2 {
+    adapter_code(
+        name,
+        &quote!(#base),
+        quote!(let inner = &**data;),
+        quote!(let inner = &mut **data;),
+        rlua,
+    )
+}
+
+/// Generates code registering the exported methods of the given component type
+/// on the user data being generated, which must implement `AsRef` and `AsMut`
+/// of the component type, used to call the methods
+fn include_code(name: &TokenStream2, component: &syn::Type, paths: &CratePaths) -> TokenStream2 {
+    let rudeboy = &paths.rudeboy;
+    adapter_code_with(
+        name,
+        &quote!(#component),
+        quote!(let inner = <#name as ::std::convert::AsRef<#component>>::as_ref(data);),
+        quote!(let inner = <#name as ::std::convert::AsMut<#component>>::as_mut(data);),
+        quote!(<#component as #rudeboy::RudeboyMethods>::generate_methods),
+        &paths.rlua,
+    )
+}
+
+/// Generates a `new_in_lua` associated function for the given struct, which
+/// takes the struct's fields in order and returns them as a new Lua userdata.
+/// Returns nothing for other items, which have no single constructor
+fn new_in_lua_code(item: &syn::Item, rlua: &TokenStream2) -> TokenStream2 {
+    let s = match item {
+        syn::Item::Struct(s) if s.generics.params.is_empty() => s,
+        _ => return quote!(),
+    };
+    let name = &s.ident;
+    let vis = &s.vis;
+    let params: Vec<_> = s
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => ident.clone(),
+            None => quote::format_ident!("field{}", i),
+        })
+        .collect();
+    let tys = s.fields.iter().map(|f| &f.ty);
+    let value = match &s.fields {
+        syn::Fields::Named(_) => quote!(#name { #( #params ),* }),
+        syn::Fields::Unnamed(_) => quote!(#name ( #( #params ),* )),
+        syn::Fields::Unit => quote!(#name),
+    };
+    let doc = format!("Creates a new [`{}`] from its fields as a Lua userdata", name);
+    quote! {
+        impl #name {
+            #[doc = #doc]
+            #[allow(dead_code, clippy::too_many_arguments)]
+            #vis fn new_in_lua<'lua>(
+                ctx: #rlua::Context<'lua>,
+                #( #params: #tys ),*
+            ) -> #rlua::Result<#rlua::AnyUserData<'lua>> {
+                ctx.create_userdata(#value)
+            }
+        }
+    }
+}
+
+/// Generates an assertion that the given type is `Send`, and `Sync` too if
+/// `sync` is set, as needed by the generated code which creates user data of
+/// it. It is spanned at the type, so that a type which is not is reported there
+/// rather than deep inside rlua
+fn send_assertion(name: &TokenStream2, span: proc_macro2::Span, sync: bool) -> TokenStream2 {
+    let (assertion, bounds) = if sync {
+        (quote!(user_data_must_be_send_and_sync), quote!(::std::marker::Send + ::std::marker::Sync))
+    } else {
+        (quote!(user_data_must_be_send), quote!(::std::marker::Send))
+    };
+    quote_spanned! {
+        span =>
+        const _: fn() = || {
+            fn #assertion<T: ?Sized + #bounds>() {}
+            #assertion::<#name>();
+        };
+    }
+}
+
+/// Generates a `default_function` associated function for the given type,
+/// which creates a Lua function returning a fresh default value of the type,
+/// e.g. for use as `Type.default()` in a class table
+fn default_function_code(name: &TokenStream2, generics: &syn::Generics, rlua: &TokenStream2) -> TokenStream2 {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #where_clause {
+            /// Creates a Lua function which returns a new default value of this
+            /// type as a Lua userdata
+            #[allow(dead_code)]
+            pub fn default_function<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Function<'lua>> {
+                ctx.create_function(|_, ()| Ok(<Self as ::std::default::Default>::default()))
+            }
+        }
+    }
+}
+
+/// Generates the `parse_function` associated function for the given type, for
+/// use in a class table
+fn parse_function_code(name: &TokenStream2, generics: &syn::Generics, rlua: &TokenStream2) -> TokenStream2 {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #where_clause {
+            /// Creates a Lua function which parses a string into a value of this
+            /// type with `std::str::FromStr`, returned as a Lua userdata, and
+            /// raises the parse error as a Lua error if it fails
+            #[allow(dead_code)]
+            pub fn parse_function<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<#rlua::Function<'lua>> {
+                ctx.create_function(|_, s: #rlua::String| {
+                    <Self as ::std::str::FromStr>::from_str(s.to_str()?)
+                        .map_err(|error| #rlua::Error::external(::std::string::ToString::to_string(&error)))
+                })
+            }
+        }
+    }
+}
+
+/// Generates the `LUA_TYPE_NAME` constant for the given type, holding the given
+/// name or else the module path of the type, along with a function registering
+/// a predicate for the type under that name in the Lua registry
+fn type_name_code(
+    name: &TokenStream2,
+    generics: &syn::Generics,
+    type_name: Option<&str>,
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    let type_name = match type_name {
+        Some(type_name) => quote!(#type_name),
+        None => {
+            let name = type_string(name);
+            quote!(concat!(module_path!(), "::", #name))
+        }
+    };
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #where_clause {
+            /// The stable name identifying this type to Lua, returned by the
+            /// `type_name()` function of its userdata
+            pub const LUA_TYPE_NAME: &'static str = #type_name;
+
+            /// Stores a function in the Lua registry under [`Self::LUA_TYPE_NAME`]
+            /// which returns whether a value is a userdata of this type, so that
+            /// other libraries sharing the Lua state can identify it by name
+            #[allow(dead_code)]
+            pub fn register_type_name(ctx: #rlua::Context) -> #rlua::Result<()> {
+                let is_type = ctx.create_function(|_, value: #rlua::Value| {
+                    Ok(matches!(value, #rlua::Value::UserData(ud) if ud.is::<Self>()))
+                })?;
+                ctx.set_named_registry_value(Self::LUA_TYPE_NAME, is_type)
+            }
         }
     }
 }
 
+/// The keys of the metamethods which user_data registers itself
+struct Keys<'a> {
+    /// Those registered for the type's properties if it has any
+    properties: &'a [&'a str],
+    /// Those registered to look up the type's extensions
+    extensions: &'a [&'a str],
+}
+
+/// Generates the registration of the `methods()` and `metamethods()` functions
+/// of the given type. `methods()` returns a table keyed by the name of each
+/// function a script may call on the user data, holding the `min` and `max`
+/// number of arguments it takes if known, with `max` nil if any number of
+/// further arguments is accepted. `metamethods()` returns the sorted keys of
+/// the metamethods registered on the user data, e.g. `__add`, including those
+/// registered by user_data itself
+fn introspect_code(
+    name: &TokenStream2,
+    user_data_attrs: &HashSet<UserDataAttr>,
+    keys: Keys,
+    includes: &[syn::Type],
+    own_names: &[&str],
+    rlua: &TokenStream2,
+) -> TokenStream2 {
+    let has_methods = user_data_attrs.contains(&UserDataAttr::Methods);
+    // The type's own methods are listed last, so that their arities take
+    // precedence as they do when registered
+    let (methods_fn, metamethods_fn) = (UserDataAttr::METHODS_FN, UserDataAttr::METAMETHODS_FN);
+    let introspection = [methods_fn, metamethods_fn];
+    let mut arities = vec![quote!(&[#( (#introspection, 0, Some(0)) ),*])];
+    arities.extend(includes.iter().map(|component| quote!(<#component>::RUDEBOY_ARITIES)));
+    let mut methods = includes.iter().map(|component| quote!(<#component>::LUA_METHODS)).collect::<Vec<_>>();
+    if has_methods {
+        arities.push(quote!(<#name>::RUDEBOY_ARITIES));
+        methods.push(quote!(<#name>::LUA_METHODS));
+    }
+    let Keys { properties, extensions } = keys;
+    let mut keys = vec![quote! {
+        names.extend(parts.iter().flat_map(|part| part.iter()).filter(|name| name.starts_with("__")));
+    }];
+    if !extensions.is_empty() {
+        keys.push(quote!(names.extend([#( #extensions ),*]);));
+    }
+    if !properties.is_empty() {
+        keys.push(quote! {
+            if !<#name>::LUA_PROPERTIES.is_empty() {
+                names.extend([#( #properties ),*]);
+            }
+        });
+    }
+    if user_data_attrs.contains(&UserDataAttr::MetaMethods) {
+        keys.push(quote! {
+            names.extend(<#name>::LUA_METAMETHODS.iter().filter(|name| name.starts_with("__")));
+        });
+    }
+    quote! {
+        methods.add_method(#methods_fn, |ctx, _, ()| {
+            let table = ctx.create_table()?;
+            for name in [#( #own_names ),*] {
+                table.set(name, ctx.create_table()?)?;
+            }
+            let parts: &[&[(&str, usize, Option<usize>)]] = &[#( #arities ),*];
+            for &(name, min, max) in parts.iter().flat_map(|part| part.iter()) {
+                let arity = ctx.create_table()?;
+                arity.set("min", min)?;
+                arity.set("max", max)?;
+                table.set(name, arity)?;
+            }
+            Ok(table)
+        });
+        methods.add_method(#metamethods_fn, |ctx, _, ()| {
+            let parts: &[&[&str]] = &[#( #methods ),*];
+            let mut names: Vec<&str> = Vec::new();
+            #( #keys )*
+            names.sort_unstable();
+            names.dedup();
+            ctx.create_sequence_from(names).map(#rlua::Value::Table)
+        });
+    }
+}
+
+/// Generates the functions setting and looking up the extension table of the
+/// given type, which is kept in the Lua registry under a name unique to the
+/// type
+fn extensions_code(name: &TokenStream2, generics: &syn::Generics, rlua: &TokenStream2) -> TokenStream2 {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #where_clause {
+            fn rudeboy_extensions_key() -> String {
+                format!("rudeboy_extensions:{}", ::std::any::type_name::<Self>())
+            }
+
+            /// Sets the table which this type's Index metamethod consults for
+            /// keys naming no field, method, or property, e.g. to add methods
+            /// written in Lua, or removes it if given None. Scripts may also
+            /// set it with `value:set_extensions(table)`
+            pub fn set_extensions<'lua>(
+                ctx: #rlua::Context<'lua>,
+                extensions: Option<#rlua::Table<'lua>>,
+            ) -> #rlua::Result<()> {
+                ctx.set_named_registry_value(&Self::rudeboy_extensions_key(), extensions)
+            }
+
+            /// Returns the table set by `set_extensions`, if any
+            pub fn extensions<'lua>(ctx: #rlua::Context<'lua>) -> #rlua::Result<Option<#rlua::Table<'lua>>> {
+                ctx.named_registry_value(&Self::rudeboy_extensions_key())
+            }
+
+            #[doc(hidden)]
+            pub fn rudeboy_extension<'lua>(
+                ctx: #rlua::Context<'lua>,
+                key: #rlua::Value<'lua>,
+            ) -> #rlua::Result<#rlua::Value<'lua>> {
+                match Self::extensions(ctx)? {
+                    Some(extensions) => extensions.get(key),
+                    None => Ok(#rlua::Value::Nil),
+                }
+            }
+        }
+    }
+}
+
+/// Generates an associated constant which fails to evaluate if any of the given
+/// names registered by user_data, e.g. the getters of the Getters parameter, is
+/// also registered by the methods attribute, which would silently replace one
+/// of them. With the MetaMethods parameter, the metamethods registered by the
+/// metamethods attribute, e.g. the `__len` of Collection, are compared as well
+fn unique_names_code(name: &TokenStream2, generics: &syn::Generics, own_names: &[&str], metamethods: bool) -> TokenStream2 {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let mut checks: Vec<_> = own_names
+        .iter()
+        .map(|own| {
+            let message = format!("`{}` is registered both by user_data and by the methods attribute", own);
+            quote! {
+                if contains(Self::LUA_METHODS, #own) {
+                    panic!(#message);
+                }
+            }
+        })
+        .collect();
+    if metamethods {
+        checks.extend(RLUA_META_METHODS.iter().map(|metamethod| {
+            let key = format!("__{}", metamethod.to_lowercase());
+            let message = format!("`{}` is registered both by the metamethods attribute and by the methods attribute", key);
+            quote! {
+                if contains(Self::LUA_METAMETHODS, #key) && contains(Self::LUA_METHODS, #key) {
+                    panic!(#message);
+                }
+            }
+        }));
+    }
+    quote! {
+        impl #impl_generics #name #where_clause {
+            #[doc(hidden)]
+            const RUDEBOY_UNIQUE_NAMES: () = {
+                const fn contains(names: &[&str], name: &str) -> bool {
+                    let name = name.as_bytes();
+                    let mut i = 0;
+                    while i < names.len() {
+                        let other = names[i].as_bytes();
+                        if other.len() == name.len() {
+                            let mut j = 0;
+                            while j < name.len() && other[j] == name[j] {
+                                j += 1;
+                            }
+                            if j == name.len() {
+                                return true;
+                            }
+                        }
+                        i += 1;
+                    }
+                    false
+                }
+                #( #checks )*
+            };
+        }
+    }
+}
+
+/// Generates the `LUA_EXPORTS` constant for the given type, concatenating the
+/// given slices of names at compile time
+fn exports_code(name: &TokenStream2, generics: &syn::Generics, parts: &[TokenStream2]) -> TokenStream2 {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #where_clause {
+            /// Every name exported to Lua on this type's userdata: its methods,
+            /// metamethods, and the fields accessible through them
+            pub const LUA_EXPORTS: &'static [&'static str] = {
+                const PARTS: &[&[&str]] = &[#( #parts ),*];
+                const LEN: usize = {
+                    let mut len = 0;
+                    let mut i = 0;
+                    while i < PARTS.len() {
+                        len += PARTS[i].len();
+                        i += 1;
+                    }
+                    len
+                };
+                const EXPORTS: [&str; LEN] = {
+                    let mut exports = [""; LEN];
+                    let mut n = 0;
+                    let mut i = 0;
+                    while i < PARTS.len() {
+                        let mut j = 0;
+                        while j < PARTS[i].len() {
+                            exports[n] = PARTS[i][j];
+                            n += 1;
+                            j += 1;
+                        }
+                        i += 1;
+                    }
+                    exports
+                };
+                &EXPORTS
+            };
+        }
+    }
+}
+
+/// The lock used by a generated shared handle type
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SharedLock {
+    /// An `RwLock`, where conflicting borrows raise an error as with `RefCell`
+    RwLock,
+    /// A `Mutex`, where a borrow while the lock is held raises an error as
+    /// with `RefCell`, as does a poisoned lock
+    Mutex,
+}
+
+impl SharedLock {
+    const RWLOCK: &'static str = "rwlock";
+    const MUTEX: &'static str = "mutex";
+
+    fn try_parse(lit: &syn::Lit) -> Result<SharedLock, TokenStream2> {
+        match lit {
+            syn::Lit::Str(s) if s.value() == Self::RWLOCK => Ok(SharedLock::RwLock),
+            syn::Lit::Str(s) if s.value() == Self::MUTEX => Ok(SharedLock::Mutex),
+            _ => Err(quote_spanned! {
+                lit.span() => compile_error!("Expected one of: \"rwlock\", \"mutex\"");
+            }),
+        }
+    }
+}
+
+/// Generates a shared handle type for the given type definition, named by
+/// appending `Shared` to its name, along with its impl of `rlua::UserData`. Lua
+/// values created from a handle refer to the same value as the handle, rather
+/// than to a copy of it
+fn shared_code(item: &syn::Item, lock: SharedLock, rlua: &TokenStream2) -> Result<TokenStream2, TokenStream2> {
+    let (vis, ident, generics) = match item {
+        syn::Item::Struct(s) => (&s.vis, &s.ident, &s.generics),
+        syn::Item::Enum(e) => (&e.vis, &e.ident, &e.generics),
+        syn::Item::Type(t) => (&t.vis, &t.ident, &t.generics),
+        _ => {
+            return Err(quote_spanned! {
+                item.span() => compile_error!("shared can only be used on a struct, enum, or type alias definition");
+            })
+        }
+    };
+    if !generics.params.is_empty() {
+        return Err(quote_spanned! {
+            generics.span() => compile_error!("shared cannot be used on a generic type");
+        });
+    }
+    let shared = quote::format_ident!("{}Shared", ident);
+    let name = quote!(#ident);
+    let (lock_ty, borrow, borrow_mut) = match lock {
+        SharedLock::RwLock => {
+            let borrow_error = format!("{} is already mutably borrowed", ident);
+            let borrow_mut_error = format!("{} is already borrowed", ident);
+            (
+                quote!(::std::sync::RwLock),
+                quote! {
+                    let guard = data.0.try_read().map_err(|_| {
+                        #rlua::ExternalError::to_lua_err(#borrow_error)
+                    })?;
+                    let inner = &*guard;
+                },
+                quote! {
+                    let mut guard = data.0.try_write().map_err(|_| {
+                        #rlua::ExternalError::to_lua_err(#borrow_mut_error)
+                    })?;
+                    let inner = &mut *guard;
+                },
+            )
+        }
+        SharedLock::Mutex => {
+            let borrowed_error = format!("{} is already borrowed", ident);
+            let poisoned_error = format!("The lock on the {} was poisoned", ident);
+            let lock = quote! {
+                match data.0.try_lock() {
+                    Ok(guard) => guard,
+                    Err(::std::sync::TryLockError::WouldBlock) => {
+                        return Err(#rlua::ExternalError::to_lua_err(#borrowed_error));
+                    }
+                    Err(::std::sync::TryLockError::Poisoned(_)) => {
+                        return Err(#rlua::ExternalError::to_lua_err(#poisoned_error));
+                    }
+                }
+            };
+            (
+                quote!(::std::sync::Mutex),
+                quote! {
+                    let guard = #lock;
+                    let inner = &*guard;
+                },
+                quote! {
+                    let mut guard = #lock;
+                    let inner = &mut *guard;
+                },
+            )
+        }
+    };
+    let register = adapter_code(&quote!(#shared), &name, borrow, borrow_mut, rlua);
+    let doc = format!(
+        "A shared handle to a [`{}`], through which Lua and Rust access the same value",
+        ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Clone)]
+        #vis struct #shared(pub ::std::sync::Arc<#lock_ty<#name>>);
+
+        impl #shared {
+            /// Creates a handle to the given value
+            pub fn new(value: #name) -> Self {
+                #shared(::std::sync::Arc::new(#lock_ty::new(value)))
+            }
+        }
+
+        const _: () = {
+            impl ::std::convert::From<#name> for #shared {
+                fn from(value: #name) -> Self {
+                    #shared::new(value)
+                }
+            }
+
+            impl #rlua::UserData for #shared {
+                fn add_methods<'lua, M: #rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                    #register
+                }
+            }
+        };
+    })
+}
+
+/// The parsed parameters of the user_data attribute
+#[derive(Default)]
+pub(crate) struct UserDataParams<'a> {
+    /// The set of parameters
+    pub attrs: HashSet<UserDataAttr>,
+    /// Any metamethods given in a `MetaMethods(...)` list
+    pub metamethods: Vec<&'a syn::NestedMeta>,
+    /// The base type whose methods and metamethods are inherited, if any
+    pub extends: Option<syn::Type>,
+    /// The component types whose exported methods are also registered
+    pub includes: Vec<syn::Type>,
+    /// The lock used by the shared handle type to generate, if any
+    pub shared: Option<SharedLock>,
+    /// The stable name identifying the type to Lua, if one should be generated.
+    /// The inner value is None if the default name, i.e. the module path of
+    /// the type, should be used
+    pub type_name: Option<Option<String>>,
+    /// Whether to generate the `LUA_EXPORTS` constant listing every name
+    /// exported to Lua
+    pub exports: bool,
+    /// Whether to register the `methods()` and `metamethods()` functions
+    /// listing the user data's methods and metamethods at runtime
+    pub introspect: bool,
+    /// Whether Index falls back to an extension table which the host or
+    /// scripts may set
+    pub extensions: bool,
+    /// The paths to the rudeboy and rlua crates
+    pub paths: CratePaths,
+}
+
+/// Parses the user_data parameters
 fn attrs_to_user_data_attrs(
     attrs: Vec<&syn::NestedMeta>,
-) -> Result<HashSet<UserDataAttr>, TokenStream2> {
+) -> Result<UserDataParams<'_>, TokenStream2> {
     let mut ret = HashSet::new();
+    let mut metamethods = Vec::new();
+    let mut extends = None;
+    let mut includes = Vec::new();
+    let mut shared = None;
+    let mut type_name = None;
+    let mut exports = false;
+    let mut introspect = false;
+    let mut extensions = false;
+    let mut paths = CratePaths::default();
     for attr in attrs {
+        if paths.try_parse_option(attr)? {
+            continue;
+        }
         use syn::{Meta, NestedMeta};
         ret.insert(match attr {
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(UserDataAttr::SHARED_IDENT) => {
+                shared = Some(SharedLock::RwLock);
+                continue;
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(UserDataAttr::SHARED_IDENT) => {
+                shared = Some(SharedLock::try_parse(&nv.lit)?);
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(UserDataAttr::TYPE_NAME_IDENT) => {
+                type_name = Some(None);
+                continue;
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(UserDataAttr::TYPE_NAME_IDENT) => {
+                type_name = Some(Some(match &nv.lit {
+                    syn::Lit::Str(s) => s.value(),
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the type name");
+                        })
+                    }
+                }));
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(UserDataAttr::EXPORTS_IDENT) => {
+                exports = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(UserDataAttr::INTROSPECT_IDENT) => {
+                introspect = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident(UserDataAttr::EXTENSIONS_IDENT) => {
+                extensions = true;
+                continue;
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(UserDataAttr::EXTENDS_IDENT) => {
+                extends = Some(match &nv.lit {
+                    syn::Lit::Str(s) => s.parse::<syn::Type>().map_err(|e| e.to_compile_error())?,
+                    lit => {
+                        return Err(quote_spanned! {
+                            lit.span() => compile_error!("Expected a string containing the base type");
+                        })
+                    }
+                });
+                continue;
+            }
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(UserDataAttr::INCLUDE_IDENT) => {
+                for nested in &l.nested {
+                    includes.push(match nested {
+                        NestedMeta::Meta(Meta::Path(p)) => syn::parse_quote!(#p),
+                        NestedMeta::Lit(syn::Lit::Str(s)) => s.parse::<syn::Type>().map_err(|e| e.to_compile_error())?,
+                        _ => {
+                            return Err(quote_spanned! {
+                                nested.span() => compile_error!("Expected a type, or a string containing one");
+                            })
+                        }
+                    });
+                }
+                continue;
+            }
             NestedMeta::Meta(Meta::Path(p)) => UserDataAttr::try_parse(p)?,
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(UserDataAttr::META_METHODS_IDENT) => {
+                metamethods.extend(l.nested.iter());
+                UserDataAttr::MetaMethods
+            }
+            NestedMeta::Meta(Meta::List(l)) if l.path.is_ident(UserDataAttr::VARIANT_PREDICATES_IDENT) => {
+                UserDataAttr::try_parse_variant_predicates(l)?
+            }
             _ => {
                 return Err(quote_spanned! {
                     attr.span() => compile_error!("Expected a valid user_data identifier");
-                }
-                .into())
+                })
             }
         });
     }
-    Ok(ret)
+    Ok(UserDataParams {
+        attrs: ret,
+        metamethods,
+        extends,
+        includes,
+        shared,
+        type_name,
+        exports,
+        introspect,
+        extensions,
+        paths,
+    })
 }
 
-pub(crate) fn impl_user_data_attr_macro(
-    item: syn::Item,
-    attrs: Vec<&syn::NestedMeta>,
-) -> TokenStream2 {
-    let name = if let syn::Item::Impl(i) = &item {
+/// Generates the impl of `rlua::UserData` for the given item, along with the
+/// impl of `RudeboyMetaMethods` if a list of metamethods was given
+pub(crate) fn user_data_impl(
+    item: &syn::Item,
+    params: UserDataParams,
+) -> Result<TokenStream2, TokenStream2> {
+    let UserDataParams {
+        attrs: user_data_attrs,
+        metamethods,
+        extends,
+        includes,
+        shared,
+        type_name,
+        exports,
+        introspect,
+        extensions,
+        paths,
+    } = params;
+    let rlua = &paths.rlua;
+    // The generic parameters of the item, including const generics, are
+    // carried through to every impl generated for it
+    let (name, generics) = if let syn::Item::Impl(i) = item {
         let self_ty = &i.self_ty;
-        quote!(#self_ty)
-    } else if let syn::Item::Struct(s) = &item {
+        (quote!(#self_ty), &i.generics)
+    } else if let syn::Item::Struct(s) = item {
         let name = &s.ident;
-        quote!(#name)
-    } else if let syn::Item::Enum(e) = &item {
+        let (_, ty_generics, _) = s.generics.split_for_impl();
+        (quote!(#name #ty_generics), &s.generics)
+    } else if let syn::Item::Enum(e) = item {
         let name = &e.ident;
-        quote!(#name)
+        let (_, ty_generics, _) = e.generics.split_for_impl();
+        (quote!(#name #ty_generics), &e.generics)
+    } else if let syn::Item::Type(t) = item {
+        // The impl is generated for the aliased type, which must be concrete
+        // apart from const generic parameters, e.g.
+        // `type Square<const N: usize> = Matrix<f64, N, N>;`
+        if let Some(param) = t.generics.params.iter().find(|p| !matches!(p, syn::GenericParam::Const(_))) {
+            return Err(quote_spanned! {
+                param.span() => compile_error!("user_data macro can only be applied to type aliases whose generic parameters are const generics");
+            });
+        }
+        let ty = &t.ty;
+        (quote!(#ty), &t.generics)
     } else {
-        return quote_spanned! {
-            item.span() => compile_error!("user_data macro can only be applied to a struct or an inherent impl block");
+        return Err(quote_spanned! {
+            item.span() => compile_error!("user_data macro can only be applied to a struct, an enum, a type alias, or an inherent impl block");
+        });
+    };
+
+    // Getters and setters of the type's methods are accessed through Index and
+    // NewIndex, which are registered just for them if they were not listed
+    let has_methods = user_data_attrs.contains(&UserDataAttr::Methods);
+    let mut properties_code = Vec::new();
+    let mut property_keys = Vec::new();
+    // Index is registered whether or not there are properties if it falls back
+    // to the extension table
+    let mut extension_keys = Vec::new();
+    let extension_index = if extensions && !lists_index(&metamethods) {
+        extension_keys.push("__index");
+        let (data, property) = if has_methods {
+            let property = quote! {
+                if let #rlua::Value::String(index) = &index {
+                    if let Some(value) = Self::rudeboy_get_property(data, ctx, index.to_str()?)? {
+                        return Ok(value);
+                    }
+                }
+            };
+            (quote!(data), property)
+        } else {
+            (quote!(_), quote!())
         };
+        quote! {
+            methods.add_meta_method(#rlua::MetaMethod::Index, |ctx, #data, index: #rlua::Value| {
+                #property
+                Self::rudeboy_extension(ctx, index)
+            });
+        }
+    } else {
+        quote!()
     };
+    if has_methods && !extensions && !lists_index(&metamethods) {
+        property_keys.push("__index");
+        properties_code.push(quote! {
+            methods.add_meta_method(#rlua::MetaMethod::Index, |ctx, data, index: #rlua::String| {
+                Ok(Self::rudeboy_get_property(data, ctx, index.to_str()?)?.unwrap_or(#rlua::Value::Nil))
+            });
+        });
+    }
+    if has_methods && !lists_new_index(&metamethods) {
+        property_keys.push("__newindex");
+        properties_code.push(quote! {
+            methods.add_meta_method_mut(#rlua::MetaMethod::NewIndex, |ctx, data, (index, value): (#rlua::String, #rlua::Value)| {
+                let index_str = index.to_str()?;
+                if Self::rudeboy_set_property(data, ctx, index_str, value)? {
+                    Ok(())
+                } else {
+                    Err(#rlua::ExternalError::to_lua_err(format!("No such index: {}", index_str)))
+                }
+            });
+        });
+    }
+    let properties_code = if properties_code.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            if !Self::LUA_PROPERTIES.is_empty() {
+                #( #properties_code )*
+            }
+        }
+    };
+    let metamethods_impl = if metamethods.is_empty() {
+        quote!()
+    } else {
+        let di = item_to_derive_input(item).map_err(|_| {
+            quote_spanned! {
+                item.span() => compile_error!("A list of metamethods can only be given on a struct or enum");
+            }
+        })?;
+        metamethods_impl(&di, metamethods, paths.clone(), has_methods, extensions)?
+    };
+
+    // The base type's methods are registered first, so that the user data's own
+    // methods and metamethods take precedence
+    let extends_code = match &extends {
+        Some(base) => extends_code(&name, base, rlua),
+        None => quote!(),
+    };
+    // The methods of included components likewise give way to the type's own
+    let include_code = includes.iter().map(|component| include_code(&name, component, &paths));
+
+    let shared_code = match shared {
+        Some(lock) => shared_code(item, lock, rlua)?,
+        None => quote!(),
+    };
+    let new_in_lua_code = new_in_lua_code(item, rlua);
+    // rlua requires user data to be Send, which the generated constructors and
+    // shared handles rely on. A shared handle's `Arc<RwLock<T>>` is only Send
+    // if the type is also Sync
+    let creates_user_data = !new_in_lua_code.is_empty()
+        || shared.is_some()
+        || user_data_attrs.contains(&UserDataAttr::Default)
+        || user_data_attrs.contains(&UserDataAttr::Parse);
+    // The assertion cannot name a generic type outside of its impls, where
+    // rlua's own bounds report it instead
+    let send_assertion = if creates_user_data && generics.params.is_empty() {
+        let span = match item {
+            syn::Item::Struct(s) => s.ident.span(),
+            syn::Item::Enum(e) => e.ident.span(),
+            item => item.span(),
+        };
+        send_assertion(&name, span, shared == Some(SharedLock::RwLock))
+    } else {
+        quote!()
+    };
+    let default_code = if user_data_attrs.iter().any(|a| matches!(a, UserDataAttr::Default)) {
+        default_function_code(&name, generics, rlua)
+    } else {
+        quote!()
+    };
+    let parse_code = if user_data_attrs.contains(&UserDataAttr::Parse) {
+        parse_function_code(&name, generics, rlua)
+    } else {
+        quote!()
+    };
+
+    let mut lua_names = LuaNames::default();
+    let (type_name_method, type_name_code) = match &type_name {
+        Some(type_name) => {
+            lua_names.insert(UserDataAttr::TYPE_NAME_IDENT, UserDataAttr::TYPE_NAME_IDENT, item.span())?;
+            let method = quote! {
+                methods.add_function("type_name", |_, ()| Ok(Self::LUA_TYPE_NAME));
+            };
+            (method, type_name_code(&name, generics, type_name.as_deref(), rlua))
+        }
+        None => (quote!(), quote!()),
+    };
+    let mut manifest = Vec::new();
+    let inner_code = user_data_attrs
+        .iter()
+        .map(|a| a.get_code(name.clone(), item, &paths, &mut lua_names, &mut manifest))
+        .collect::<Result<Vec<_>, _>>()?;
+    // The methods registered here are not listed in the manifest of the methods
+    // attribute, so are written to one of their own
+    let manifest = if manifest.is_empty() {
+        quote!()
+    } else {
+        let manifest_name = match item {
+            syn::Item::Struct(s) => s.ident.to_string(),
+            syn::Item::Enum(e) => e.ident.to_string(),
+            syn::Item::Type(t) => t.ident.to_string(),
+            _ => type_string(&name),
+        };
+        write_manifest(
+            item.span(),
+            &manifest_name,
+            "user_data",
+            Json::Object(vec![
+                ("type", Json::str(&manifest_name)),
+                ("methods", Json::Array(manifest)),
+            ]),
+        )
+    };
+    let (extension_code, extensions_code) = if extensions {
+        lua_names.insert(UserDataAttr::SET_EXTENSIONS_FN, UserDataAttr::EXTENSIONS_IDENT, item.span())?;
+        let set_extensions = UserDataAttr::SET_EXTENSIONS_FN;
+        let method = quote! {
+            methods.add_method(#set_extensions, |ctx, _, extensions: Option<#rlua::Table>| Self::set_extensions(ctx, extensions));
+        };
+        (method, extensions_code(&name, generics, rlua))
+    } else {
+        (quote!(), quote!())
+    };
+    let introspect_code = if introspect {
+        for name in [UserDataAttr::METHODS_FN, UserDataAttr::METAMETHODS_FN] {
+            lua_names.insert(name, UserDataAttr::INTROSPECT_IDENT, item.span())?;
+        }
+        let keys = Keys { properties: &property_keys, extensions: &extension_keys };
+        introspect_code(&name, &user_data_attrs, keys, &includes, &lua_names.lua_names(), rlua)
+    } else {
+        quote!()
+    };
+    // The names registered here and by the metamethods attribute are only known
+    // to differ from those of the methods attribute once both have been
+    // expanded, so they are compared when the userdata's methods are added
+    let has_metamethods = user_data_attrs.contains(&UserDataAttr::MetaMethods);
+    let (collision_check, collision_code) = if has_methods && (has_metamethods || !lua_names.lua_names().is_empty()) {
+        let check = quote!(let () = Self::RUDEBOY_UNIQUE_NAMES;);
+        (check, unique_names_code(&name, generics, &lua_names.lua_names(), has_metamethods))
+    } else {
+        (quote!(), quote!())
+    };
+    let exports_code = if exports {
+        let own_names = lua_names.lua_names();
+        let mut parts = vec![quote!(&[#( #own_names ),*])];
+        if has_methods {
+            parts.push(quote!(<#name>::LUA_METHODS));
+            parts.push(quote!(<#name>::LUA_PROPERTIES));
+        }
+        if user_data_attrs.contains(&UserDataAttr::MetaMethods) {
+            parts.push(quote!(<#name>::LUA_METAMETHODS));
+            parts.push(quote!(<#name>::LUA_FIELDS));
+        }
+        exports_code(&name, generics, &parts)
+    } else {
+        quote!()
+    };
+
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let generated = paths.allow_lints(quote! {
+        const _: () = {
+            impl #impl_generics #rlua::UserData for #name #where_clause {
+                fn add_methods<'lua, M: #rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                    #collision_check
+                    #extends_code
+                    #( #include_code )*
+                    #type_name_method
+                    #( #inner_code )*
+                    #properties_code
+                    #extension_index
+                    #extension_code
+                    #introspect_code
+                }
+            }
+        };
+
+        #send_assertion
+
+        #shared_code
+
+        #new_in_lua_code
+
+        #default_code
+
+        #parse_code
+
+        #type_name_code
 
-    let inner_code: Vec<_> = match attrs_to_user_data_attrs(attrs) {
+        #extensions_code
+
+        #exports_code
+
+        #collision_code
+    });
+    Ok(quote! {
+        #metamethods_impl
+
+        #manifest
+
+        #generated
+    })
+}
+
+pub(crate) fn impl_user_data_attr_macro(
+    item: syn::Item,
+    attrs: Vec<&syn::NestedMeta>,
+) -> TokenStream2 {
+    let params = match attrs_to_user_data_attrs(attrs) {
         Ok(uda) => uda,
         Err(e) => return e,
-    }
-    .iter()
-    .map(|a| a.get_code(name.clone()))
-    .collect();
+    };
+
+    let user_data_impl = match user_data_impl(&item, params) {
+        Ok(udi) => udi,
+        Err(e) => return e,
+    };
 
+    let item = strip_helper_attrs(&item);
     quote! {
         #item
 
-        impl ::rlua::UserData for #name {
-            fn add_methods<'lua, M: ::rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-                #( #inner_code )*
-            }
-        }
+        #user_data_impl
     }
 }
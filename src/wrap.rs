@@ -0,0 +1,169 @@
+use crate::methods::{impl_methods_attr_macro, MethodsOptions};
+use crate::user_data::{user_data_impl, UserDataAttr, UserDataParams};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+
+const METHODS_IDENT: &str = "methods";
+
+/// A method of the wrapped type which the wrapper forwards to, given by the
+/// signature the wrapper exports it with
+struct ForwardedMethod {
+    attrs: Vec<syn::Attribute>,
+    sig: syn::Signature,
+}
+
+impl Parse for ForwardedMethod {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(ForwardedMethod {
+            attrs: input.call(syn::Attribute::parse_outer)?,
+            sig: input.parse()?,
+        })
+    }
+}
+
+/// The input of the wrap macro, e.g.
+/// `pub LuaPath for std::path::PathBuf { methods: [fn exists(&self) -> bool] }`
+pub(crate) struct WrapInput {
+    attrs: Vec<syn::Attribute>,
+    vis: syn::Visibility,
+    ident: syn::Ident,
+    inner: syn::Type,
+    methods: Vec<ForwardedMethod>,
+}
+
+impl Parse for WrapInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let ident = input.parse()?;
+        input.parse::<syn::Token![for]>()?;
+        let inner = input.parse()?;
+        let content;
+        syn::braced!(content in input);
+        let mut methods = Vec::new();
+        if !content.is_empty() {
+            let key: syn::Ident = content.parse()?;
+            if key != METHODS_IDENT {
+                return Err(syn::Error::new(key.span(), "Expected `methods: [...]`"));
+            }
+            content.parse::<syn::Token![:]>()?;
+            let list;
+            syn::bracketed!(list in content);
+            methods.extend(list.parse_terminated::<_, syn::Token![,]>(ForwardedMethod::parse)?);
+            content.parse::<Option<syn::Token![,]>>()?;
+        }
+        Ok(WrapInput {
+            attrs,
+            vis,
+            ident,
+            inner,
+            methods,
+        })
+    }
+}
+
+/// Generates a method of the wrapper with the given signature, which calls the
+/// wrapped value's method of the same name with the same arguments, and
+/// converts its return value with `From`, e.g. into the wrapper
+fn forwarding_method(method: &ForwardedMethod, vis: &syn::Visibility) -> Result<TokenStream2, TokenStream2> {
+    let ForwardedMethod { attrs, sig } = method;
+    if sig.receiver().is_none() {
+        return Err(quote_spanned! {
+            sig.span() => compile_error!("Expected a method taking self");
+        });
+    }
+    let mut args = Vec::new();
+    for input in sig.inputs.iter() {
+        if let syn::FnArg::Typed(pat) = input {
+            match pat.pat.as_ref() {
+                syn::Pat::Ident(i) => args.push(&i.ident),
+                pat => {
+                    return Err(quote_spanned! {
+                        pat.span() => compile_error!("Expected an identifier here");
+                    })
+                }
+            }
+        }
+    }
+    let name = &sig.ident;
+    Ok(quote! {
+        #( #attrs )*
+        #vis #sig {
+            ::std::convert::From::from(self.0.#name(#( #args ),*))
+        }
+    })
+}
+
+pub(crate) fn impl_wrap_macro(input: WrapInput) -> TokenStream2 {
+    let WrapInput {
+        attrs,
+        vis,
+        ident,
+        inner,
+        methods,
+    } = input;
+    let forwarded = match methods.iter().map(|m| forwarding_method(m, &vis)).collect::<Result<Vec<_>, _>>() {
+        Ok(forwarded) => forwarded,
+        Err(e) => return e,
+    };
+
+    let item: syn::Item = syn::parse_quote! {
+        #( #attrs )*
+        #vis struct #ident(pub #inner);
+    };
+    let methods_impl: syn::Item = syn::parse_quote! {
+        impl #ident {
+            #( #forwarded )*
+        }
+    };
+    // A wrapper forwarding no methods has none to export
+    let mut params = UserDataParams::default();
+    let methods_impl = if forwarded.is_empty() {
+        quote!()
+    } else {
+        params.attrs.insert(UserDataAttr::Methods);
+        impl_methods_attr_macro(methods_impl, &MethodsOptions::default())
+    };
+    let user_data_impl = match user_data_impl(&item, params) {
+        Ok(udi) => udi,
+        Err(e) => return e,
+    };
+
+    quote! {
+        #item
+
+        const _: () = {
+            impl ::std::ops::Deref for #ident {
+                type Target = #inner;
+
+                fn deref(&self) -> &#inner {
+                    &self.0
+                }
+            }
+
+            impl ::std::ops::DerefMut for #ident {
+                fn deref_mut(&mut self) -> &mut #inner {
+                    &mut self.0
+                }
+            }
+
+            impl ::std::convert::From<#inner> for #ident {
+                fn from(inner: #inner) -> Self {
+                    #ident(inner)
+                }
+            }
+
+            impl ::std::convert::From<#ident> for #inner {
+                fn from(wrapper: #ident) -> Self {
+                    wrapper.0
+                }
+            }
+        };
+
+        #methods_impl
+
+        #user_data_impl
+    }
+}
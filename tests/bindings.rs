@@ -0,0 +1,93 @@
+// Generated code names the rudeboy crate by `::rudeboy`, which this crate
+// stands in for
+extern crate self as rudeboy;
+
+mod common;
+pub use common::*;
+
+use rlua::Lua;
+
+#[rudeboy::user_data(MetaMethods(Eq, Index, NewIndex), Methods)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Counter {
+    pub count: i64,
+    pub label: String,
+}
+
+// The generated test calls `clamp` with a default argument of 0, which its
+// range rejects, and must not report that as broken bindings
+#[rudeboy::test_bindings]
+#[rudeboy::methods]
+impl Counter {
+    fn bump(&mut self, by: i64) -> i64 {
+        self.count += by;
+        self.count
+    }
+
+    fn clamp(&self, #[rudeboy(range(min = 1, max = 5))] n: i64) -> i64 {
+        n.min(self.count)
+    }
+
+    fn fail(&self) -> rlua::Result<()> {
+        Err(rlua::Error::RuntimeError("failed".to_string()))
+    }
+}
+
+/// Runs the chunk with a counter holding 3 bound to `counter`, returning the
+/// result or the message of the error it raised
+fn eval<T: for<'lua> rlua::FromLua<'lua>>(chunk: &str) -> Result<T, String> {
+    Lua::new().context(|ctx| {
+        let counter = Counter { count: 3, label: "c".to_string() };
+        ctx.globals().set("counter", counter).unwrap();
+        ctx.load(chunk).eval::<T>().map_err(|e| match e {
+            rlua::Error::CallbackError { cause, .. } => cause.to_string(),
+            e => e.to_string(),
+        })
+    })
+}
+
+/// Runs the chunk as `eval` does, returning whether its error was raised as an
+/// error of the arguments of a method
+fn is_argument_error(chunk: &str) -> bool {
+    Lua::new().context(|ctx| {
+        ctx.globals().set("counter", Counter::default()).unwrap();
+        match ctx.load(chunk).exec() {
+            Err(rlua::Error::CallbackError { cause, .. }) => Counter::rudeboy_is_argument_error(&cause),
+            result => panic!("expected a callback error, got {:?}", result),
+        }
+    })
+}
+
+#[test]
+fn index_and_new_index() {
+    assert_eq!(eval::<i64>("return counter.count"), Ok(3));
+    assert_eq!(eval::<String>("counter.label = 'd' return counter.label"), Ok("d".to_string()));
+}
+
+#[test]
+fn methods() {
+    assert_eq!(eval::<i64>("return counter:bump(2)"), Ok(5));
+    assert_eq!(eval::<i64>("counter:bump(2) return counter.count"), Ok(5));
+    assert_eq!(eval::<i64>("return counter:clamp(4)"), Ok(3));
+    assert!(eval::<Option<i64>>("return counter:fail()").unwrap_err().contains("failed"));
+}
+
+#[test]
+fn metamethods() {
+    assert_eq!(eval::<bool>("return counter == counter"), Ok(true));
+    assert_eq!(eval::<bool>("return counter == 3"), Ok(false));
+}
+
+#[test]
+fn argument_errors() {
+    assert!(is_argument_error("counter:bump()"));
+    assert!(is_argument_error("counter:bump(1, 2)"));
+    assert!(is_argument_error("counter:bump({})"));
+}
+
+#[test]
+fn parameter_checks_are_method_errors() {
+    let message = eval::<i64>("return counter:clamp(9)").unwrap_err();
+    assert!(message.contains("argument #1"), "{}", message);
+    assert!(!is_argument_error("counter:clamp(9)"));
+}
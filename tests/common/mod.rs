@@ -0,0 +1,48 @@
+//! The traits of the rudeboy crate which generated code implements, standing in
+//! for rudeboy itself so that the bindings can be tested against rlua alone
+
+#![allow(dead_code)]
+
+pub use rudeboy_derive::*;
+use rlua::{UserData, UserDataMethods};
+
+pub trait RudeboyMethods: Sized + UserData {
+    fn generate_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M);
+}
+
+pub trait RudeboyMetaMethods: Sized + UserData {
+    fn generate_metamethods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        Self::generate_add(methods);
+        Self::generate_eq(methods);
+        Self::generate_index(methods);
+        Self::generate_sub(methods);
+        Self::generate_mul(methods);
+        Self::generate_div(methods);
+        Self::generate_mod(methods);
+        Self::generate_unm(methods);
+        Self::generate_band(methods);
+        Self::generate_bor(methods);
+        Self::generate_bxor(methods);
+        Self::generate_bnot(methods);
+        Self::generate_shl(methods);
+        Self::generate_shr(methods);
+        Self::generate_lt(methods);
+        Self::generate_le(methods);
+    }
+    fn generate_add<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_eq<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_index<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_sub<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_mul<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_div<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_mod<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_unm<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_band<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_bor<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_bxor<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_bnot<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_shl<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_shr<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_lt<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+    fn generate_le<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+}
@@ -0,0 +1,11 @@
+pub struct Counter(i64);
+
+#[rudeboy_derive::methods]
+impl Counter {
+    #[rudeboy(deprecated = "use get instead")]
+    fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+pub struct Counter(i64);
+
+#[rudeboy_derive::methods]
+impl Counter {
+    fn clamp(&self, #[rudeboy(range = 5)] n: i64) -> i64 {
+        n.min(self.0)
+    }
+}
+
+fn main() {}
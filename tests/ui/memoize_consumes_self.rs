@@ -0,0 +1,12 @@
+#[derive(Clone)]
+pub struct Builder(i64);
+
+#[rudeboy_derive::methods(builder)]
+impl Builder {
+    #[rudeboy(memoize)]
+    fn with(self, n: i64) -> Self {
+        Builder(self.0 + n)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[derive(Default)]
+pub struct Counter(i64);
+
+#[rudeboy_derive::methods]
+#[rudeboy_derive::test_bindings]
+impl Counter {
+    fn clamp(&self, #[rudeboy(range(min = 1, max = 5))] n: i64) -> i64 {
+        n.min(self.0)
+    }
+}
+
+fn main() {}